@@ -10,6 +10,67 @@ pub enum SerializationError {
 	BufferOverflow,
 	/// Encountered an unexpected (uninterpretable) value during serialization.
 	UnexpectedValue,
+	/// Serialization of a field of a compound object would cause a buffer-overflow.
+	FieldBufferOverflow {
+		/// Name of the field, if known.
+		field: Option<&'static str>,
+		/// Byte offset of the field from the start of the serialized object.
+		offset: usize,
+	},
+	/// Encountered an unexpected (uninterpretable) value in a field of a compound object.
+	FieldUnexpectedValue {
+		/// Name of the field, if known.
+		field: Option<&'static str>,
+		/// Byte offset of the field from the start of the serialized object.
+		offset: usize,
+	},
+	/// Encountered a schema version newer than the one known to the reader, see
+	/// [`Versioned`](Versioned).
+	UnsupportedVersion(u16),
+}
+
+impl SerializationError {
+	/// Attach the context of a field at provided byte offset to the error.
+	///
+	/// Implementations of [`ByteSerialize`](ByteSerialize) for compound objects should use this
+	/// to report which of their fields failed. If the error already carries field context the
+	/// offsets are accumulated, while the name of the innermost known field is kept.
+	pub fn in_field(self, field: Option<&'static str>, offset: usize) -> Self {
+		match self {
+			Self::BufferOverflow => Self::FieldBufferOverflow { field, offset },
+			Self::UnexpectedValue => Self::FieldUnexpectedValue { field, offset },
+			Self::FieldBufferOverflow { field: inner, offset: inner_offset } => Self::FieldBufferOverflow {
+				field: inner.or(field),
+				offset: offset + inner_offset,
+			},
+			Self::FieldUnexpectedValue { field: inner, offset: inner_offset } => Self::FieldUnexpectedValue {
+				field: inner.or(field),
+				offset: offset + inner_offset,
+			},
+			Self::UnsupportedVersion(_) => self,
+		}
+	}
+
+	/// Get the name of the field that failed to serialize, if known.
+	pub fn field(&self) -> Option<&'static str> {
+		match self {
+			Self::FieldBufferOverflow { field, .. } | Self::FieldUnexpectedValue { field, .. } => *field,
+			_ => None,
+		}
+	}
+
+	/// Get the byte offset of the field that failed to serialize, if known.
+	pub fn offset(&self) -> Option<usize> {
+		match self {
+			Self::FieldBufferOverflow { offset, .. } | Self::FieldUnexpectedValue { offset, .. } => Some(*offset),
+			_ => None,
+		}
+	}
+
+	/// Check whether the error was caused by a buffer-overflow.
+	pub fn is_buffer_overflow(&self) -> bool {
+		matches!(self, Self::BufferOverflow | Self::FieldBufferOverflow { .. })
+	}
 }
 
 // TODO: custom #[derive(ByteSerialize)]
@@ -17,17 +78,17 @@ pub enum SerializationError {
 ///
 /// Correct implementations of this trait fulfil following predicates:
 /// - A call to [`to_bytes`](Self::to_bytes) must write no more than
-/// [`byte_count`](Self::byte_count) bytes.
+///   [`byte_count`](Self::byte_count) bytes.
 /// - The byte-stream produced by a call to [`to_bytes`](Self::to_bytes) should produce a valid
-/// object on call of [`from_bytes`](Self::from_bytes).
+///   object on call of [`from_bytes`](Self::from_bytes).
 ///
 /// `ByteSerialize` is implemented by default for:
 /// - Empty type. (`()`)
 /// - Trivial types. (ex: `u8`, `usize`, `float`).
 /// - Arrays of `ByteSerialize + Default` objects up to size 32.
-/// (ex: `[f32; 3]`, `[[f32; 4]; 4]`, `[u8; 4]`).
+///   (ex: `[f32; 3]`, `[[f32; 4]; 4]`, `[u8; 4]`).
 /// - Tuples of `ByteSerialize` objects.
-/// (ex: `(f32, f64, u16)`, `([u16; 4], u16)`, `((i32, isize), usize)`).
+///   (ex: `(f32, f64, u16)`, `([u16; 4], u16)`, `((i32, isize), usize)`).
 pub trait ByteSerialize: Sized {
 	/// Size of the serialization of the object in bytes.
 	fn byte_count(&self) -> usize;
@@ -47,10 +108,25 @@ pub trait ByteSerialize: Sized {
 }
 
 mod standard;
+mod versioned;
+
+pub use versioned::{Versioned, VersionedSerialize};
 
 impl Display for SerializationError {
 	fn fmt(&self, f: &mut Formatter<'_>) -> FmtResult {
-		write!(f, "serialization would cause buffer overflow")
+		if let Self::UnsupportedVersion(version) = self {
+			return write!(f, "encountered unsupported schema version {} during serialization", version)
+		}
+		if self.is_buffer_overflow() {
+			write!(f, "serialization would cause buffer overflow")?;
+		} else {
+			write!(f, "encountered an unexpected value during serialization")?;
+		}
+		match (self.field(), self.offset()) {
+			(Some(field), Some(offset)) => write!(f, " in field `{}` at byte offset {}", field, offset),
+			(None, Some(offset)) => write!(f, " at byte offset {}", offset),
+			_ => Ok(()),
+		}
 	}
 }
 
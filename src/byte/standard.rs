@@ -103,7 +103,8 @@ macro_rules! impl_byte_serialize_generic_array {
 				let mut result = Self::default();
 				let mut processed_byte_count = 0;
 				for i in 0..$count {
-					let (item, item_bytes) = T::from_bytes(&bytes[processed_byte_count..])?;
+					let (item, item_bytes) = T::from_bytes(&bytes[processed_byte_count..])
+						.map_err(|error| error.in_field(None, processed_byte_count))?;
 					result[i] = item;
 					processed_byte_count += item_bytes;
 				};
@@ -153,7 +154,8 @@ macro_rules! impl_byte_serialize_tuple {
 			fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), SerializationError> {
 				let mut total_processed_bytes = 0;
 				$(
-					let ($element, processed_bytes) = $name::from_bytes(&bytes[total_processed_bytes..])?;
+					let ($element, processed_bytes) = $name::from_bytes(&bytes[total_processed_bytes..])
+						.map_err(|error| error.in_field(None, total_processed_bytes))?;
 					total_processed_bytes += processed_bytes;
 				)+
 				Ok((($($element,)+), total_processed_bytes))
@@ -172,7 +174,7 @@ impl_byte_serialize_tuple! { (T11, e11, 11), (T10, e10, 10), (T9, e9, 9), (T8, e
 
 #[cfg(test)]
 mod test {
-	use super::ByteSerialize;
+	use super::{ByteSerialize, SerializationError};
 
 	#[test]
 	fn u32_serializes() {
@@ -294,4 +296,32 @@ mod test {
 		assert_eq!(byte_count, EXPECTED_BYTE_COUNT);
 		assert_eq!(original, deserialized);
 	}
+
+	#[test]
+	fn tuple_error_reports_field_and_offset() {
+		let bytes = [0xFF; 6];
+
+		let error = <(u32, u32)>::from_bytes(&bytes).unwrap_err();
+
+		assert_eq!(error, SerializationError::FieldBufferOverflow { field: None, offset: 4 });
+		assert_eq!(error.to_string(), "serialization would cause buffer overflow at byte offset 4");
+	}
+
+	#[test]
+	fn named_field_error_is_displayed() {
+		let error = SerializationError::UnexpectedValue.in_field(Some("kind"), 0).in_field(Some("header"), 2);
+
+		assert_eq!(error.field(), Some("kind"));
+		assert_eq!(error.to_string(), "encountered an unexpected value during serialization in field `kind` at byte offset 2");
+	}
+
+	#[test]
+	fn nested_error_accumulates_offset() {
+		let bytes = [0xFF; 8];
+
+		let error = <(u8, [u32; 2])>::from_bytes(&bytes).unwrap_err();
+
+		assert_eq!(error.offset(), Some(5));
+		assert!(error.is_buffer_overflow());
+	}
 }
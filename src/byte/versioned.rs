@@ -0,0 +1,153 @@
+//! Schema versioning of byte-serialized objects.
+
+use super::{ByteSerialize, SerializationError};
+
+use std::mem::size_of;
+
+/// A trait for objects whose serialization evolves over versions of their schema.
+///
+/// The [`ByteSerialize`](ByteSerialize) implementation of the object always serializes the
+/// current [`VERSION`](Self::VERSION) of the schema, while byte-streams of older versions are
+/// read with [`from_older_bytes`](Self::from_older_bytes). Wrap the object in
+/// [`Versioned`](Versioned) to prefix its serialization with the schema version.
+pub trait VersionedSerialize: ByteSerialize {
+	/// Current version of the schema.
+	const VERSION: u16;
+
+	/// Construct Self from a byte-stream of an older version of the schema.
+	///
+	/// The version is guaranteed to be lower than [`VERSION`](Self::VERSION). Fields missing from
+	/// older versions should be filled in with defaults.
+	///
+	/// Should produce a constructed instance of [`Self`](Self) and the number of bytes read.
+	fn from_older_bytes(version: u16, bytes: &[u8]) -> Result<(Self, usize), SerializationError>;
+}
+
+/// A [`VersionedSerialize`](VersionedSerialize) object, serialized with its schema version.
+///
+/// The serialization is prefixed with the version as `u16`. Deserialization of a version newer
+/// than [`VERSION`](VersionedSerialize::VERSION) fails with
+/// [`UnsupportedVersion`](SerializationError::UnsupportedVersion).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Versioned<T: VersionedSerialize>(pub T);
+
+impl<T: VersionedSerialize> ByteSerialize for Versioned<T> {
+	#[inline]
+	fn byte_count(&self) -> usize {
+		size_of::<u16>() + self.0.byte_count()
+	}
+
+	fn to_bytes(&self, bytes: &mut [u8]) {
+		T::VERSION.to_bytes(bytes);
+		self.0.to_bytes(&mut bytes[size_of::<u16>() ..]);
+	}
+
+	fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), SerializationError> {
+		let (version, offset) = u16::from_bytes(bytes)?;
+		let (value, byte_count) = if version == T::VERSION {
+			T::from_bytes(&bytes[offset ..])
+		} else if version < T::VERSION {
+			T::from_older_bytes(version, &bytes[offset ..])
+		} else {
+			return Err(SerializationError::UnsupportedVersion(version))
+		}.map_err(|error| error.in_field(None, offset))?;
+		Ok((Self(value), offset + byte_count))
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	/// First version of a schema.
+	#[derive(Debug, PartialEq)]
+	struct PlayerV1 {
+		health: u32,
+	}
+
+	impl ByteSerialize for PlayerV1 {
+		fn byte_count(&self) -> usize {
+			self.health.byte_count()
+		}
+
+		fn to_bytes(&self, bytes: &mut [u8]) {
+			self.health.to_bytes(bytes)
+		}
+
+		fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), SerializationError> {
+			u32::from_bytes(bytes).map(|(health, byte_count)| (Self { health }, byte_count))
+		}
+	}
+
+	impl VersionedSerialize for PlayerV1 {
+		const VERSION: u16 = 1;
+
+		fn from_older_bytes(_: u16, _: &[u8]) -> Result<(Self, usize), SerializationError> {
+			Err(SerializationError::UnexpectedValue)
+		}
+	}
+
+	/// Second version of the same schema, with an added field.
+	#[derive(Debug, PartialEq)]
+	struct PlayerV2 {
+		health: u32,
+		armor: u16,
+	}
+
+	impl ByteSerialize for PlayerV2 {
+		fn byte_count(&self) -> usize {
+			(self.health, self.armor).byte_count()
+		}
+
+		fn to_bytes(&self, bytes: &mut [u8]) {
+			(self.health, self.armor).to_bytes(bytes)
+		}
+
+		fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), SerializationError> {
+			<(u32, u16)>::from_bytes(bytes).map(|((health, armor), byte_count)| (Self { health, armor }, byte_count))
+		}
+	}
+
+	impl VersionedSerialize for PlayerV2 {
+		const VERSION: u16 = 2;
+
+		fn from_older_bytes(_: u16, bytes: &[u8]) -> Result<(Self, usize), SerializationError> {
+			PlayerV1::from_bytes(bytes).map(|(PlayerV1 { health }, byte_count)| (Self { health, armor: 0 }, byte_count))
+		}
+	}
+
+	#[test]
+	fn versioned_serializes() {
+		let original = Versioned(PlayerV2 { health: 100, armor: 25 });
+		let mut bytes = [0; 8];
+
+		original.to_bytes(&mut bytes);
+		let (deserialized, byte_count) = Versioned::<PlayerV2>::from_bytes(&bytes).unwrap();
+
+		assert_eq!(byte_count, original.byte_count());
+		assert_eq!(byte_count, 8);
+		assert_eq!(deserialized, original);
+	}
+
+	#[test]
+	fn older_version_is_read_with_defaults() {
+		let mut bytes = [0; 6];
+		Versioned(PlayerV1 { health: 70 }).to_bytes(&mut bytes);
+
+		let (deserialized, byte_count) = Versioned::<PlayerV2>::from_bytes(&bytes).unwrap();
+
+		assert_eq!(byte_count, 6);
+		assert_eq!(deserialized, Versioned(PlayerV2 { health: 70, armor: 0 }));
+	}
+
+	#[test]
+	fn future_version_is_rejected() {
+		let mut bytes = [0; 8];
+		Versioned(PlayerV2 { health: 70, armor: 5 }).to_bytes(&mut bytes);
+
+		let error = Versioned::<PlayerV1>::from_bytes(&bytes).unwrap_err();
+
+		assert_eq!(error, SerializationError::UnsupportedVersion(2));
+		assert_eq!(error.to_string(), "encountered unsupported schema version 2 during serialization");
+	}
+}
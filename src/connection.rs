@@ -1,13 +1,43 @@
 //! High level [`Connection`](connection::Connection) functionality.
 
+pub mod clock;
+pub mod compress;
+pub mod pool;
+pub mod stats;
 pub mod id;
 pub mod packet;
 pub mod error;
 pub mod context;
-// pub mod listen;
+pub mod client;
+pub mod peer;
+pub mod listen;
+pub mod router;
 
 /// Possible message that is passed by connections.
-pub trait Parcel: super::byte::ByteSerialize {}
+///
+/// Every parcel is transmitted within a single packet, so its serialization must not exceed
+/// [`MAX_BYTE_COUNT`](Self::MAX_BYTE_COUNT) bytes.
+pub trait Parcel: super::byte::ByteSerialize {
+	/// Maximum number of bytes a serialized parcel of this type may take.
+	///
+	/// Must not exceed [`MAX_SEGMENT_BYTE_COUNT`](packet::MAX_SEGMENT_BYTE_COUNT), which is
+	/// verified at compile time. Buffers provided to build packets must be able to hold a
+	/// parcel of this size in addition to the packet header. Includes the
+	/// [type tag](Self::TYPE_ID), if any.
+	const MAX_BYTE_COUNT: usize = 1024;
+
+	/// Stable tag identifying the type of the parcel on the wire.
+	///
+	/// If set, the tag is written in front of every serialized parcel and checked when parcels are
+	/// received, so a payload is never deserialized as a parcel of a different type. Receiving a
+	/// parcel with a different tag fails with
+	/// [`UnexpectedParcelType`](error::ProcessPacketError::UnexpectedParcelType). Defaults to no
+	/// tag.
+	const TYPE_ID: Option<u16> = None;
+}
 
 #[cfg(test)]
 impl Parcel for () {}
+
+#[cfg(test)]
+impl Parcel for u32 {}
@@ -0,0 +1,309 @@
+//! Client-side connections.
+//!
+//! A [`ClientConnection`](ClientConnection) owns a [transmitter](Transmit) and the
+//! [context](Context) of a single connection to a remote
+//! [listener](super::listen::ConnectionListener). Received datagrams from any other address are
+//! dropped.
+
+use crate::endpoint::{Transmit, TransmitError};
+
+use super::context::{Context, ConnectionStatus};
+use super::error::{ConnectError, FlushError};
+use super::packet::PacketHeader;
+use super::Parcel;
+
+use std::mem::size_of;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+
+/// A connection initiated by this end.
+///
+/// The connection starts [pending](ConnectionStatus::Pending) and is opened once the accept
+/// packet of the remote end is [received](Self::receive).
+///
+/// # Note
+/// The transmitter should be non-blocking, as [`receive`](Self::receive) reads datagrams until
+/// there are no more pending ones.
+pub struct ClientConnection<T: Transmit, P: Parcel> {
+	transmitter: T,
+	remote: SocketAddr,
+	context: Context<P>,
+	/// Payload of the connection request, kept for re-sending it.
+	payload: Vec<u8>,
+	buffer: Vec<u8>,
+}
+
+impl<T: Transmit, P: Parcel> ClientConnection<T, P> {
+	/// Request a connection from provided remote address, using provided transmitter.
+	///
+	/// The payload is delivered to the remote end along with the request, for it to decide whether
+	/// to accept the connection.
+	///
+	/// Returns [`AddressFamilyMismatch`](ConnectError::AddressFamilyMismatch) if the remote address
+	/// is IPv4 while the transmitter is bound to an IPv6 address, or vice versa.
+	pub fn connect(transmitter: T, remote: SocketAddr, payload: &[u8]) -> Result<Self, ConnectError> {
+		if let Some(local) = transmitter.local_addr() {
+			if local.is_ipv4() != remote.is_ipv4() {
+				return Err(ConnectError::AddressFamilyMismatch)
+			}
+		}
+		let max_datagram_length = transmitter.max_datagram_length();
+		if size_of::<PacketHeader>() + payload.len() > max_datagram_length {
+			return Err(ConnectError::PayloadTooLarge)
+		}
+
+		let mut connection = Self {
+			transmitter,
+			remote,
+			context: Context::pending(),
+			payload: payload.to_vec(),
+			buffer: vec![0; max_datagram_length],
+		};
+		connection.send_request()?;
+		Ok(connection)
+	}
+
+	/// Send the connection request again, in case it or its answer was lost.
+	///
+	/// The connection must be [pending](ConnectionStatus::Pending), see
+	/// [`Context::next_update_deadline`](Context::next_update_deadline) for when the request is
+	/// due to be re-sent.
+	pub fn send_request(&mut self) -> Result<(), ConnectError> {
+		let len = self.context.build_request_packet(&mut self.buffer, &self.payload)?;
+		self.transmitter.send_to(&self.buffer[.. len], self.remote)?;
+		Ok(())
+	}
+
+	/// Get the transmitter used by the connection.
+	#[inline]
+	pub fn transmitter(&self) -> &T {
+		&self.transmitter
+	}
+
+	/// Get the address of the remote end of the connection.
+	#[inline]
+	pub fn remote_addr(&self) -> SocketAddr {
+		self.remote
+	}
+
+	/// Get the context of the connection.
+	#[inline]
+	pub fn context(&self) -> &Context<P> {
+		&self.context
+	}
+
+	/// Get the mutable context of the connection.
+	#[inline]
+	pub fn context_mut(&mut self) -> &mut Context<P> {
+		&mut self.context
+	}
+
+	/// Close the connection, informing the other end, see [`Context::close`](Context::close).
+	pub fn close(&mut self) -> Result<(), FlushError> {
+		let len = self.context.close(&mut self.buffer)?;
+		self.transmitter.send_to(&self.buffer[.. len], self.remote)?;
+		Ok(())
+	}
+
+	/// Render a human-readable snapshot of the connection, see
+	/// [`Context::debug_dump`](Context::debug_dump).
+	pub fn debug_dump(&self) -> String {
+		format!("remote: {}\n{}", self.remote, self.context.debug_dump())
+	}
+
+	/// Receive all pending datagrams, processing those from the remote end.
+	///
+	/// # Returns
+	/// Number of received datagrams.
+	pub fn receive(&mut self) -> Result<usize, TransmitError> {
+		let mut datagram_count = 0;
+		loop {
+			let (len, src, received_at) = match self.transmitter.try_recv_from_timestamped(&mut self.buffer) {
+				Ok(result) => result,
+				Err(TransmitError::NoPendingPackets) => return Ok(datagram_count),
+				Err(TransmitError::MalformedPacket) => continue,
+				Err(error) => return Err(error),
+			};
+			datagram_count += 1;
+
+			// Packets the connection fails to process are simply dropped.
+			if src == self.remote {
+				let _ = self.context.process_packet_received_at(&self.buffer[.. len], received_at);
+			}
+		}
+	}
+
+	/// Build and send all packets that are due, if the connection is
+	/// [open](ConnectionStatus::Open).
+	///
+	/// # Returns
+	/// Number of sent datagrams.
+	pub fn flush(&mut self) -> Result<usize, FlushError> {
+		let mut sent_count = 0;
+		if self.context.status() != ConnectionStatus::Open {
+			return Ok(sent_count)
+		}
+		loop {
+			let len = self.context.build_packet(&mut self.buffer)?;
+			if len == 0 {
+				return Ok(sent_count)
+			}
+			self.transmitter.send_to(&self.buffer[.. len], self.remote)?;
+			sent_count += 1;
+		}
+	}
+
+	/// Send a control packet acknowledging received packets right away, if any are yet to be
+	/// acknowledged and the connection is [open](ConnectionStatus::Open).
+	///
+	/// Useful before going idle after receiving, so that the other end does not re-transmit
+	/// packets it has no acknowledgement for. Unnecessary when [flushing](Self::flush) regularly
+	/// with the default [acknowledgement delay](Context::set_ack_delay), or when data is sent
+	/// anyway, as every packet carries the acknowledgements.
+	///
+	/// # Returns
+	/// Whether a packet was sent.
+	pub fn flush_acks(&mut self) -> Result<bool, FlushError> {
+		if self.context.status() != ConnectionStatus::Open || self.context.pending_ack_count() == 0 {
+			return Ok(false)
+		}
+		let len = self.context.build_ack_packet(&mut self.buffer)?;
+		self.transmitter.send_to(&self.buffer[.. len], self.remote)?;
+		Ok(true)
+	}
+}
+
+impl<P: Parcel> ClientConnection<UdpSocket, P> {
+	/// Request a connection from provided remote address, using a new non-blocking socket.
+	///
+	/// The socket is bound to an ephemeral port on the unspecified address of the same family as
+	/// the remote address (`0.0.0.0:0` or `[::]:0`).
+	pub fn connect_new(remote: SocketAddr, payload: &[u8]) -> Result<Self, ConnectError> {
+		Self::connect_from_port(remote, 0, payload)
+	}
+
+	/// Request a connection from provided remote address, using a new non-blocking socket bound
+	/// to provided local port.
+	///
+	/// Same as [`connect_new`](Self::connect_new), but with a predictable source port, for
+	/// example when a rendezvous server tells the other end which port to expect. Port `0` binds
+	/// an ephemeral port.
+	///
+	/// # Errors
+	/// Binding a fixed port fails with an [`Io`](ConnectError::Io) error of
+	/// [`AddrInUse`](std::io::ErrorKind::AddrInUse) kind if another socket, possibly of another
+	/// process, is already bound to it.
+	pub fn connect_from_port(remote: SocketAddr, local_port: u16, payload: &[u8]) -> Result<Self, ConnectError> {
+		let local = if remote.is_ipv4() {
+			SocketAddr::from((Ipv4Addr::UNSPECIFIED, local_port))
+		} else {
+			SocketAddr::from((Ipv6Addr::UNSPECIFIED, local_port))
+		};
+		let socket = UdpSocket::bind(local)?;
+		socket.set_nonblocking(true)?;
+		Self::connect(socket, remote, payload)
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use super::super::listen::{AcceptDecision, ConnectionListener};
+
+	use std::time::Duration;
+
+	/// Bind a non-blocking socket on provided address.
+	fn bind(addr: SocketAddr) -> UdpSocket {
+		let socket = UdpSocket::bind(addr).unwrap();
+		socket.set_nonblocking(true).unwrap();
+		socket
+	}
+
+	#[test]
+	fn client_connects_to_listener() {
+		let server_addr = SocketAddr::from(([ 127, 0, 0, 1, ], 10027));
+		let client_addr = SocketAddr::from(([ 127, 0, 0, 1, ], 10028));
+		let mut listener = ConnectionListener::<_, u32>::new(bind(server_addr));
+		let mut client = ClientConnection::<_, u32>::connect(bind(client_addr), server_addr, b"hello").unwrap();
+		assert_eq!(client.context().status(), ConnectionStatus::Pending);
+
+		while listener.receive().unwrap() == 0 {}
+		let connection_id = listener.try_accept(|addr, payload| {
+			assert_eq!((addr, payload), (client_addr, &b"hello"[..]));
+			AcceptDecision::Allow
+		}).unwrap();
+		while client.receive().unwrap() == 0 {}
+		assert_eq!(client.context().status(), ConnectionStatus::Open);
+		assert_eq!(client.context().connection_id(), Some(connection_id));
+		assert_eq!(client.context().remote_max_datagram_length(), Some(listener.transmitters()[0].max_datagram_length()));
+
+		client.context_mut().push_reliable_parcel(42).unwrap();
+		assert_eq!(client.flush().unwrap(), 1);
+		while listener.receive().unwrap() == 0 {}
+		assert_eq!(listener.connection_mut(connection_id).unwrap().pop_parcel(), Ok((42, [0; 4])));
+	}
+
+	#[test]
+	fn flush_acks_acknowledges_received_packets() {
+		let server_addr = SocketAddr::from(([ 127, 0, 0, 1, ], 10073));
+		let client_addr = SocketAddr::from(([ 127, 0, 0, 1, ], 10074));
+		let mut listener = ConnectionListener::<_, u32>::new(bind(server_addr));
+		let mut client = ClientConnection::<_, u32>::connect(bind(client_addr), server_addr, &[]).unwrap();
+		while listener.receive().unwrap() == 0 {}
+		let connection_id = listener.try_accept(|_, _| AcceptDecision::Allow).unwrap();
+		while client.receive().unwrap() == 0 {}
+		assert!(!client.flush_acks().unwrap());
+
+		client.context_mut().set_ack_delay(4, Duration::from_secs(1));
+		listener.connection_mut(connection_id).unwrap().push_reliable_parcel(7).unwrap();
+		assert_eq!(listener.flush_all().unwrap(), 1);
+		while client.receive().unwrap() == 0 {}
+		assert_eq!(client.context_mut().pop_parcel(), Ok((7, [0; 4])));
+		assert_eq!(client.context().pending_ack_count(), 1);
+		// The acknowledgement is delayed, so a regular flush sends nothing.
+		assert_eq!(client.flush().unwrap(), 0);
+
+		assert!(client.flush_acks().unwrap());
+		assert_eq!(client.context().pending_ack_count(), 0);
+		assert!(!client.flush_acks().unwrap());
+		while listener.receive().unwrap() == 0 {}
+		assert_eq!(listener.connection(connection_id).unwrap().sequence_state().in_flight_count, 0);
+	}
+
+	#[test]
+	fn connect_new_binds_ephemeral_socket() {
+		let server_addr = SocketAddr::from(([ 127, 0, 0, 1, ], 10031));
+		let mut listener = ConnectionListener::<_, u32>::new(bind(server_addr));
+		let client = ClientConnection::<_, u32>::connect_new(server_addr, &[]).unwrap();
+		let local = client.transmitter().local_addr().unwrap();
+		assert!(local.is_ipv4());
+		assert_ne!(local.port(), 0);
+
+		while listener.receive().unwrap() == 0 {}
+		let mut source = None;
+		listener.try_accept(|addr, _| {
+			source = Some(addr);
+			AcceptDecision::Allow
+		}).unwrap();
+		assert_eq!(source.map(|addr| addr.port()), Some(local.port()));
+	}
+
+	#[test]
+	fn connect_from_port_binds_provided_port() {
+		let server_addr = SocketAddr::from(([ 127, 0, 0, 1, ], 10046));
+		let _server = bind(server_addr);
+		let client = ClientConnection::<_, u32>::connect_from_port(server_addr, 10047, &[]).unwrap();
+		assert_eq!(client.transmitter().local_addr().unwrap().port(), 10047);
+
+		let result = ClientConnection::<_, u32>::connect_from_port(server_addr, 10047, &[]);
+		assert!(matches!(result.err(), Some(ConnectError::Io(_))));
+	}
+
+	#[test]
+	fn connect_detects_address_family_mismatch() {
+		let client = bind(SocketAddr::from(([ 127, 0, 0, 1, ], 10029)));
+		let remote = SocketAddr::from(([ 0, 0, 0, 0, 0, 0, 0, 1, ], 10030));
+
+		let result = ClientConnection::<_, u32>::connect(client, remote, &[]);
+		assert_eq!(result.err(), Some(ConnectError::AddressFamilyMismatch));
+	}
+}
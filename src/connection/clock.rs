@@ -0,0 +1,106 @@
+//! Sources of time for connection timers.
+//!
+//! Every time-dependent decision of a [`Context`](super::context::Context) (keep-alives,
+//! re-transmissions and timeouts) reads the current time from a [`Clock`](Clock). By default the
+//! [`SystemClock`](SystemClock) is used, while a [`ManualClock`](ManualClock) allows advancing
+//! time explicitly, making timing behavior deterministic in tests.
+
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::{Duration, Instant};
+
+/// A trait for sources of the current time.
+pub trait Clock: Send {
+	/// Get the current moment in time.
+	fn now(&self) -> Instant;
+}
+
+/// A clock reading the monotonic system time, using [`Instant::now`](Instant::now).
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+	#[inline]
+	fn now(&self) -> Instant {
+		Instant::now()
+	}
+}
+
+/// A clock that only advances when explicitly told to.
+///
+/// Clones share the same time, so a clone may be given to a connection while the original is used
+/// to advance the time.
+///
+/// A panic in another thread using a clone does not render the clock unusable, as the shared time
+/// is always consistent.
+#[derive(Debug, Clone)]
+pub struct ManualClock {
+	now: Arc<Mutex<Instant>>,
+}
+
+impl ManualClock {
+	/// Construct a manual clock stopped at the current system time.
+	pub fn new() -> Self {
+		Self::starting_at(Instant::now())
+	}
+
+	/// Construct a manual clock stopped at provided moment.
+	pub fn starting_at(now: Instant) -> Self {
+		Self { now: Arc::new(Mutex::new(now)) }
+	}
+
+	/// Advance the time of the clock (and all its clones) by provided duration.
+	pub fn advance(&self, duration: Duration) {
+		*self.lock() += duration;
+	}
+
+	/// Lock the shared time, recovering it if another thread panicked while holding the lock.
+	#[inline]
+	fn lock(&self) -> MutexGuard<'_, Instant> {
+		self.now.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+}
+
+impl Default for ManualClock {
+	#[inline]
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl Clock for ManualClock {
+	#[inline]
+	fn now(&self) -> Instant {
+		*self.lock()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn manual_clock_clones_share_time() {
+		let clock = ManualClock::new();
+		let clone = clock.clone();
+		let start = clock.now();
+
+		clock.advance(Duration::from_millis(250));
+		assert_eq!(clone.now(), start + Duration::from_millis(250));
+		assert_eq!(clock.now(), clone.now());
+	}
+
+	#[test]
+	fn manual_clock_survives_poisoning() {
+		let clock = ManualClock::new();
+		let start = clock.now();
+		let clone = clock.clone();
+		std::thread::spawn(move || {
+			let _guard = clone.now.lock().unwrap();
+			panic!("poisoning the clock");
+		}).join().unwrap_err();
+
+		assert!(clock.now.is_poisoned());
+		clock.advance(Duration::from_secs(1));
+		assert_eq!(clock.now(), start + Duration::from_secs(1));
+	}
+}
@@ -0,0 +1,26 @@
+//! Optional compression of packet payloads.
+//!
+//! GNet does not mandate a particular compression algorithm. Instead users may provide their own
+//! [`Compressor`](Compressor) implementation (for example backed by LZ4 or zstd) to a
+//! [`Context`](super::context::Context). Both ends of a connection must use compatible
+//! compressors.
+//!
+//! Compression is applied to the whole data segment (parcels and stream) of a packet. A packet is
+//! only sent compressed if that makes it smaller, which is indicated by the
+//! [`Compressed`](super::packet::Signal::Compressed) signal of the packet header. Data segments
+//! shorter than the [minimum compressed length](super::context::Context::set_min_compressed_length)
+//! are not compressed at all.
+
+use crate::byte::SerializationError;
+
+/// A trait for objects that compress and decompress packet payloads.
+pub trait Compressor: Send {
+	/// Compress provided data, appending the result to `output`.
+	fn compress(&self, data: &[u8], output: &mut Vec<u8>);
+
+	/// Decompress provided data, appending the result to `output`.
+	///
+	/// Should return [`UnexpectedValue`](SerializationError::UnexpectedValue) if the data was not
+	/// produced by a matching [`compress`](Self::compress) call.
+	fn decompress(&self, data: &[u8], output: &mut Vec<u8>) -> Result<(), SerializationError>;
+}
@@ -1,10 +1,91 @@
 //! Connection context.
 
+use crate::byte::{ByteSerialize, SerializationError, VersionedSerialize};
+
 use super::Parcel;
+use super::clock::{Clock, SystemClock};
+use super::compress::Compressor;
+use super::pool::{BufferPool, HeapPool};
+use super::stats::{self, ConnectionStats, Counters, StatsHandle};
 use super::id::ConnectionId;
-use super::error::{BuildPacketError, ConnectionError};
+use super::error::{BuildPacketError, ConnectionError, ProcessPacketError};
+use super::packet::{self, DataPrelude, PacketHeader, PacketIndex, Signal, SignalBits, MAX_SEGMENT_BYTE_COUNT};
+
+use rand::random;
+
+use std::collections::VecDeque;
+use std::fmt::Write;
+use std::mem::{self, size_of};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Span of time without received packets after which the connection is deemed lost.
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Span of time without sent packets after which a keep-alive packet should be sent.
+///
+/// Until an open connection sends its first packet, the keep-alive is due earlier, by a random
+/// phase of up to half the interval, so that idle connections opened at once do not send their
+/// keep-alives in lockstep.
+pub const KEEP_ALIVE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Default span of time without exchanged application data after which the connection is idle.
+pub const IDLE_THRESHOLD: Duration = Duration::from_secs(5);
+
+/// Default span of time without an acknowledgement after which a synchronized packet is deemed
+/// lost, used until the round-trip time of the connection is measured.
+///
+/// Matches the initial retransmission timeout of TCP, see
+/// [RFC 6298](https://tools.ietf.org/html/rfc6298#section-2).
+pub const RETRANSMISSION_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Default lower bound of the [retransmission timeout](Context::current_rto).
+pub const MIN_RETRANSMISSION_TIMEOUT: Duration = Duration::from_millis(50);
+
+/// Default upper bound of the [retransmission timeout](Context::current_rto).
+pub const MAX_RETRANSMISSION_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Default span of time after which an unanswered connection request is first re-sent.
+pub const INITIAL_REQUEST_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Default upper bound of the span of time between re-sent connection requests.
+pub const MAX_REQUEST_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Default byte count of the smallest packet payload that is compressed.
+pub const MIN_COMPRESSED_LENGTH: usize = 64;
+
+/// Length of the datagrams built once a black hole is detected, the minimum IPv6 MTU less the
+/// IPv6 and UDP headers, expected to pass any path.
+pub const BLACK_HOLE_DATAGRAM_LENGTH: usize = 1232;
+
+/// Number of consecutive losses of packets longer than
+/// [`BLACK_HOLE_DATAGRAM_LENGTH`](BLACK_HOLE_DATAGRAM_LENGTH), while shorter ones are delivered,
+/// after which a black hole is detected.
+const BLACK_HOLE_LOSS_THRESHOLD: u32 = 3;
 
-use std::marker::PhantomData;
+/// Estimated number of packets queued along the path, below which
+/// [delay-based](CongestionControl::DelayBased) congestion control grows the window.
+const MIN_QUEUED_PACKETS: f64 = 2.0;
+
+/// Estimated number of packets queued along the path, above which
+/// [delay-based](CongestionControl::DelayBased) congestion control shrinks the window.
+const MAX_QUEUED_PACKETS: f64 = 4.0;
+
+/// Number of in-flight synchronized packets a connection with
+/// [congestion control](Context::set_congestion_control) starts with.
+pub const INITIAL_CONGESTION_WINDOW: usize = 4;
+
+/// Span of time worth of bytes the [send budget](Context::set_send_rate) may accumulate.
+pub const SEND_BUDGET_BURST: Duration = Duration::from_millis(100);
+
+/// Number of subsequent acknowledged packets after which an unacknowledged packet is deemed lost.
+const LOSS_PACKET_THRESHOLD: u8 = 8;
+
+/// Maximum number of synchronized packets that may be unacknowledged at once.
+///
+/// The acknowledgement of the latest packet and the mask of 64 preceding ones cover exactly this
+/// many packets.
+const MAX_IN_FLIGHT_PACKETS: u8 = 65;
 
 /// State of a connection.
 #[derive(Copy, Clone, Debug, PartialEq, Eq)]
@@ -23,46 +104,664 @@ pub enum ConnectionStatus {
 	/// may be caused by a sudden shutdown of the other end or due to network conditions.
 	Lost,
 
-	/// Connection has been explicitly closed by the other end.
+	/// Connection has been explicitly closed, by either end, see
+	/// [`Context::close_reason`](Context::close_reason).
 	///
 	/// Connection may only be dropped to free system resources.
 	Closed,
 }
 
+/// The reason a connection was deemed [`Lost`](ConnectionStatus::Lost).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum LossReason {
+	/// No packets were received within the [`TIMEOUT`](TIMEOUT) window.
+	TimedOut,
+	/// A new connection from the same address as the other end was accepted, implying it has
+	/// restarted and no longer knows about this connection (the connection was half-open).
+	RemoteRestarted,
+	/// The connection request was sent the [maximum](Context::set_max_request_count) number of
+	/// times without being answered.
+	TooManyRequests,
+}
+
+/// The reason a connection was [`Closed`](ConnectionStatus::Closed) or rejected.
+///
+/// Carried by close and reject packets as a single byte code, optionally followed by an
+/// application-defined [payload](Context::close_payload).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CloseReason {
+	/// No reason was given.
+	Unspecified,
+	/// The listener can not accept more connections.
+	ServerFull,
+	/// The ends of the connection use incompatible protocols.
+	ProtocolMismatch,
+	/// The requesting end failed to authenticate.
+	AuthFailed,
+	/// The connection was forcibly closed by the other end, for example by an administrator.
+	Kicked,
+	/// The other end is shutting down.
+	GracefulShutdown,
+	/// A code unknown to this version of the library.
+	Other(u8),
+}
+
+impl From<u8> for CloseReason {
+	fn from(code: u8) -> Self {
+		match code {
+			0 => Self::Unspecified,
+			1 => Self::ServerFull,
+			2 => Self::ProtocolMismatch,
+			3 => Self::AuthFailed,
+			4 => Self::Kicked,
+			5 => Self::GracefulShutdown,
+			code => Self::Other(code),
+		}
+	}
+}
+
+impl From<CloseReason> for u8 {
+	fn from(reason: CloseReason) -> Self {
+		match reason {
+			CloseReason::Unspecified => 0,
+			CloseReason::ServerFull => 1,
+			CloseReason::ProtocolMismatch => 2,
+			CloseReason::AuthFailed => 3,
+			CloseReason::Kicked => 4,
+			CloseReason::GracefulShutdown => 5,
+			CloseReason::Other(code) => code,
+		}
+	}
+}
+
+/// A class of traffic a connection carries, presetting its settings, see
+/// [`Context::set_qos_class`](Context::set_qos_class).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum QosClass {
+	/// Latency-sensitive traffic, such as game state updates.
+	///
+	/// Packets are neither paced nor delayed for coalescing acknowledgements, and lost packets
+	/// are re-transmitted eagerly, with the retransmission timeout capped at
+	/// [`RETRANSMISSION_TIMEOUT`](RETRANSMISSION_TIMEOUT).
+	Realtime,
+	/// Throughput-oriented traffic, such as asset downloads.
+	///
+	/// Packets are [paced](Context::set_pacing) and acknowledgements are coalesced, every 4
+	/// packets or 20 milliseconds.
+	Bulk,
+	/// Traffic that yields to everything else, such as telemetry.
+	///
+	/// Packets are paced, acknowledgements are coalesced every 8 packets or 40 milliseconds and
+	/// the retransmission timeout is at least 200 milliseconds.
+	Background,
+}
+
+/// An algorithm limiting the synchronized packets in flight, see
+/// [`Context::set_congestion_control`](Context::set_congestion_control).
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CongestionControl {
+	/// Losses are the only signal of congestion, the window grows until packets are lost.
+	LossBased,
+	/// Rising round-trip times signal congestion before packets are lost, as queues along the
+	/// path fill up, following [TCP Vegas](https://en.wikipedia.org/wiki/TCP_Vegas).
+	///
+	/// The number of packets queued along the path is estimated from the lowest and the latest
+	/// round-trip time samples as `window * (1 - lowest / latest)`. Past slow start the window
+	/// grows by a packet per round-trip while fewer than 2 packets are queued and shrinks by one
+	/// while more than 4 are. Slow start also ends once more than 4 packets are queued. Losses
+	/// halve the window as with [`LossBased`](Self::LossBased) control.
+	DelayBased,
+}
+
+/// An event of a connection, returned by [`Context::poll`](Context::poll).
+#[derive(Debug, PartialEq)]
+pub enum ConnectionEvent<P: Parcel> {
+	/// The status of the connection changed to the contained one.
+	StatusChanged(ConnectionStatus),
+	/// A sent synchronized packet with the contained id was acknowledged by the other end, so
+	/// all of its parcels and stream data were delivered.
+	PacketDelivered(PacketIndex),
+	/// A sent synchronized packet with the contained id was deemed lost and re-transmitted.
+	PacketLost(PacketIndex),
+	/// A parcel was received, along with the data prelude of its packet.
+	ParcelReceived(P, DataPrelude),
+	/// A [ping](Context::ping) was answered, after the contained round-trip time.
+	PingResult(Duration),
+	/// New stream data was received, the contained number of bytes is ready to be
+	/// [read](Context::read_from_stream).
+	StreamData(usize),
+}
+
+/// A snapshot of the raw sequencing counters of a connection.
+///
+/// Obtained with [`Context::sequence_state`](Context::sequence_state), useful for diagnosing
+/// why acknowledgements do not advance.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct SequenceState {
+	/// Id the next sent synchronized packet will have.
+	pub next_packet_id: PacketIndex,
+	/// Latest sent packet id acknowledged by the other end.
+	pub acknowledged_packet_id: PacketIndex,
+	/// Number of sent synchronized packets that are not acknowledged yet.
+	pub in_flight_count: usize,
+	/// Latest received synchronized packet id, sent as the acknowledgement.
+	pub received_packet_ack_id: PacketIndex,
+	/// Acknowledgement mask of the 64 packets preceding
+	/// [`received_packet_ack_id`](Self::received_packet_ack_id).
+	pub received_packet_ack_mask: u64,
+	/// Number of received synchronized packets that were dropped, as they were already received
+	/// or are too old to tell.
+	///
+	/// Duplicates are expected when acknowledgements are lost, a steadily growing count hints at
+	/// packets being duplicated on the path.
+	pub duplicate_packet_count: u64,
+}
+
+/// The minimal state of an open connection, from which it may be resumed, for example by
+/// another process taking over the connections of a failed server.
+///
+/// Obtained with [`Context::resumable_state`](Context::resumable_state) and restored with
+/// [`Context::resume`](Context::resume). Consists of the connection id, the sequencing counters
+/// and the maximum datagram length announced by the other end. The remote address of the
+/// connection is not part of the state, it is kept by the owner of the context.
+///
+/// Everything else is not restored:
+/// - Timers, the round-trip time estimate and the retransmission timeout reset as if the
+///   connection was just opened.
+/// - Sent packets that are not acknowledged yet are not re-transmitted. Their parcels are lost,
+///   and as their stream segments are never delivered, the incoming stream of the other end
+///   stalls. Resume connections with no stream data in flight.
+/// - Queued parcels and stream data, in either direction, are dropped.
+/// - Settings of the connection, such as the [compressor](Context::set_compressor) or
+///   [delayed acknowledgements](Context::set_ack_delay), should be applied again.
+///
+/// The state is serialized with its [schema version](VersionedSerialize), wrap it in
+/// [`Versioned`](crate::byte::Versioned) to store the version along with it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ResumableState {
+	/// Id of the connection.
+	pub connection_id: ConnectionId,
+	/// Id the next sent synchronized packet will have.
+	pub next_packet_id: PacketIndex,
+	/// Latest sent packet id acknowledged by the other end.
+	pub acknowledged_packet_id: PacketIndex,
+	/// Latest received synchronized packet id.
+	pub received_packet_ack_id: PacketIndex,
+	/// Acknowledgement mask of the 64 packets preceding
+	/// [`received_packet_ack_id`](Self::received_packet_ack_id).
+	pub received_packet_ack_mask: u64,
+	/// Id of the next synchronized packet whose stream segment continues the incoming stream.
+	pub next_stream_packet_id: PacketIndex,
+	/// Largest datagram the other end can receive, if it is known.
+	pub remote_max_datagram_length: Option<u16>,
+}
+
+impl ResumableState {
+	/// Fields of the state as a tuple, for serialization.
+	#[inline]
+	fn to_tuple(self) -> (ConnectionId, PacketIndex, PacketIndex, PacketIndex, u64, PacketIndex, u16) {
+		(
+			self.connection_id,
+			self.next_packet_id,
+			self.acknowledged_packet_id,
+			self.received_packet_ack_id,
+			self.received_packet_ack_mask,
+			self.next_stream_packet_id,
+			// Datagrams are never empty, so 0 marks an unknown length.
+			self.remote_max_datagram_length.unwrap_or(0),
+		)
+	}
+}
+
+impl ByteSerialize for ResumableState {
+	#[inline]
+	fn byte_count(&self) -> usize {
+		self.to_tuple().byte_count()
+	}
+
+	#[inline]
+	fn to_bytes(&self, bytes: &mut [u8]) {
+		self.to_tuple().to_bytes(bytes)
+	}
+
+	fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), SerializationError> {
+		let (fields, byte_count) = <(ConnectionId, PacketIndex, PacketIndex, PacketIndex, u64, PacketIndex, u16)>::from_bytes(bytes)?;
+		if fields.0 == 0 {
+			return Err(SerializationError::UnexpectedValue.in_field(Some("connection_id"), 0))
+		}
+		let state = Self {
+			connection_id: fields.0,
+			next_packet_id: fields.1,
+			acknowledged_packet_id: fields.2,
+			received_packet_ack_id: fields.3,
+			received_packet_ack_mask: fields.4,
+			next_stream_packet_id: fields.5,
+			remote_max_datagram_length: Some(fields.6).filter(|&length| length != 0),
+		};
+		Ok((state, byte_count))
+	}
+}
+
+impl VersionedSerialize for ResumableState {
+	const VERSION: u16 = 1;
+
+	fn from_older_bytes(_: u16, _: &[u8]) -> Result<(Self, usize), SerializationError> {
+		// There are no older versions.
+		Err(SerializationError::UnexpectedValue)
+	}
+}
+
+/// A synchronized packet that has not been acknowledged by the other end yet.
+struct SentPacket {
+	packet_id: PacketIndex,
+	sent_time: Instant,
+	retransmission_count: u32,
+	/// Whether the packet is re-transmitted regardless of being deemed lost.
+	is_resend_forced: bool,
+	/// Whether the packet carries a [ping](Context::ping).
+	is_ping: bool,
+	/// Number of reliable parcels the packet carries.
+	parcel_count: usize,
+	bytes: Vec<u8>,
+}
+
+/// A serialized parcel waiting to be written into a packet.
+struct OutgoingParcel {
+	bytes: Vec<u8>,
+	/// Number of parcels serialized into the bytes, more than one for a
+	/// [batch](Context::push_reliable_batch).
+	parcel_count: usize,
+	/// Prelude of the packet the parcel must be sent with.
+	prelude: DataPrelude,
+	/// Moment the parcel was queued.
+	queued_at: Instant,
+	/// Moment after which the parcel is dropped instead of sent, if any.
+	expires_at: Option<Instant>,
+}
+
+/// Round-trip time estimate, following [RFC 6298](https://tools.ietf.org/html/rfc6298).
+#[derive(Debug, Default, Clone, Copy)]
+struct RoundTripTime {
+	/// Smoothed round-trip time, `None` until the first sample.
+	smoothed: Option<Duration>,
+	/// Round-trip time variation.
+	variance: Duration,
+}
+
+impl RoundTripTime {
+	/// Update the estimate with a new round-trip time sample.
+	fn add_sample(&mut self, sample: Duration) {
+		match self.smoothed {
+			None => {
+				self.smoothed = Some(sample);
+				self.variance = sample / 2;
+			},
+			Some(smoothed) => {
+				let deviation = smoothed.abs_diff(sample);
+				self.variance = (self.variance * 3 + deviation) / 4;
+				self.smoothed = Some((smoothed * 7 + sample) / 8);
+			},
+		}
+	}
+
+	/// Get the retransmission timeout derived from the estimate, unbounded.
+	///
+	/// Returns `None` until the first sample.
+	fn retransmission_timeout(&self) -> Option<Duration> {
+		self.smoothed.map(|smoothed| smoothed + (self.variance * 4).max(Duration::from_millis(1)))
+	}
+}
+
 /// Connection context.
 ///
 /// Used for processing incoming and build outgoing datagrams.
 pub struct Context<P: Parcel> {
 	connection_id: ConnectionId,
 	status: ConnectionStatus,
-	buffer: Vec<u8>,
+	loss_reason: Option<LossReason>,
+	close_reason: Option<CloseReason>,
+	/// Payload accompanying the close reason.
+	close_payload: Vec<u8>,
+	/// Payload the listener attached to its accept packet.
+	accept_payload: Vec<u8>,
+	/// Latest status reported by [`poll`](Self::poll).
+	reported_status: ConnectionStatus,
+	handshake_id: DataPrelude,
+	/// Initial and maximum span of time between sent connection requests.
+	request_backoff: (Duration, Duration),
+	/// Number of connection requests sent so far.
+	request_count: u32,
+	/// Number of connection requests after which the connection is given up on, if limited.
+	max_request_count: Option<u32>,
+	/// Moment the connection request is due to be re-sent.
+	request_retry_time: Instant,
+	last_sent_packet_time: Instant,
+	/// Span of time the next keep-alive is sent early by, spreading keep-alives of connections.
+	keep_alive_phase: Duration,
+	last_received_packet_time: Instant,
+	/// Latest time parcels or stream data were either sent or received.
+	last_data_time: Instant,
+	idle_threshold: Duration,
+	clock: Box<dyn Clock>,
+	compressor: Option<Box<dyn Compressor>>,
+	/// Byte count of the smallest payload that is compressed.
+	min_compressed_length: usize,
+	buffer_pool: Arc<dyn BufferPool>,
+	/// Length every built packet is padded to.
+	padded_length: Option<usize>,
+	/// Largest datagram the other end can receive, as announced in its accept packet.
+	remote_max_datagram_length: Option<usize>,
+	/// Fixed length of the largest built datagram, regardless of provided buffers.
+	max_datagram_length: Option<usize>,
+	/// Length datagrams are limited to after a black hole was detected.
+	black_hole_length: Option<usize>,
+	/// Number of consecutive losses of long packets.
+	long_packet_loss_count: u32,
+	/// Whether a short packet was delivered since the latest long one.
+	is_short_packet_delivered: bool,
+
+	next_packet_id: PacketIndex,
+	/// Latest packet id acknowledged by the other end.
+	acknowledged_packet_id: PacketIndex,
+	/// Number of further parcels the other end is able to receive, as advertised by it.
+	remote_receive_window: u16,
+	sent_packets: VecDeque<SentPacket>,
+	round_trip_time: RoundTripTime,
+	/// Moment sent packet timestamps are relative to.
+	epoch: Instant,
+	/// Timestamp and receive moment of the latest received packet.
+	last_arrival: Option<(u16, Instant)>,
+	/// Smoothed inter-arrival jitter in seconds.
+	jitter: Option<f64>,
+	/// Smoothed span of time sent parcels were queued for.
+	queue_latency: Option<Duration>,
+	/// Byte count of the largest synchronized packet sent.
+	max_sent_packet_length: usize,
+	/// Smoothed ratio of lost to sent synchronized packets.
+	loss_rate: f64,
+	/// Retransmission timeout used until the round-trip time is measured.
+	initial_rto: Duration,
+	/// Lower and upper bounds of the retransmission timeout.
+	rto_bounds: (Duration, Duration),
+	is_pacing_enabled: bool,
+	/// Earliest moment the next data packet may be sent when pacing.
+	next_paced_time: Instant,
+	congestion_control: Option<CongestionControl>,
+	/// Number of synchronized packets allowed in flight with congestion control.
+	congestion_window: f64,
+	/// Congestion window up to which it grows exponentially.
+	slow_start_threshold: f64,
+	/// Moment the congestion window was last reduced, losses of packets sent before are ignored.
+	congestion_reduction_time: Option<Instant>,
+	/// Lowest and latest round-trip time samples, for delay-based congestion control.
+	round_trip_time_samples: Option<(Duration, Duration)>,
+	/// Rate in bytes per second the send budget refills at, if limited.
+	send_rate: Option<u64>,
+	/// Byte count data packets may still use, negative when overdrawn by the latest packet.
+	send_budget: f64,
+	/// Moment the send budget was last refilled.
+	send_budget_time: Instant,
+	/// Prelude of parcels pushed from now on.
+	outgoing_prelude: DataPrelude,
+	reliable_parcels: VecDeque<OutgoingParcel>,
+	volatile_parcels: VecDeque<OutgoingParcel>,
+	outgoing_stream: VecDeque<u8>,
 
-	_message_type: PhantomData<P>,
+	received_packet_ack_id: PacketIndex,
+	received_packet_ack_mask: u64,
+	/// Number of received synchronized packets that were not acknowledged yet.
+	pending_ack_count: u32,
+	/// Moment the earliest of the unacknowledged synchronized packets was received.
+	pending_ack_time: Instant,
+	/// Number of unacknowledged packets after which an acknowledgement is due immediately.
+	ack_frequency: u32,
+	/// Maximum span of time an acknowledgement may be delayed for.
+	max_ack_delay: Duration,
+	/// Received parcels, along with their preludes and the moments they were received.
+	received_parcels: VecDeque<(P, DataPrelude, Instant)>,
+	/// Number of received parcels after which further ones are not queued.
+	max_received_parcels: Option<usize>,
+	/// Number of parcels after which a built packet is not extended with further ones.
+	max_parcels_per_packet: Option<usize>,
+	/// Counters of the connection, shared with its stats handles.
+	counters: Arc<Counters>,
+	/// Whether data segments of volatile packets are raw datagrams rather than parcels.
+	is_raw_mode: bool,
+	received_raw_datagrams: VecDeque<Vec<u8>>,
+	incoming_stream: VecDeque<u8>,
+	/// Id of the next synchronized packet whose stream segment should be appended to the stream.
+	next_stream_packet_id: PacketIndex,
+	/// Stream segments of synchronized packets that were received out of order.
+	pending_stream_segments: Vec<(PacketIndex, Vec<u8>)>,
+	/// Number of buffered incoming stream bytes after which further segments are refused.
+	max_stream_buffer_length: Option<usize>,
+	/// Whether stream data was received since the last [`poll`](Self::poll).
+	is_stream_data_unreported: bool,
+	/// Ids of packets acknowledged since the last [`poll`](Self::poll).
+	delivered_packets: VecDeque<PacketIndex>,
+	/// Ids of packets deemed lost since the last [`poll`](Self::poll).
+	lost_packets: VecDeque<PacketIndex>,
+	/// Whether a [ping](Self::ping) is due to be sent.
+	is_ping_requested: bool,
+	/// Round-trip times of pings answered since the last [`poll`](Self::poll).
+	ping_results: VecDeque<Duration>,
 }
 
 impl<P: Parcel> Context<P> {
+	/// Evaluates to a compile-time error if parcels of type `P` may not fit a packet segment.
+	const PARCEL_FITS_SEGMENT: () = assert!(
+		P::MAX_BYTE_COUNT <= MAX_SEGMENT_BYTE_COUNT,
+		"Parcel::MAX_BYTE_COUNT exceeds the capacity of a packet segment",
+	);
+
+	/// Construct a context with provided id, status and clock.
+	fn new(connection_id: ConnectionId, status: ConnectionStatus, clock: Box<dyn Clock>) -> Self {
+		let () = Self::PARCEL_FITS_SEGMENT;
+		let now = clock.now();
+		// No packets have been exchanged yet, so the "latest" ones precede the first packet id.
+		let initial_packet_id = PacketIndex::from(u8::MAX);
+		Self {
+			connection_id,
+			status,
+			loss_reason: None,
+			close_reason: None,
+			close_payload: Vec::new(),
+			accept_payload: Vec::new(),
+			reported_status: status,
+			handshake_id: random::<u32>().to_ne_bytes(),
+			request_backoff: (INITIAL_REQUEST_INTERVAL, MAX_REQUEST_INTERVAL),
+			request_count: 0,
+			max_request_count: None,
+			request_retry_time: now,
+			last_sent_packet_time: now,
+			keep_alive_phase: KEEP_ALIVE_INTERVAL.mul_f64(random::<f64>() / 2.0),
+			last_received_packet_time: now,
+			last_data_time: now,
+			idle_threshold: IDLE_THRESHOLD,
+			clock,
+			compressor: None,
+			min_compressed_length: MIN_COMPRESSED_LENGTH,
+			buffer_pool: Arc::new(HeapPool),
+			padded_length: None,
+			remote_max_datagram_length: None,
+			max_datagram_length: None,
+			black_hole_length: None,
+			long_packet_loss_count: 0,
+			is_short_packet_delivered: false,
+
+			next_packet_id: Default::default(),
+			acknowledged_packet_id: initial_packet_id,
+			remote_receive_window: u16::MAX,
+			sent_packets: VecDeque::with_capacity(MAX_IN_FLIGHT_PACKETS as usize),
+			round_trip_time: Default::default(),
+			epoch: now,
+			last_arrival: None,
+			jitter: None,
+			queue_latency: None,
+			max_sent_packet_length: 0,
+			loss_rate: 0.0,
+			initial_rto: RETRANSMISSION_TIMEOUT,
+			rto_bounds: (MIN_RETRANSMISSION_TIMEOUT, MAX_RETRANSMISSION_TIMEOUT),
+			is_pacing_enabled: false,
+			next_paced_time: now,
+			congestion_control: None,
+			congestion_window: INITIAL_CONGESTION_WINDOW as f64,
+			slow_start_threshold: MAX_IN_FLIGHT_PACKETS as f64,
+			congestion_reduction_time: None,
+			round_trip_time_samples: None,
+			send_rate: None,
+			send_budget: 0.0,
+			send_budget_time: now,
+			outgoing_prelude: [0; 4],
+			reliable_parcels: VecDeque::new(),
+			volatile_parcels: VecDeque::new(),
+			outgoing_stream: VecDeque::new(),
+
+			received_packet_ack_id: initial_packet_id,
+			received_packet_ack_mask: 0,
+			pending_ack_count: 0,
+			pending_ack_time: now,
+			ack_frequency: 1,
+			max_ack_delay: Duration::ZERO,
+			received_parcels: VecDeque::new(),
+			max_received_parcels: None,
+			max_parcels_per_packet: None,
+			counters: Default::default(),
+			is_raw_mode: false,
+			received_raw_datagrams: VecDeque::new(),
+			incoming_stream: VecDeque::new(),
+			next_stream_packet_id: Default::default(),
+			pending_stream_segments: Vec::new(),
+			max_stream_buffer_length: None,
+			is_stream_data_unreported: false,
+			delivered_packets: VecDeque::new(),
+			lost_packets: VecDeque::new(),
+			is_ping_requested: false,
+			ping_results: VecDeque::new(),
+		}
+	}
+
 	/// Construct a pending connection context.
 	///
 	/// A pending connection is not yet established and as such can not be used to transmit data
 	/// between endpoints.
 	pub fn pending() -> Self {
-		Self {
-			connection_id: 0,
-			status: ConnectionStatus::Pending,
-			buffer: Vec::new(),
+		Self::pending_with_clock(Box::new(SystemClock))
+	}
 
-			_message_type: Default::default(),
-		}
+	/// Construct a pending connection context that reads time from provided clock.
+	pub fn pending_with_clock(clock: Box<dyn Clock>) -> Self {
+		Self::new(0, ConnectionStatus::Pending, clock)
 	}
 
 	/// Construct an accepted connection context with provided id.
+	///
+	/// Packet ids of both ends start at zero, matching another context constructed this way.
+	/// Connections established through a handshake start at random ids instead, picked from the
+	/// handshake id of their request.
 	pub fn accept(connection_id: ConnectionId) -> Self {
-		Self {
-			connection_id,
-			status: ConnectionStatus::Open,
-			buffer: Vec::new(),
-			
-			_message_type: Default::default(),
+		Self::accept_with_clock(connection_id, Box::new(SystemClock))
+	}
+
+	/// Construct an accepted connection context with provided id that reads time from provided
+	/// clock.
+	pub fn accept_with_clock(connection_id: ConnectionId, clock: Box<dyn Clock>) -> Self {
+		Self::new(connection_id, ConnectionStatus::Open, clock)
+	}
+
+	/// Construct a connection context with provided id, accepting the request with provided
+	/// handshake id.
+	pub(crate) fn accept_handshake(connection_id: ConnectionId, handshake_id: DataPrelude) -> Self {
+		let mut context = Self::accept(connection_id);
+		context.start_sequences(handshake_id, false);
+		context
+	}
+
+	/// Open a [pending](ConnectionStatus::Pending) connection by accepting the request of the
+	/// other end with provided handshake id instead, as done by [peer-to-peer](super::peer)
+	/// connections.
+	///
+	/// Queued parcels and settings of the connection are kept.
+	pub(crate) fn open_accepted(&mut self, connection_id: ConnectionId, handshake_id: DataPrelude) {
+		debug_assert_eq!(self.status, ConnectionStatus::Pending);
+		self.start_sequences(handshake_id, false);
+		self.open(connection_id);
+	}
+
+	/// Open a [pending](ConnectionStatus::Pending) connection with provided id.
+	fn open(&mut self, connection_id: ConnectionId) {
+		self.connection_id = connection_id;
+		self.status = ConnectionStatus::Open;
+		self.last_received_packet_time = self.clock.now();
+	}
+
+	/// Start the packet ids of both ends from the handshake id of the request establishing the
+	/// connection, as the requesting end or the accepting one.
+	///
+	/// The packets of the requesting end start at the first byte of the handshake id and those of
+	/// the accepting end at the second one. As the handshake id is random, so are the starting
+	/// ids, making packets of the connection harder to inject blindly, while both ends learn them
+	/// without any further exchange. Wrapping comparison of packet ids tolerates any start.
+	fn start_sequences(&mut self, handshake_id: DataPrelude, is_requester: bool) {
+		let (own_id, remote_id) = if is_requester {
+			(handshake_id[0], handshake_id[1])
+		} else {
+			(handshake_id[1], handshake_id[0])
+		};
+		self.next_packet_id = own_id.into();
+		self.acknowledged_packet_id = self.next_packet_id.previous();
+		let remote_id = PacketIndex::from(remote_id);
+		// No packets have been received yet, so the "latest" one precedes the first packet id.
+		self.received_packet_ack_id = remote_id.previous();
+		self.received_packet_ack_mask = 0;
+		self.next_stream_packet_id = remote_id;
+	}
+
+	/// Get the handshake id sent with connection requests of the connection.
+	#[inline]
+	pub(crate) fn handshake_id(&self) -> DataPrelude {
+		self.handshake_id
+	}
+
+	/// Pick a new random handshake id for following connection requests.
+	#[inline]
+	pub(crate) fn regenerate_handshake_id(&mut self) {
+		self.handshake_id = random::<u32>().to_ne_bytes();
+	}
+
+	/// Construct an open connection context continuing from provided resumable state.
+	///
+	/// See [`ResumableState`](ResumableState) for what is and is not restored.
+	pub fn resume(state: ResumableState) -> Self {
+		Self::resume_with_clock(state, Box::new(SystemClock))
+	}
+
+	/// Construct an open connection context continuing from provided resumable state, that reads
+	/// time from provided clock.
+	pub fn resume_with_clock(state: ResumableState, clock: Box<dyn Clock>) -> Self {
+		let mut context = Self::new(state.connection_id, ConnectionStatus::Open, clock);
+		context.next_packet_id = state.next_packet_id;
+		context.acknowledged_packet_id = state.acknowledged_packet_id;
+		context.received_packet_ack_id = state.received_packet_ack_id;
+		context.received_packet_ack_mask = state.received_packet_ack_mask;
+		context.next_stream_packet_id = state.next_stream_packet_id;
+		context.remote_max_datagram_length = state.remote_max_datagram_length.map(usize::from);
+		context
+	}
+
+	/// Get the state the connection may be [resumed](Self::resume) from.
+	///
+	/// The connection should be [open](ConnectionStatus::Open) and have no stream data in flight,
+	/// see [`ResumableState`](ResumableState).
+	pub fn resumable_state(&self) -> ResumableState {
+		ResumableState {
+			connection_id: self.connection_id,
+			next_packet_id: self.next_packet_id,
+			acknowledged_packet_id: self.acknowledged_packet_id,
+			received_packet_ack_id: self.received_packet_ack_id,
+			received_packet_ack_mask: self.received_packet_ack_mask,
+			next_stream_packet_id: self.next_stream_packet_id,
+			remote_max_datagram_length: self.remote_max_datagram_length.map(|length| length.min(u16::MAX as usize) as u16),
 		}
 	}
 
@@ -72,6 +771,51 @@ impl<P: Parcel> Context<P> {
 		self.status
 	}
 
+	/// Get the reason the connection was deemed [`Lost`](ConnectionStatus::Lost).
+	///
+	/// `None` unless the connection is lost.
+	#[inline]
+	pub fn loss_reason(&self) -> Option<LossReason> {
+		self.loss_reason
+	}
+
+	/// Get the reason the connection was [`Closed`](ConnectionStatus::Closed), by either end.
+	///
+	/// `None` unless the connection is closed. Close packets of the other end that carry no
+	/// reason are reported as [`Unspecified`](CloseReason::Unspecified).
+	#[inline]
+	pub fn close_reason(&self) -> Option<CloseReason> {
+		self.close_reason
+	}
+
+	/// Get the payload accompanying the [close reason](Self::close_reason), empty if there is
+	/// none.
+	#[inline]
+	pub fn close_payload(&self) -> &[u8] {
+		&self.close_payload
+	}
+
+	/// Get the payload the listener attached to its answer to the connection request, empty if
+	/// there is none, see
+	/// [`ConnectionListener::try_accept_with_payload`](super::listen::ConnectionListener::try_accept_with_payload).
+	///
+	/// Only known by the requesting end, once the accept packet is received. A connection opened
+	/// by a data packet, as the accept packet was lost, never learns the payload.
+	#[inline]
+	pub fn accept_payload(&self) -> &[u8] {
+		&self.accept_payload
+	}
+
+	/// Deem the connection [`Lost`](ConnectionStatus::Lost) for provided reason.
+	///
+	/// Has no effect on connections that are already lost or closed.
+	pub fn mark_lost(&mut self, reason: LossReason) {
+		if matches!(self.status, ConnectionStatus::Pending | ConnectionStatus::Open) {
+			self.status = ConnectionStatus::Lost;
+			self.loss_reason = Some(reason);
+		}
+	}
+
 	/// Get the connection id if the connection has one.
 	///
 	/// A [pending](ConnectionStatus::Pending) connection may not have a valid id yet.
@@ -83,71 +827,3933 @@ impl<P: Parcel> Context<P> {
 		}
 	}
 
-	/// Get the next processed parcel.
+	/// Check whether a black hole was detected on the path to the other end.
 	///
-	/// Includes the data prelude from the network packet that the parcel was transmitted with.
-	pub fn pop_parcel(&mut self) -> Result<(P, [u8; 4]), ConnectionError> {
-		todo!()
+	/// Some paths silently drop datagrams above a certain length. Once several consecutive
+	/// synchronized packets longer than [`BLACK_HOLE_DATAGRAM_LENGTH`](BLACK_HOLE_DATAGRAM_LENGTH)
+	/// are lost while shorter ones are delivered, further packets are limited to that length, see
+	/// [`ConnectionStats::black_hole_count`](ConnectionStats::black_hole_count). Lost packets keep
+	/// their length when re-transmitted, as they can not be split without reordering their data,
+	/// so the ones that revealed the black hole are still re-sent and depend on the path
+	/// recovering.
+	#[inline]
+	pub fn is_black_hole_detected(&self) -> bool {
+		self.black_hole_length.is_some()
 	}
 
-	/// Queue provided parcel to be included in built packets.
+	/// Get the length of the largest datagram the other end can receive, if it is known.
 	///
-	/// Reliable parcels are guaranteed to be delivered as long as the connection
-	/// is in a valid state. The order of delivery is not guaranteed however, for
-	/// order-dependent functionality use streams.
-	pub fn push_reliable_parcel(&mut self, parcel: P) -> Result<(), ConnectionError> {
-		todo!()
+	/// The length is announced by the listener in its accept packet, so it is only known by the
+	/// requesting end of the connection. Built packets are limited to this length.
+	#[inline]
+	pub fn remote_max_datagram_length(&self) -> Option<usize> {
+		self.remote_max_datagram_length
 	}
 
-	/// Queue provided parcel to be included in built packets.
+	/// Fix the length of the largest datagram built by the connection, or `None` to only be
+	/// limited by provided buffers, which is the default.
 	///
-	/// Unreliable (volatile) parcels are delivered in a best-effort manner, however no
-	/// re-transmission occurs of the parcel was not received by the other end. The order
-	/// of delivery is not guaranteed, for order-dependent functionality use streams.
-	pub fn push_volatile_parcel(&mut self, parcel: P) -> Result<(), ConnectionError> {
-		todo!()
+	/// For networks with a known MTU, so that built packets fit it regardless of the buffers they
+	/// are built into. The length still has to hold the largest possible parcel, otherwise
+	/// building packets fails with [`InsufficientBuffer`](BuildPacketError::InsufficientBuffer).
+	/// The [maximum datagram length of the other end](Self::remote_max_datagram_length) limits
+	/// packets further, as larger ones can not be received.
+	///
+	/// A detected [black hole](Self::is_black_hole_detected) limits packets further. Lowering the
+	/// length does not affect packets already sent, which keep their length when re-transmitted.
+	#[inline]
+	pub fn set_max_datagram_length(&mut self, length: Option<usize>) {
+		self.max_datagram_length = length;
 	}
 
-	/// Attempt to read data from the connection stream into the provided buffer.
+	/// Set the compressor applied to payloads of sent packets and used to decompress received ones.
 	///
-	/// # Returns
-	/// Number of bytes read.
+	/// Both ends of the connection must use compatible compressors. Packets whose payload does not
+	/// get smaller are sent uncompressed.
+	pub fn set_compressor(&mut self, compressor: Option<Box<dyn Compressor>>) {
+		self.compressor = compressor;
+	}
+
+	/// Set the byte count of the smallest packet payload that is compressed.
 	///
-	/// # Streams
-	/// Connection streams offer
-	/// [TCP](https://en.wikipedia.org/wiki/Transmission_Control_Protocol)-like functionality for
-	/// contiguous streams of data. Streams are transmitted with the same network packets as
-	/// reliable parcels, reducing overall data duplication for lost packets.
+	/// Defaults to [`MIN_COMPRESSED_LENGTH`](MIN_COMPRESSED_LENGTH). Smaller payloads, such as
+	/// those of small real-time parcels, are sent uncompressed without invoking the
+	/// [compressor](Self::set_compressor), as they rarely get smaller and compressing them only
+	/// costs time. The receiving end is unaffected, the packet header tells whether a packet is
+	/// compressed.
+	#[inline]
+	pub fn set_min_compressed_length(&mut self, byte_count: usize) {
+		self.min_compressed_length = byte_count;
+	}
+
+	/// Set the pool serialized parcels and copies of sent packets are allocated from.
 	///
-	/// # Note
-	/// Has consuming behavior, meaning repeated invocations will read exhaust internal stream
-	/// buffer.
-	pub fn read_from_stream(&mut self, buffer: &mut [u8]) -> Result<usize, ConnectionError> {
-		todo!()
+	/// Defaults to the [`HeapPool`](HeapPool). Buffers are released to the pool once their
+	/// parcels are written into a packet, or once their packets are acknowledged. Buffers
+	/// allocated before the pool is set are released to the new pool as well.
+	#[inline]
+	pub fn set_buffer_pool(&mut self, pool: Arc<dyn BufferPool>) {
+		self.buffer_pool = pool;
 	}
 
-	/// Write a given slice of bytes to the connection stream.
+	/// Set the length every built packet is padded to, or `None` to disable padding.
 	///
-	/// # Streams
-	/// Connection streams offer
-	/// [TCP](https://en.wikipedia.org/wiki/Transmission_Control_Protocol)-like functionality
-	/// for contiguous streams of data. Streams are transmitted with the same network packets
-	/// as reliable parcels, reducing overall data duplication for lost packets.
-	pub fn write_bytes_to_stream(&mut self, bytes: &[u8]) -> Result<(), ConnectionError> {
-		todo!()
+	/// Padding hides the size of the actual payload from traffic analysis and allows keeping a
+	/// constant bitrate. The padding consists of zeroes following the data accounted for by the
+	/// packet header, so it is ignored by the receiving end and never delivered to the application.
+	/// Packets are padded up to the length of the buffer they are built into at most. Disabled by
+	/// default.
+	#[inline]
+	pub fn set_padding(&mut self, padded_length: Option<usize>) {
+		self.padded_length = padded_length;
 	}
 
-	/// Build the next packet that should be sent for this connection.
+	/// Set the number of received parcels that may be queued, or `None` for no limit.
 	///
-	/// The connection must be in [`Open`](ConnectionStatus::Open) state!
-	pub fn build_packet(&mut self, buffer: &mut [u8]) -> Result<usize, BuildPacketError> {
-		todo!()
+	/// Once the queue is full, received volatile parcels are dropped and counted, see
+	/// [`dropped_parcel_count`](Self::dropped_parcel_count). Synchronized packets carrying
+	/// parcels are neither processed nor acknowledged, so the other end re-transmits them until
+	/// parcels are [popped](Self::pop_parcel). A single packet may exceed the limit by the
+	/// parcels it carries. No limit by default.
+	///
+	/// The remaining capacity of the queue is advertised to the other end with every sent packet,
+	/// which holds back queued reliable parcels once it is exhausted, instead of re-transmitting
+	/// refused packets. Volatile parcels and stream data are not held back.
+	#[inline]
+	pub fn set_max_received_parcels(&mut self, max_parcel_count: Option<usize>) {
+		self.max_received_parcels = max_parcel_count;
 	}
 
-	/// Build a connection-requesting packet that contains provided payload.
+	/// Set the number of incoming stream bytes that may be buffered, or `None` for no limit.
 	///
-	/// The connection must be in [`Pending`](ConnectionStatus::Pending) state!
-	pub fn build_request_packet(&mut self, buffer: &mut [u8], payload: &[u8]) -> Result<usize, BuildPacketError> {
-		todo!()
+	/// Counts both the bytes ready to be [read](Self::read_from_stream) and those received past a
+	/// gap left by a lost packet. Like the [received parcel limit](Self::set_max_received_parcels),
+	/// synchronized packets whose stream segments do not fit are neither processed nor
+	/// acknowledged, so the other end re-transmits them until there is room, keeping the stream
+	/// intact. The segment filling the gap is only refused while the readable bytes alone reach
+	/// the limit, so data past the gap can not hold the gap open forever, and the buffer may
+	/// exceed the limit by the segments it releases. No limit by default.
+	#[inline]
+	pub fn set_max_stream_buffer_length(&mut self, byte_count: Option<usize>) {
+		self.max_stream_buffer_length = byte_count;
+	}
+
+	/// Set the number of parcels coalesced into a single packet, or `None` for no limit.
+	///
+	/// By default a packet carries as many queued parcels as fit it, which minimizes the number
+	/// of packets, but a lost synchronized packet then re-transmits all of its parcels together,
+	/// and a lost volatile packet loses all of them. A lower limit bounds how many parcels a
+	/// single loss affects, at the cost of more packets and more header overhead. A
+	/// [batch](Self::push_reliable_batch) counts as all of its parcels, and is sent in a packet of
+	/// its own if it holds more parcels than the limit, as it is never split. Stream data is not
+	/// limited.
+	///
+	/// # Panics
+	/// If the count is zero.
+	pub fn set_max_parcels_per_packet(&mut self, max_parcel_count: Option<usize>) {
+		assert!(max_parcel_count != Some(0), "packets must be able to carry at least one parcel");
+		self.max_parcels_per_packet = max_parcel_count;
+	}
+
+	/// Get the number of processed parcels yet to be [popped](Self::pop_parcel).
+	///
+	/// Zero once the queue of received parcels is drained.
+	#[inline]
+	pub fn pending_parcel_count(&self) -> usize {
+		self.received_parcels.len()
+	}
+
+	/// Get the number of received volatile parcels that were dropped, as the queue of received
+	/// parcels was full.
+	///
+	/// See [`set_max_received_parcels`](Self::set_max_received_parcels).
+	#[inline]
+	pub fn dropped_parcel_count(&self) -> u64 {
+		stats::load(&self.counters.dropped_parcels)
+	}
+
+	/// Set how received synchronized packets are acknowledged.
+	///
+	/// Acknowledgements are carried by every sent packet. Without outgoing data to carry them, an
+	/// acknowledgement is sent once `packet_count` synchronized packets are received, or
+	/// `max_delay` after the first of them is received, whichever comes first. Delaying
+	/// acknowledgements reduces the return traffic of high-rate connections, however the delay
+	/// must stay well under the [retransmission timeout](Self::current_rto) of the other end to
+	/// avoid spurious re-transmissions. Defaults to acknowledging every packet immediately.
+	///
+	/// # Panics
+	/// If `packet_count` is zero.
+	pub fn set_ack_delay(&mut self, packet_count: u32, max_delay: Duration) {
+		assert!(packet_count > 0, "acknowledgement frequency must be non-zero");
+		self.ack_frequency = packet_count;
+		self.max_ack_delay = max_delay;
+	}
+
+	/// Get the number of received synchronized packets yet to be acknowledged.
+	///
+	/// Reset by every built packet, as all of them carry the current acknowledgements.
+	#[inline]
+	pub fn pending_ack_count(&self) -> u32 {
+		self.pending_ack_count
+	}
+
+	/// Apply the preset settings of provided [QoS class](QosClass).
+	///
+	/// Overrides [pacing](Self::set_pacing), the [acknowledgement delay](Self::set_ack_delay) and
+	/// the [retransmission timeout bounds](Self::set_rto_bounds), see each class for its presets.
+	/// Individual settings may still be changed afterwards. A connection that is never assigned a
+	/// class uses the defaults of each setting.
+	pub fn set_qos_class(&mut self, class: QosClass) {
+		let (is_pacing_enabled, ack_frequency, max_ack_delay, rto_bounds) = match class {
+			QosClass::Realtime => (false, 1, 0, (MIN_RETRANSMISSION_TIMEOUT, RETRANSMISSION_TIMEOUT)),
+			QosClass::Bulk => (true, 4, 20, (MIN_RETRANSMISSION_TIMEOUT, MAX_RETRANSMISSION_TIMEOUT)),
+			QosClass::Background => (true, 8, 40, (Duration::from_millis(200), MAX_RETRANSMISSION_TIMEOUT)),
+		};
+		self.set_pacing(is_pacing_enabled);
+		self.set_ack_delay(ack_frequency, Duration::from_millis(max_ack_delay));
+		self.set_rto_bounds(rto_bounds.0, rto_bounds.1);
+	}
+
+	/// Set the span of time without exchanged application data after which the connection is
+	/// considered [idle](Self::is_idle).
+	///
+	/// Defaults to [`IDLE_THRESHOLD`](IDLE_THRESHOLD).
+	pub fn set_idle_threshold(&mut self, threshold: Duration) {
+		self.idle_threshold = threshold;
+	}
+
+	/// Check whether the connection has not exchanged any application data recently.
+	///
+	/// An idle connection is still [open](ConnectionStatus::Open) and kept alive by keep-alive
+	/// packets, but neither end has sent parcels or stream data within the
+	/// [idle threshold](Self::set_idle_threshold). Servers may use this to update such connections
+	/// less frequently without closing them.
+	pub fn is_idle(&self) -> bool {
+		self.status == ConnectionStatus::Open && self.clock.now().saturating_duration_since(self.last_data_time) >= self.idle_threshold
+	}
+
+	/// Get a snapshot of the sequencing counters of the connection.
+	pub fn sequence_state(&self) -> SequenceState {
+		SequenceState {
+			next_packet_id: self.next_packet_id,
+			acknowledged_packet_id: self.acknowledged_packet_id,
+			in_flight_count: self.sent_packets.len(),
+			received_packet_ack_id: self.received_packet_ack_id,
+			received_packet_ack_mask: self.received_packet_ack_mask,
+			duplicate_packet_count: stats::load(&self.counters.duplicate_packets),
+		}
+	}
+
+	/// Get a snapshot of the counters of the connection.
+	#[inline]
+	pub fn stats(&self) -> ConnectionStats {
+		self.counters.snapshot()
+	}
+
+	/// Get a handle to the counters of the connection, to take [snapshots](StatsHandle::snapshot)
+	/// of them from another thread without borrowing the context.
+	///
+	/// All handles share the same counters, which are updated as the connection builds and
+	/// processes packets. A [resumed](Self::resume) connection starts with new counters.
+	#[inline]
+	pub fn stats_handle(&self) -> StatsHandle {
+		self.counters.handle()
+	}
+
+	/// Render a human-readable snapshot of the internal state of the connection.
+	///
+	/// Includes the status, id, round-trip time, in-flight packets, acknowledgement state, queue
+	/// depths and the time since the last sent and received packets. Intended for diagnosing
+	/// stuck connections, for example by logging it when a connection is unexpectedly lost. Has
+	/// no side effects.
+	pub fn debug_dump(&self) -> String {
+		let now = self.clock.now();
+		let mut dump = String::new();
+		let _ = writeln!(dump, "status: {:?}", self.status);
+		if let Some(reason) = self.loss_reason {
+			let _ = writeln!(dump, "loss reason: {:?}", reason);
+		}
+		let _ = writeln!(dump, "connection id: {}", self.connection_id);
+		match self.round_trip_time() {
+			Some(rtt) => {
+				let _ = writeln!(dump, "round-trip time: {:?}, rto: {:?}", rtt, self.current_rto());
+			},
+			None => {
+				let _ = writeln!(dump, "round-trip time: unmeasured, rto: {:?}", self.current_rto());
+			},
+		}
+		let _ = writeln!(
+			dump,
+			"in-flight packets: {} (next id {}, acknowledged id {})",
+			self.sent_packets.len(),
+			self.next_packet_id,
+			self.acknowledged_packet_id,
+		);
+		let _ = writeln!(
+			dump,
+			"received ack id: {}, mask: {:064b}",
+			self.received_packet_ack_id,
+			self.received_packet_ack_mask,
+		);
+		let _ = writeln!(
+			dump,
+			"outgoing: {} reliable parcels, {} volatile parcels, {} stream bytes",
+			self.reliable_parcels.len(),
+			self.volatile_parcels.len(),
+			self.outgoing_stream.len(),
+		);
+		let _ = writeln!(
+			dump,
+			"incoming: {} parcels, {} stream bytes, {} pending stream segments",
+			self.received_parcels.len(),
+			self.incoming_stream.len(),
+			self.pending_stream_segments.len(),
+		);
+		let _ = write!(
+			dump,
+			"last sent: {:?} ago, last received: {:?} ago",
+			now.saturating_duration_since(self.last_sent_packet_time),
+			now.saturating_duration_since(self.last_received_packet_time),
+		);
+		dump
+	}
+
+	/// Get the smoothed round-trip time of the connection.
+	///
+	/// Measured from acknowledgements of synchronized packets that were not re-transmitted.
+	/// `None` until the first such acknowledgement is received.
+	#[inline]
+	pub fn round_trip_time(&self) -> Option<Duration> {
+		self.round_trip_time.smoothed
+	}
+
+	/// Get the smoothed inter-arrival jitter of received packets.
+	///
+	/// Every packet carries the moment it was sent in milliseconds, so the jitter is computed as
+	/// in [RFC 3550](https://tools.ietf.org/html/rfc3550#appendix-A.8). For consecutively
+	/// received packets `i` and `j`, sent at `S` and received at `R` (see
+	/// [`process_packet_received_at`](Self::process_packet_received_at)), the difference of their
+	/// transit times is `D(i, j) = (Rj - Ri) - (Sj - Si)` and the jitter is updated as
+	/// `J = J + (|D(i, j)| - J) / 16`, starting at zero. `None` until two packets are received.
+	#[inline]
+	pub fn jitter(&self) -> Option<Duration> {
+		self.jitter.map(Duration::from_secs_f64)
+	}
+
+	/// Get the smoothed span of time parcels wait in the outgoing queues before they are sent.
+	///
+	/// Sampled for every parcel when it is first written into a packet, re-transmissions are not
+	/// accounted for, and smoothed as `L = L + (sample - L) / 8`. Unlike the
+	/// [round-trip time](Self::round_trip_time) it reflects local delays, such as a full
+	/// in-flight window, [pacing](Self::set_pacing) or infrequent calls to
+	/// [`build_packet`](Self::build_packet). A growing latency signals the application to queue
+	/// less data. `None` until the first parcel is sent.
+	#[inline]
+	pub fn queue_latency(&self) -> Option<Duration> {
+		self.queue_latency
+	}
+
+	/// Get an estimate of the rate the path to the other end currently absorbs, in bytes per
+	/// second.
+	///
+	/// Computed as the in-flight window in bytes over the round-trip time, reduced by the recent
+	/// loss rate: `window * length / rtt * (1 - loss)`, where `window` is the
+	/// [congestion window](Self::congestion_window) when congestion control is enabled and the
+	/// full in-flight window of 65 packets otherwise, `length` the byte count of the largest
+	/// synchronized packet sent, `rtt` the smoothed
+	/// [round-trip time](Self::round_trip_time) and `loss` the smoothed ratio of lost
+	/// synchronized packets, updated as `L = L + (sample - L) / 16` for every delivered (`0`) and
+	/// lost (`1`) packet. `None` until the round-trip time is measured.
+	///
+	/// Intended for picking an encoding rate, such as the bitrate of a media stream. As it is
+	/// derived from smoothed samples the estimate lags behind changing path conditions, and it is
+	/// an upper bound of what the connection sends, as the in-flight window is rarely full.
+	pub fn estimated_bandwidth(&self) -> Option<u64> {
+		let rtt = self.round_trip_time.smoothed?.as_secs_f64().max(f64::EPSILON);
+		let window = (self.in_flight_window() * self.max_sent_packet_length) as f64;
+		Some((window / rtt * (1.0 - self.loss_rate)) as u64)
+	}
+
+	/// Get the current retransmission timeout (RTO).
+	///
+	/// Synchronized packets that are not acknowledged within this span of time are deemed lost
+	/// and re-transmitted. The timeout is derived from the measured round-trip time (initially
+	/// the [initial timeout](Self::set_initial_rto)) and clamped to the
+	/// [configured bounds](Self::set_rto_bounds).
+	pub fn current_rto(&self) -> Duration {
+		let (min, max) = self.rto_bounds;
+		self.round_trip_time.retransmission_timeout().unwrap_or(self.initial_rto).max(min).min(max)
+	}
+
+	/// Set the retransmission timeout used until the first round-trip time is measured.
+	///
+	/// Defaults to [`RETRANSMISSION_TIMEOUT`](RETRANSMISSION_TIMEOUT). Applies to the handshake
+	/// and the first packets of the connection, once an acknowledgement is received the timeout
+	/// follows the measured round-trip time instead.
+	#[inline]
+	pub fn set_initial_rto(&mut self, timeout: Duration) {
+		self.initial_rto = timeout;
+	}
+
+	/// Set the bounds the [retransmission timeout](Self::current_rto) is clamped to.
+	///
+	/// Defaults to [`MIN_RETRANSMISSION_TIMEOUT`](MIN_RETRANSMISSION_TIMEOUT) and
+	/// [`MAX_RETRANSMISSION_TIMEOUT`](MAX_RETRANSMISSION_TIMEOUT). Bounding the timeout prevents
+	/// round-trip time spikes from stalling delivery. Setting both bounds to the same value
+	/// overrides the timeout entirely.
+	///
+	/// # Panics
+	/// If `min` is greater than `max`.
+	pub fn set_rto_bounds(&mut self, min: Duration, max: Duration) {
+		assert!(min <= max, "minimum retransmission timeout exceeds the maximum");
+		self.rto_bounds = (min, max);
+	}
+
+	/// Enable or disable pacing of outgoing data packets.
+	///
+	/// Disabled by default, in which case [`build_packet`](Self::build_packet) builds data packets
+	/// back-to-back for as long as the in-flight window permits. When enabled, consecutive data
+	/// packets are spaced by the [pacing interval](Self::pacing_interval), spreading a window
+	/// worth of packets over a round-trip instead of sending them in a burst. Acknowledgements
+	/// and keep-alives are never delayed. Use [`next_update_deadline`](Self::next_update_deadline)
+	/// to learn when the next paced packet is due.
+	#[inline]
+	pub fn set_pacing(&mut self, is_enabled: bool) {
+		self.is_pacing_enabled = is_enabled;
+	}
+
+	/// Get the span of time between consecutive data packets when [pacing](Self::set_pacing).
+	///
+	/// The smoothed round-trip time divided by the size of the in-flight window, which is the
+	/// [congestion window](Self::congestion_window) when congestion control is enabled. Zero until
+	/// the round-trip time is measured, so the first packets are not paced.
+	pub fn pacing_interval(&self) -> Duration {
+		self.round_trip_time.smoothed.map_or(Duration::ZERO, |smoothed| smoothed / self.in_flight_window() as u32)
+	}
+
+	/// Set the congestion control algorithm of synchronized packets, or `None` to disable it.
+	///
+	/// Disabled by default, in which case up to the full in-flight window of synchronized packets
+	/// is sent right away. When enabled, the number of unacknowledged synchronized packets is
+	/// limited by a congestion window, starting at
+	/// [`INITIAL_CONGESTION_WINDOW`](INITIAL_CONGESTION_WINDOW) packets:
+	/// - In slow start, every acknowledged packet grows the window by one packet, doubling it
+	///   every round-trip, until the first loss or the full in-flight window is reached.
+	/// - Every loss halves the window, once per round-trip, as losses of packets sent before the
+	///   previous reduction are ignored. Slow start ends at the first loss.
+	/// - Afterwards the window grows additively, by one packet per round-trip, or follows the
+	///   round-trip time with [delay-based](CongestionControl::DelayBased) control.
+	///
+	/// Only new synchronized packets are held back, re-transmissions of lost ones are already
+	/// counted in flight. Volatile packets, acknowledgements and keep-alives are not limited.
+	/// Setting an algorithm restarts slow start from the initial window.
+	pub fn set_congestion_control(&mut self, algorithm: Option<CongestionControl>) {
+		self.congestion_control = algorithm;
+		self.congestion_window = INITIAL_CONGESTION_WINDOW as f64;
+		self.slow_start_threshold = MAX_IN_FLIGHT_PACKETS as f64;
+		self.congestion_reduction_time = None;
+		self.round_trip_time_samples = None;
+	}
+
+	/// Get the number of synchronized packets allowed in flight by
+	/// [congestion control](Self::set_congestion_control), `None` if disabled.
+	#[inline]
+	pub fn congestion_window(&self) -> Option<usize> {
+		self.congestion_control.map(|_| self.congestion_window as usize)
+	}
+
+	/// Check whether [congestion control](Self::set_congestion_control) is in its slow start
+	/// phase, growing the congestion window exponentially.
+	#[inline]
+	pub fn is_slow_start(&self) -> bool {
+		self.congestion_control.is_some() && self.congestion_window < self.slow_start_threshold
+	}
+
+	/// Limit the rate of outgoing data packets, in bytes per second.
+	///
+	/// Unlimited by default. When limited, data packets draw from a send budget that is refilled
+	/// at the rate on every [`update`](Self::update), holding up to
+	/// [`SEND_BUDGET_BURST`](SEND_BUDGET_BURST) worth of bytes. A data packet is built whenever the
+	/// budget is not overdrawn, so the packet that exhausts it may overdraw it, delaying the
+	/// following ones. Parcels that do not fit the budget stay queued for later packets.
+	/// Acknowledgements and keep-alives are never delayed, nor do they draw from the budget. Use
+	/// [`next_update_deadline`](Self::next_update_deadline) to learn when the budget allows the
+	/// next data packet.
+	///
+	/// Setting a rate fills the budget.
+	///
+	/// # Panics
+	/// If the rate is zero.
+	pub fn set_send_rate(&mut self, rate: Option<u64>) {
+		assert!(rate != Some(0), "send rate must be non-zero");
+		self.send_rate = rate;
+		self.send_budget = rate.map_or(0.0, Self::max_send_budget);
+		self.send_budget_time = self.clock.now();
+	}
+
+	/// Get the rate outgoing data packets are [limited](Self::set_send_rate) to, if any.
+	#[inline]
+	pub fn send_rate(&self) -> Option<u64> {
+		self.send_rate
+	}
+
+	/// Set the backoff of re-sent connection requests.
+	///
+	/// Defaults to [`INITIAL_REQUEST_INTERVAL`](INITIAL_REQUEST_INTERVAL) and
+	/// [`MAX_REQUEST_INTERVAL`](MAX_REQUEST_INTERVAL). The first request is re-sent after
+	/// `initial`, and every further interval doubles up to `max`. Each interval is randomly
+	/// shortened by up to a quarter, so that clients reconnecting to a restarted server do not
+	/// send their requests in lockstep. See
+	/// [`next_update_deadline`](Self::next_update_deadline) for when the request is due.
+	///
+	/// # Panics
+	/// If `initial` is greater than `max`.
+	pub fn set_request_backoff(&mut self, initial: Duration, max: Duration) {
+		assert!(initial <= max, "initial request interval exceeds the maximum");
+		self.request_backoff = (initial, max);
+	}
+
+	/// Limit the number of sent connection requests.
+	///
+	/// Unlimited by default, in which case a [pending](ConnectionStatus::Pending) connection keeps
+	/// re-sending its request until the [`TIMEOUT`](TIMEOUT) elapses. When limited, the
+	/// connection is deemed [lost](ConnectionStatus::Lost) with
+	/// [`TooManyRequests`](LossReason::TooManyRequests) reason once the last request is due to be
+	/// re-sent, on the next [`update`](Self::update) or
+	/// [`build_request_packet`](Self::build_request_packet). Both limits apply, whichever is hit
+	/// first.
+	///
+	/// # Panics
+	/// If the count is zero.
+	pub fn set_max_request_count(&mut self, count: Option<u32>) {
+		assert!(count != Some(0), "maximum request count must be non-zero");
+		self.max_request_count = count;
+	}
+
+	/// Get the earliest moment the connection requires an [`update`](Self::update) or a call to
+	/// [`build_packet`](Self::build_packet).
+	///
+	/// The deadline is the soonest of the next retransmission, the next keep-alive, the next
+	/// [paced](Self::set_pacing) or [budgeted](Self::set_send_rate) data packet and the timeout.
+	/// A [pending](ConnectionStatus::Pending) connection instead requires the connection request
+	/// to be re-sent, with an exponential [backoff](Self::set_request_backoff).
+	///
+	/// # Notes
+	/// - Does not perform any I/O, only reads the internal timers.
+	/// - The deadline may already be in the past, for example when there is data ready to be
+	///   sent. [`Lost`](ConnectionStatus::Lost) and [`Closed`](ConnectionStatus::Closed)
+	///   connections require no more updates and always return the current moment.
+	pub fn next_update_deadline(&self) -> Instant {
+		let timeout = self.last_received_packet_time + TIMEOUT;
+		match self.status {
+			ConnectionStatus::Pending => timeout.min(self.request_retry_time),
+			ConnectionStatus::Open => {
+				if self.has_ready_packet() {
+					return self.clock.now()
+				}
+				let paced_time = self.next_send_time();
+				self.sent_packets
+					.iter()
+					.map(|packet| (packet.sent_time + self.current_rto()).max(paced_time))
+					.chain(self.has_ready_data().then_some(paced_time))
+					.chain(self.ack_deadline())
+					.fold(timeout.min(self.keep_alive_deadline()), Instant::min)
+			},
+			ConnectionStatus::Lost | ConnectionStatus::Closed => self.clock.now(),
+		}
+	}
+
+	/// Update the connection timers.
+	///
+	/// Transitions the connection to [`Lost`](ConnectionStatus::Lost) state if no packets have
+	/// been received within the [`TIMEOUT`](TIMEOUT) window, and refills the
+	/// [send budget](Self::set_send_rate).
+	pub fn update(&mut self) {
+		self.refill_send_budget();
+		if self.clock.now().saturating_duration_since(self.last_received_packet_time) >= TIMEOUT {
+			self.mark_lost(LossReason::TimedOut);
+		}
+		self.check_request_count();
+	}
+
+	/// Update the connection and get the next event, if any.
+	///
+	/// A single entry point to the state of the connection, [updating](Self::update) its timers
+	/// and reporting, in order: status changes, delivered packets, lost packets, ping results,
+	/// received parcels and received stream data. Returns `None` once there are no more events,
+	/// call again after processing more packets.
+	///
+	/// Received parcels are popped, so they are no longer returned by
+	/// [`pop_parcel`](Self::pop_parcel). Stream data is only reported and remains to be
+	/// [read](Self::read_from_stream).
+	pub fn poll(&mut self) -> Option<ConnectionEvent<P>> {
+		self.update();
+		if self.status != self.reported_status {
+			self.reported_status = self.status;
+			return Some(ConnectionEvent::StatusChanged(self.status))
+		}
+		if let Some(packet_id) = self.delivered_packets.pop_front() {
+			return Some(ConnectionEvent::PacketDelivered(packet_id))
+		}
+		if let Some(packet_id) = self.lost_packets.pop_front() {
+			return Some(ConnectionEvent::PacketLost(packet_id))
+		}
+		if let Some(round_trip_time) = self.ping_results.pop_front() {
+			return Some(ConnectionEvent::PingResult(round_trip_time))
+		}
+		if let Some((parcel, prelude, _)) = self.received_parcels.pop_front() {
+			return Some(ConnectionEvent::ParcelReceived(parcel, prelude))
+		}
+		if self.is_stream_data_unreported {
+			self.is_stream_data_unreported = false;
+			return Some(ConnectionEvent::StreamData(self.incoming_stream.len()))
+		}
+		None
+	}
+
+	/// Get the next processed parcel.
+	///
+	/// Includes the data prelude from the network packet that the parcel was transmitted with.
+	pub fn pop_parcel(&mut self) -> Result<(P, DataPrelude), ConnectionError> {
+		self.pop_parcel_timestamped().map(|(parcel, prelude, _)| (parcel, prelude))
+	}
+
+	/// Get the next processed parcel, along with the moment the packet carrying it was received.
+	///
+	/// The moment is the one provided to
+	/// [`process_packet_received_at`](Self::process_packet_received_at), or the time of processing
+	/// the packet otherwise.
+	pub fn pop_parcel_timestamped(&mut self) -> Result<(P, DataPrelude, Instant), ConnectionError> {
+		self.received_parcels.pop_front().ok_or(ConnectionError::NoPendingParcels)
+	}
+
+	/// Set the data prelude of parcels pushed from now on.
+	///
+	/// The prelude is transmitted in the header of each packet, so parcels with different
+	/// preludes are never sent in the same packet. The receiving end gets the prelude along with
+	/// each parcel from [`pop_parcel`](Self::pop_parcel), for example to tag the type of the
+	/// parcel (see [`ParcelRouter`](super::router::ParcelRouter)). Defaults to zeroes.
+	#[inline]
+	pub fn set_prelude(&mut self, prelude: DataPrelude) {
+		self.outgoing_prelude = prelude;
+	}
+
+	/// Queue provided parcel to be included in built packets.
+	///
+	/// Reliable parcels are guaranteed to be delivered as long as the connection
+	/// is in a valid state. The order of delivery is not guaranteed however, for
+	/// order-dependent functionality use streams.
+	///
+	/// Parcels may already be queued while the connection is
+	/// [`Pending`](ConnectionStatus::Pending), to be sent by the first
+	/// [built packets](Self::build_packet) once it opens, saving the application a round trip.
+	/// The delivery guarantee only applies once the connection is open: if the request is
+	/// rejected or times out, the queued parcels are never sent. Parcels of a connection that was
+	/// lost may be taken back with [`take_unacked_parcels`](Self::take_unacked_parcels).
+	pub fn push_reliable_parcel(&mut self, parcel: P) -> Result<(), ConnectionError> {
+		let bytes = self.serialize_parcel(&parcel)?;
+		self.reliable_parcels.push_back(OutgoingParcel { bytes, parcel_count: 1, prelude: self.outgoing_prelude, queued_at: self.clock.now(), expires_at: None });
+		Ok(())
+	}
+
+	/// Queue provided parcels to be delivered together, in a single packet.
+	///
+	/// The batch is atomic with respect to packet loss: either all of the parcels arrive or all
+	/// of them are re-transmitted together. The receiving end pops them in provided order. The
+	/// parcels together must not serialize to more than
+	/// [`Parcel::MAX_BYTE_COUNT`](Parcel::MAX_BYTE_COUNT) bytes, otherwise
+	/// [`BatchTooLarge`](ConnectionError::BatchTooLarge) is returned and nothing is queued.
+	///
+	/// The batch counts as all of its parcels against the receive window of the other end, see
+	/// [`set_max_received_parcels`](Self::set_max_received_parcels), so it is held back until the
+	/// whole batch fits.
+	pub fn push_reliable_batch(&mut self, parcels: &[P]) -> Result<(), ConnectionError> {
+		let mut bytes = self.buffer_pool.acquire(P::MAX_BYTE_COUNT);
+		for parcel in parcels {
+			if let Err(error) = self.serialize_parcel_into(parcel, &mut bytes) {
+				self.buffer_pool.release(bytes);
+				return Err(error)
+			}
+			if bytes.len() > P::MAX_BYTE_COUNT {
+				self.buffer_pool.release(bytes);
+				return Err(ConnectionError::BatchTooLarge)
+			}
+		}
+		if bytes.is_empty() {
+			self.buffer_pool.release(bytes);
+		} else {
+			let parcel_count = parcels.len();
+			self.reliable_parcels.push_back(OutgoingParcel { bytes, parcel_count, prelude: self.outgoing_prelude, queued_at: self.clock.now(), expires_at: None });
+		}
+		Ok(())
+	}
+
+	/// Queue provided parcel to be included in built packets.
+	///
+	/// Unreliable (volatile) parcels are delivered in a best-effort manner, however no
+	/// re-transmission occurs of the parcel was not received by the other end. The order
+	/// of delivery is not guaranteed, for order-dependent functionality use streams.
+	pub fn push_volatile_parcel(&mut self, parcel: P) -> Result<(), ConnectionError> {
+		self.push_volatile(parcel, None)
+	}
+
+	/// Queue provided parcel to be included in built packets, unless it is still queued after
+	/// provided time-to-live.
+	///
+	/// Same as [`push_volatile_parcel`](Self::push_volatile_parcel), but for time-sensitive data
+	/// that is useless once late, such as a position update. Parcels that expire before they are
+	/// written into a packet, for example as they are held back by [pacing](Self::set_pacing),
+	/// are dropped and [counted](Self::expired_parcel_count). The time-to-live only applies to
+	/// the local queue, parcels are not dropped by the other end.
+	pub fn push_volatile_parcel_with_ttl(&mut self, parcel: P, ttl: Duration) -> Result<(), ConnectionError> {
+		self.push_volatile(parcel, Some(ttl))
+	}
+
+	/// Get the number of volatile parcels that were dropped as their
+	/// [time-to-live](Self::push_volatile_parcel_with_ttl) expired before they were sent.
+	#[inline]
+	pub fn expired_parcel_count(&self) -> u64 {
+		stats::load(&self.counters.expired_parcels)
+	}
+
+	/// Take the reliable parcels of a [lost](ConnectionStatus::Lost) connection that the other end
+	/// never acknowledged, along with their preludes, for example to queue them on a new
+	/// connection.
+	///
+	/// Once lost, a connection stops building packets and refuses further data with
+	/// [`ConnectionLost`](ConnectionError::ConnectionLost), while keeping the parcels that were
+	/// in flight or still queued. They are returned in the order they were queued in, the ones
+	/// in flight first, and are not returned again. The other end may have received some of the
+	/// parcels in flight before the loss, so they may be delivered twice. Stream data and
+	/// volatile parcels are not recovered.
+	///
+	/// # Notes
+	/// - The connection must be in [`Lost`](ConnectionStatus::Lost) state!
+	pub fn take_unacked_parcels(&mut self) -> Result<Vec<(P, DataPrelude)>, ConnectionError> {
+		if self.status != ConnectionStatus::Lost {
+			return Err(ConnectionError::InvalidState)
+		}
+		let mut parcels = Vec::new();
+		while let Some(sent_packet) = self.sent_packets.pop_front() {
+			let header = packet::get_header(&sent_packet.bytes);
+			let result = if header.signal.is_signal_set(Signal::Compressed) {
+				self.decompress_packet(&sent_packet.bytes, header)
+					.map_err(|_| SerializationError::UnexpectedValue)
+					.and_then(|packet| read_own_parcels(packet::get_parcel_segment(&packet), header.prelude, &mut parcels))
+			} else {
+				read_own_parcels(packet::get_parcel_segment(&sent_packet.bytes), header.prelude, &mut parcels)
+			};
+			self.buffer_pool.release(sent_packet.bytes);
+			result?;
+		}
+		while let Some(parcel) = self.reliable_parcels.pop_front() {
+			let result = read_own_parcels(&parcel.bytes, parcel.prelude, &mut parcels);
+			self.buffer_pool.release(parcel.bytes);
+			result?;
+		}
+		Ok(parcels)
+	}
+
+	/// Enable or disable raw datagram mode.
+	///
+	/// In raw mode the data segment of every received volatile packet is queued as a single raw
+	/// datagram, to be read with [`recv_raw_datagrams`](Self::recv_raw_datagrams), instead of
+	/// being deserialized into parcels. Raw datagrams are sent with
+	/// [`build_raw_packet`](Self::build_raw_packet). This exposes the validated transport of the
+	/// connection (framing, acknowledgements, compression) to protocols implementing their own
+	/// delivery semantics on top. Reliable parcels and streams are unaffected.
+	///
+	/// Both ends must agree on the mode, as raw datagrams are indistinguishable from volatile
+	/// parcels on the wire. Disabled by default.
+	#[inline]
+	pub fn set_raw_mode(&mut self, is_enabled: bool) {
+		self.is_raw_mode = is_enabled;
+	}
+
+	/// Move all received raw datagrams into provided vector, in the order they were received.
+	///
+	/// Only [raw mode](Self::set_raw_mode) connections receive raw datagrams. The queue is
+	/// limited by the [parcel queue limit](Self::set_max_received_parcels), further raw datagrams
+	/// are dropped and [counted](Self::dropped_parcel_count).
+	///
+	/// The datagrams are allocated from the [buffer pool](Self::set_buffer_pool), the
+	/// application may release them to it once processed.
+	///
+	/// # Returns
+	/// Number of moved datagrams.
+	pub fn recv_raw_datagrams(&mut self, out: &mut Vec<Vec<u8>>) -> usize {
+		let datagram_count = self.received_raw_datagrams.len();
+		out.extend(self.received_raw_datagrams.drain(..));
+		datagram_count
+	}
+
+	/// Copy the next received raw datagram into provided buffer.
+	///
+	/// Unlike [`recv_raw_datagrams`](Self::recv_raw_datagrams) no buffers are handed out, the
+	/// one holding the datagram is released back to the [buffer pool](Self::set_buffer_pool).
+	/// Along with a recycling pool, receiving raw datagrams performs no allocations once the pool
+	/// is warmed up.
+	///
+	/// # Returns
+	/// Byte count of the datagram, or:
+	/// - [`NoPendingParcels`](ConnectionError::NoPendingParcels) if no raw datagrams are queued.
+	/// - [`BufferOverflow`](SerializationError::BufferOverflow) if the buffer is too small to hold
+	///   the datagram, which stays queued.
+	pub fn pop_raw_datagram_into(&mut self, buffer: &mut [u8]) -> Result<usize, ConnectionError> {
+		let len = self.received_raw_datagrams.front().ok_or(ConnectionError::NoPendingParcels)?.len();
+		if buffer.len() < len {
+			return Err(SerializationError::BufferOverflow.into())
+		}
+		let datagram = self.received_raw_datagrams.pop_front().unwrap();
+		buffer[.. len].copy_from_slice(&datagram);
+		self.buffer_pool.release(datagram);
+		Ok(len)
+	}
+
+	/// Attempt to read data from the connection stream into the provided buffer.
+	///
+	/// # Returns
+	/// Number of bytes read.
+	///
+	/// # Streams
+	/// Connection streams offer
+	/// [TCP](https://en.wikipedia.org/wiki/Transmission_Control_Protocol)-like functionality for
+	/// contiguous streams of data. Streams are transmitted with the same network packets as
+	/// reliable parcels, reducing overall data duplication for lost packets.
+	///
+	/// Only the contiguous prefix of the stream that has been received so far is read, see
+	/// [`pending_incoming_stream_bytes`](Self::pending_incoming_stream_bytes). Data received past
+	/// a gap left by a lost packet is held back until the gap is filled, the call never waits for
+	/// it.
+	///
+	/// # Note
+	/// Has consuming behavior, meaning repeated invocations will read exhaust internal stream
+	/// buffer.
+	pub fn read_from_stream(&mut self, buffer: &mut [u8]) -> Result<usize, ConnectionError> {
+		let byte_count = buffer.len().min(self.incoming_stream.len());
+		for (target, byte) in buffer.iter_mut().zip(self.incoming_stream.drain(.. byte_count)) {
+			*target = byte;
+		}
+		Ok(byte_count)
+	}
+
+	/// Get the number of contiguous stream bytes that can be read right away.
+	///
+	/// Does not include data received past a gap in the stream.
+	#[inline]
+	pub fn pending_incoming_stream_bytes(&self) -> usize {
+		self.incoming_stream.len()
+	}
+
+	/// Get the number of queued bytes yet to be written into built packets: serialized reliable
+	/// and volatile parcels, and stream data.
+	///
+	/// Excludes packet headers and packets in flight awaiting acknowledgement. Allows deciding
+	/// whether to build packets right away, as close to a full packet is queued, or to wait for
+	/// more data to coalesce.
+	pub fn pending_outgoing_bytes(&self) -> usize {
+		let parcel_byte_count: usize = self.reliable_parcels.iter()
+			.chain(&self.volatile_parcels)
+			.map(|parcel| parcel.bytes.len())
+			.sum();
+		parcel_byte_count + self.outgoing_stream.len()
+	}
+
+	/// Write a given slice of bytes to the connection stream.
+	///
+	/// # Streams
+	/// Connection streams offer
+	/// [TCP](https://en.wikipedia.org/wiki/Transmission_Control_Protocol)-like functionality
+	/// for contiguous streams of data. Streams are transmitted with the same network packets
+	/// as reliable parcels, reducing overall data duplication for lost packets.
+	pub fn write_bytes_to_stream(&mut self, bytes: &[u8]) -> Result<(), ConnectionError> {
+		self.check_can_queue(false)?;
+		self.outgoing_stream.extend(bytes);
+		Ok(())
+	}
+
+	/// Build the next packet that should be sent for this connection.
+	///
+	/// Packets are built in order of priority: re-transmissions of lost packets, new synchronized
+	/// packets, volatile packets and finally keep-alive packets. Every packet carries the current
+	/// acknowledgements, and pending acknowledgements are sent in a keep-alive packet if the
+	/// in-flight window is full, so acknowledgements are never held back by queued data.
+	///
+	/// Acknowledgements piggyback on data packets: a standalone keep-alive packet is only built
+	/// for due acknowledgements when no data packet can be built instead, that is when there is
+	/// no queued data, the in-flight window is full or data is held back by
+	/// [pacing](Self::set_pacing). Building a data packet clears the pending acknowledgements, so
+	/// no standalone acknowledgement follows it.
+	///
+	/// # Returns
+	/// Number of bytes of the built packet. `0` if no packet needs to be sent at this time.
+	///
+	/// # Notes
+	/// - The connection must be in [`Open`](ConnectionStatus::Open) state!
+	/// - The size of provided buffer, as well as the
+	///   [fixed maximum datagram length](Self::set_max_datagram_length) and the
+	///   [maximum datagram length of the other end](Self::remote_max_datagram_length), limits the
+	///   size of the built packet. It must be able to hold the largest possible parcel
+	///   ([`Parcel::MAX_BYTE_COUNT`](Parcel::MAX_BYTE_COUNT)).
+	/// - Re-transmitted packets keep their length, so provided buffer must also be able to hold
+	///   the packets in flight, even if the limits were lowered since they were built.
+	pub fn build_packet(&mut self, buffer: &mut [u8]) -> Result<usize, BuildPacketError> {
+		if self.status != ConnectionStatus::Open {
+			return Err(BuildPacketError::InvalidState)
+		}
+		let limited_length = self.limit_buffer(buffer).len();
+		if limited_length < size_of::<PacketHeader>() + P::MAX_BYTE_COUNT {
+			return Err(BuildPacketError::InsufficientBuffer)
+		}
+
+		let now = self.clock.now();
+		self.drop_expired_parcels(now);
+		let is_paced = self.is_paced(now);
+		let lost_packet_index = if is_paced { None } else { self.find_lost_packet(now) };
+		let byte_count = if let Some(index) = lost_packet_index {
+			// Lost packets keep their length, even if the limits were lowered since they were built.
+			self.build_retransmitted_packet(buffer, index, now)?
+		} else if !is_paced && self.can_send_synchronized() {
+			self.build_synchronized_packet(&mut buffer[.. limited_length], now)?
+		} else if !is_paced && !self.volatile_parcels.is_empty() {
+			self.last_data_time = now;
+			self.build_volatile_packet(&mut buffer[.. limited_length], now)?
+		} else if self.ack_deadline().is_some_and(|deadline| now >= deadline) || now >= self.keep_alive_deadline() {
+			packet::write_header(buffer, self.header(SignalBits::keep_alive()));
+			size_of::<PacketHeader>()
+		} else {
+			return Ok(0)
+		};
+
+		if byte_count > size_of::<PacketHeader>() {
+			self.next_paced_time = now + self.pacing_interval();
+			if self.send_rate.is_some() {
+				self.send_budget -= byte_count as f64;
+			}
+		}
+		self.mark_packet_sent(now);
+		self.pending_ack_count = 0;
+		Ok(self.finish_packet(&mut buffer[.. limited_length.max(byte_count)], byte_count))
+	}
+
+	/// Re-transmit all unacknowledged synchronized packets without waiting for them to be deemed
+	/// lost.
+	///
+	/// Useful for fast recovery after a known network interruption, rather than waiting for the
+	/// [retransmission timeout](Self::current_rto) of each packet. The packets are built by the
+	/// following calls to [`build_packet`](Self::build_packet), before any new data, and their
+	/// retransmission timers are reset once built. The packets are already in flight, so they are
+	/// not limited by the in-flight window. Forced re-transmissions are not reported as
+	/// [lost packets](ConnectionEvent::PacketLost).
+	///
+	/// # Returns
+	/// Number of packets that will be re-transmitted.
+	pub fn resend_unacked(&mut self) -> usize {
+		for packet in self.sent_packets.iter_mut() {
+			packet.is_resend_forced = true;
+		}
+		self.sent_packets.len()
+	}
+
+	/// Measure the round-trip time to the other end right away.
+	///
+	/// The next [built](Self::build_packet) synchronized packet carries the ping, an empty one if
+	/// there is no data to send. Once it is acknowledged, its round-trip time is reported with
+	/// [`PingResult`](ConnectionEvent::PingResult) by [`poll`](Self::poll), without waiting for
+	/// data to be exchanged. The measurement includes any
+	/// [acknowledgement delay](Self::set_ack_delay) of the other end, and is taken from the latest
+	/// transmission if the ping was re-transmitted. Repeated calls before the ping is sent request
+	/// a single ping.
+	///
+	/// # Notes
+	/// - The connection must be in [`Open`](ConnectionStatus::Open) state!
+	/// - The ping waits for the in-flight window like any synchronized packet.
+	pub fn ping(&mut self) -> Result<(), ConnectionError> {
+		if self.status != ConnectionStatus::Open {
+			return Err(ConnectionError::InvalidState)
+		}
+		self.is_ping_requested = true;
+		Ok(())
+	}
+
+	/// Build a control packet carrying the current acknowledgements, without any data.
+	///
+	/// Unlike [`build_packet`](Self::build_packet) the packet is always built, regardless of
+	/// queued data and the in-flight window, as it does not need to be acknowledged. Allows
+	/// acknowledging received packets immediately, for example when outgoing data is rate-limited.
+	///
+	/// # Notes
+	/// - The connection must be in [`Open`](ConnectionStatus::Open) state!
+	/// - Provided buffer must be able to hold a packet header.
+	pub fn build_ack_packet(&mut self, buffer: &mut [u8]) -> Result<usize, BuildPacketError> {
+		if self.status != ConnectionStatus::Open {
+			return Err(BuildPacketError::InvalidState)
+		}
+		let buffer = self.limit_buffer(buffer);
+		if buffer.len() < size_of::<PacketHeader>() {
+			return Err(BuildPacketError::InsufficientBuffer)
+		}
+
+		packet::write_header(buffer, self.header(SignalBits::keep_alive()));
+		self.mark_packet_sent(self.clock.now());
+		self.pending_ack_count = 0;
+		Ok(self.finish_packet(buffer, size_of::<PacketHeader>()))
+	}
+
+	/// Build a volatile packet carrying provided payload as a raw datagram, along with the current
+	/// acknowledgements.
+	///
+	/// The other end must be in [raw mode](Self::set_raw_mode) to receive the payload as is. Like
+	/// volatile parcels, raw datagrams are neither re-transmitted nor ordered.
+	///
+	/// # Notes
+	/// - The connection must be in [`Open`](ConnectionStatus::Open) state!
+	/// - Provided buffer must be able to hold a packet header and the payload, which may not
+	///   exceed [`MAX_SEGMENT_BYTE_COUNT`](MAX_SEGMENT_BYTE_COUNT) bytes.
+	pub fn build_raw_packet(&mut self, buffer: &mut [u8], payload: &[u8]) -> Result<usize, BuildPacketError> {
+		if self.status != ConnectionStatus::Open {
+			return Err(BuildPacketError::InvalidState)
+		}
+		let buffer = self.limit_buffer(buffer);
+		if payload.len() > MAX_SEGMENT_BYTE_COUNT || buffer.len() < size_of::<PacketHeader>() + payload.len() {
+			return Err(BuildPacketError::InsufficientBuffer)
+		}
+
+		packet::write_header(buffer, self.header(SignalBits::volatile(payload.len() as u16)));
+		packet::write_data(buffer, payload, 0)?;
+		let byte_count = self.compress_packet(buffer, size_of::<PacketHeader>() + payload.len());
+		let now = self.clock.now();
+		self.last_data_time = now;
+		self.mark_packet_sent(now);
+		self.pending_ack_count = 0;
+		Ok(self.finish_packet(buffer, byte_count))
+	}
+
+	/// Close the connection, building a packet informing the other end.
+	///
+	/// The connection becomes [`Closed`](ConnectionStatus::Closed) and no more packets are built.
+	/// Parcels and stream data that were already received may still be read. The close packet is
+	/// not acknowledged, if it is lost the other end deems the connection
+	/// [lost](ConnectionStatus::Lost) once it times out.
+	///
+	/// Same as [`close_with_reason`](Self::close_with_reason) with
+	/// [`GracefulShutdown`](CloseReason::GracefulShutdown) reason and no payload.
+	///
+	/// # Notes
+	/// - The connection must be in [`Open`](ConnectionStatus::Open) state!
+	/// - Provided buffer must be able to hold a packet header and the reason code.
+	#[inline]
+	pub fn close(&mut self, buffer: &mut [u8]) -> Result<usize, BuildPacketError> {
+		self.close_with_reason(buffer, CloseReason::GracefulShutdown, &[])
+	}
+
+	/// Close the connection for provided reason, building a packet informing the other end.
+	///
+	/// The reason and payload are delivered to the other end, see
+	/// [`close_reason`](Self::close_reason) and [`close_payload`](Self::close_payload).
+	///
+	/// # Notes
+	/// - The connection must be in [`Open`](ConnectionStatus::Open) state!
+	/// - Provided buffer must be able to hold a packet header, the reason code and the payload.
+	pub fn close_with_reason(&mut self, buffer: &mut [u8], reason: CloseReason, payload: &[u8]) -> Result<usize, BuildPacketError> {
+		if self.status != ConnectionStatus::Open {
+			return Err(BuildPacketError::InvalidState)
+		}
+		let buffer = self.limit_buffer(buffer);
+		let byte_count = write_close_packet(buffer, self.header(SignalBits::close(0)), reason, payload)?;
+		self.mark_packet_sent(self.clock.now());
+		self.status = ConnectionStatus::Closed;
+		self.close_reason = Some(reason);
+		self.close_payload = payload.to_vec();
+		Ok(self.finish_packet(buffer, byte_count))
+	}
+
+	/// Build a connection-requesting packet that contains provided payload.
+	///
+	/// # Notes
+	/// - The connection must be in [`Pending`](ConnectionStatus::Pending) state!
+	/// - Provided buffer must be able to hold the largest possible parcel
+	///   ([`Parcel::MAX_BYTE_COUNT`](Parcel::MAX_BYTE_COUNT)), as the same buffers are expected
+	///   to be used once the connection is established.
+	/// - Once the [maximum](Self::set_max_request_count) number of requests was sent, the
+	///   connection is instead deemed [lost](ConnectionStatus::Lost).
+	pub fn build_request_packet(&mut self, buffer: &mut [u8], payload: &[u8]) -> Result<usize, BuildPacketError> {
+		self.check_request_count();
+		if self.status != ConnectionStatus::Pending {
+			return Err(BuildPacketError::InvalidState)
+		}
+		if payload.len() > MAX_SEGMENT_BYTE_COUNT {
+			return Err(BuildPacketError::Serialization(SerializationError::BufferOverflow))
+		}
+		let byte_count = size_of::<PacketHeader>() + payload.len();
+		if buffer.len() < byte_count.max(size_of::<PacketHeader>() + P::MAX_BYTE_COUNT) {
+			return Err(BuildPacketError::InsufficientBuffer)
+		}
+
+		packet::write_header(buffer, PacketHeader::request_connection(self.handshake_id, payload.len() as u16));
+		packet::write_data(buffer, payload, 0)?;
+		let now = self.clock.now();
+		let (initial, max) = self.request_backoff;
+		let interval = initial.checked_mul(1 << self.request_count.min(31)).map_or(max, |interval| interval.min(max));
+		self.request_retry_time = now + interval.mul_f64(1.0 - random::<f64>() / 4.0);
+		self.request_count = self.request_count.saturating_add(1);
+		self.last_sent_packet_time = now;
+		self.count_sent_packet(byte_count);
+		Ok(byte_count)
+	}
+
+	/// Process a packet received from the other end of the connection.
+	///
+	/// A [`Pending`](ConnectionStatus::Pending) connection expects an answer to its request,
+	/// which either opens or closes the connection. An [`Open`](ConnectionStatus::Open) connection
+	/// processes acknowledgements and queues any received parcels and stream data.
+	///
+	/// # Lost accept packets
+	/// If the accept packet of the other end is lost, its following packets may arrive while the
+	/// connection is still pending. A pending connection takes such a packet of an established
+	/// connection as implicit acceptance: it opens with the connection id of the packet and
+	/// processes it as usual, instead of waiting for the re-sent request to be answered. Only
+	/// packets whose id follows the starting packet id derived from the handshake id are taken,
+	/// others fail with [`UnexpectedPacket`](ProcessPacketError::UnexpectedPacket). The other end
+	/// sends such packets only once it accepted the request, so the packet should only be passed
+	/// from the address the request was sent to, as
+	/// [`ClientConnection`](super::client::ClientConnection) does.
+	pub fn process_packet(&mut self, packet: &[u8]) -> Result<(), ProcessPacketError> {
+		let now = self.clock.now();
+		self.process_packet_received_at(packet, now)
+	}
+
+	/// Process a packet received from the other end of the connection at provided moment.
+	///
+	/// Same as [`process_packet`](Self::process_packet), but parcels of the packet are
+	/// [timestamped](Self::pop_parcel_timestamped) with provided moment, for example a kernel
+	/// receive timestamp (see
+	/// [`Transmit::try_recv_from_timestamped`](crate::endpoint::Transmit::try_recv_from_timestamped)).
+	/// Connection timers are not affected by the moment.
+	pub fn process_packet_received_at(&mut self, packet: &[u8], received_at: Instant) -> Result<(), ProcessPacketError> {
+		let result = match self.status {
+			ConnectionStatus::Pending => if packet::is_valid_connectionless(packet) {
+				self.process_handshake_packet(packet)
+			} else {
+				self.process_early_packet(packet, received_at)
+			},
+			ConnectionStatus::Open => self.process_connected_packet(packet, received_at),
+			ConnectionStatus::Lost | ConnectionStatus::Closed => Err(ProcessPacketError::InvalidState),
+		};
+		match result {
+			Ok(()) => {
+				stats::add(&self.counters.received_packets, 1);
+				stats::add(&self.counters.received_bytes, packet.len() as u64);
+			},
+			Err(ProcessPacketError::MalformedPacket) => stats::add(&self.counters.malformed_packets, 1),
+			Err(_) => {},
+		}
+		result
+	}
+}
+
+impl<P: Parcel> Context<P> {
+	/// Queue provided volatile parcel, expiring after provided time-to-live, if any.
+	fn push_volatile(&mut self, parcel: P, ttl: Option<Duration>) -> Result<(), ConnectionError> {
+		self.check_can_queue(false)?;
+		let bytes = self.serialize_parcel(&parcel)?;
+		let queued_at = self.clock.now();
+		let expires_at = ttl.map(|ttl| queued_at + ttl);
+		self.volatile_parcels.push_back(OutgoingParcel { bytes, parcel_count: 1, prelude: self.outgoing_prelude, queued_at, expires_at });
+		Ok(())
+	}
+
+	/// Drop queued volatile parcels whose time-to-live expired at provided moment.
+	fn drop_expired_parcels(&mut self, now: Instant) {
+		let buffer_pool = &self.buffer_pool;
+		let counters = &self.counters;
+		self.volatile_parcels.retain_mut(|parcel| {
+			let is_expired = parcel.expires_at.is_some_and(|expires_at| now > expires_at);
+			if is_expired {
+				stats::add(&counters.expired_parcels, 1);
+				buffer_pool.release(mem::take(&mut parcel.bytes));
+			}
+			!is_expired
+		});
+	}
+
+	/// Serialize provided parcel into a separate buffer, ready to be written into a packet.
+	///
+	/// Fails unless the connection is [pending](ConnectionStatus::Pending) or
+	/// [open](ConnectionStatus::Open).
+	fn serialize_parcel(&self, parcel: &P) -> Result<Vec<u8>, ConnectionError> {
+		let mut bytes = self.buffer_pool.acquire(P::TYPE_ID.map_or(0, |type_id| type_id.byte_count()) + parcel.byte_count());
+		if let Err(error) = self.serialize_parcel_into(parcel, &mut bytes) {
+			self.buffer_pool.release(bytes);
+			return Err(error)
+		}
+		Ok(bytes)
+	}
+
+	/// Check that data may be queued, which requires the connection to be
+	/// [open](ConnectionStatus::Open), or [pending](ConnectionStatus::Pending) if allowed.
+	fn check_can_queue(&self, is_pending_allowed: bool) -> Result<(), ConnectionError> {
+		match self.status {
+			ConnectionStatus::Open => Ok(()),
+			ConnectionStatus::Pending if is_pending_allowed => Ok(()),
+			ConnectionStatus::Lost => Err(ConnectionError::ConnectionLost),
+			_ => Err(ConnectionError::InvalidState),
+		}
+	}
+
+	/// Serialize provided parcel, appending it to provided buffer.
+	///
+	/// Fails unless the connection is [pending](ConnectionStatus::Pending) or
+	/// [open](ConnectionStatus::Open), leaving the buffer as is.
+	fn serialize_parcel_into(&self, parcel: &P, bytes: &mut Vec<u8>) -> Result<(), ConnectionError> {
+		self.check_can_queue(true)?;
+		let tag_byte_count = P::TYPE_ID.map_or(0, |type_id| type_id.byte_count());
+		let byte_count = tag_byte_count + parcel.byte_count();
+		if byte_count > P::MAX_BYTE_COUNT {
+			return Err(ConnectionError::ParcelTooLarge)
+		}
+		let offset = bytes.len();
+		bytes.resize(offset + byte_count, 0);
+		if let Some(type_id) = P::TYPE_ID {
+			type_id.to_bytes(&mut bytes[offset ..]);
+		}
+		parcel.to_bytes(&mut bytes[offset + tag_byte_count ..]);
+		Ok(())
+	}
+
+	/// Construct a header for an outgoing packet carrying the current acknowledgement state.
+	#[inline]
+	fn header(&self, signal: SignalBits) -> PacketHeader {
+		PacketHeader {
+			connection_id: self.connection_id,
+			packet_id: 0.into(),
+			ack_packet_id: self.received_packet_ack_id,
+			receive_window: self.receive_window(),
+			timestamp: self.timestamp(self.clock.now()),
+			ack_packet_mask: self.received_packet_ack_mask,
+			signal,
+			prelude: [0; 4],
+		}
+	}
+
+	/// Get the timestamp of a packet sent at provided moment.
+	#[inline]
+	fn timestamp(&self, now: Instant) -> u16 {
+		// Truncation wraps the timestamp around.
+		now.saturating_duration_since(self.epoch).as_millis() as u16
+	}
+
+	/// Get the number of further parcels this end is able to receive, advertised to the other end.
+	///
+	/// [`u16::MAX`] stands for no limit, so a limited window is clamped below it.
+	fn receive_window(&self) -> u16 {
+		match self.max_received_parcels {
+			Some(max) => max.saturating_sub(self.received_parcels.len()).min(u16::MAX as usize - 1) as u16,
+			None => u16::MAX,
+		}
+	}
+
+	/// Get the number of further reliable parcels that may be sent without overflowing the
+	/// receive window of the other end.
+	fn send_window(&self) -> usize {
+		if self.remote_receive_window == u16::MAX {
+			return usize::MAX
+		}
+		let in_flight_count: usize = self.sent_packets.iter().map(|packet| packet.parcel_count).sum();
+		(self.remote_receive_window as usize).saturating_sub(in_flight_count)
+	}
+
+	/// Record a packet of an open connection as sent at provided moment.
+	#[inline]
+	fn mark_packet_sent(&mut self, now: Instant) {
+		self.last_sent_packet_time = now;
+		// Keep-alives stay spread once the first packet was sent.
+		self.keep_alive_phase = Duration::ZERO;
+	}
+
+	/// Get the moment a keep-alive is due to be sent, unless other packets are sent first.
+	#[inline]
+	fn keep_alive_deadline(&self) -> Instant {
+		self.last_sent_packet_time + KEEP_ALIVE_INTERVAL - self.keep_alive_phase
+	}
+
+	/// Get the moment pending acknowledgements are due to be sent, `None` if there are none.
+	fn ack_deadline(&self) -> Option<Instant> {
+		if self.pending_ack_count == 0 {
+			None
+		} else if self.pending_ack_count >= self.ack_frequency {
+			Some(self.pending_ack_time)
+		} else {
+			Some(self.pending_ack_time + self.max_ack_delay)
+		}
+	}
+
+	/// Check whether a packet is ready to be built immediately.
+	fn has_ready_packet(&self) -> bool {
+		let now = self.clock.now();
+		self.ack_deadline().is_some_and(|deadline| deadline <= now) || (self.has_ready_data() && !self.is_paced(now))
+	}
+
+	/// Check whether there is data to send, regardless of pacing.
+	fn has_ready_data(&self) -> bool {
+		!self.volatile_parcels.is_empty()
+			|| self.can_send_synchronized()
+			|| self.sent_packets.iter().any(|packet| packet.is_resend_forced || self.is_lost_by_threshold(packet))
+	}
+
+	/// Give up on a pending connection whose last allowed request is due to be re-sent.
+	fn check_request_count(&mut self) {
+		let is_exhausted = self.max_request_count.is_some_and(|max| self.request_count >= max);
+		if self.status == ConnectionStatus::Pending && is_exhausted && self.clock.now() >= self.request_retry_time {
+			self.mark_lost(LossReason::TooManyRequests);
+		}
+	}
+
+	/// Check whether data packets are held back by [pacing](Self::set_pacing) at provided moment
+	/// or by an overdrawn [send budget](Self::set_send_rate).
+	#[inline]
+	fn is_paced(&self, now: Instant) -> bool {
+		(self.is_pacing_enabled && now < self.next_paced_time) || self.send_budget < 0.0
+	}
+
+	/// Get the earliest moment the next data packet may be sent, considering both pacing and the
+	/// send budget, once refilled.
+	fn next_send_time(&self) -> Instant {
+		let now = self.clock.now();
+		let paced_time = if self.is_pacing_enabled { self.next_paced_time } else { now };
+		match self.send_rate {
+			Some(rate) if self.send_budget < 0.0 => {
+				let refill_time = self.send_budget_time + Duration::from_secs_f64(-self.send_budget / rate as f64);
+				paced_time.max(refill_time)
+			},
+			_ => paced_time,
+		}
+	}
+
+	/// Get the byte count the send budget may hold at provided rate.
+	fn max_send_budget(rate: u64) -> f64 {
+		rate as f64 * SEND_BUDGET_BURST.as_secs_f64()
+	}
+
+	/// Refill the [send budget](Self::set_send_rate) for the time elapsed since the last refill.
+	fn refill_send_budget(&mut self) {
+		let now = self.clock.now();
+		if let Some(rate) = self.send_rate {
+			let elapsed = now.saturating_duration_since(self.send_budget_time);
+			self.send_budget = (self.send_budget + elapsed.as_secs_f64() * rate as f64).min(Self::max_send_budget(rate));
+		}
+		self.send_budget_time = now;
+	}
+
+	/// Get the number of synchronized packets allowed in flight, limited by the congestion window
+	/// when congestion control is enabled.
+	#[inline]
+	fn in_flight_window(&self) -> usize {
+		self.congestion_window().unwrap_or(MAX_IN_FLIGHT_PACKETS as usize)
+	}
+
+	/// Get the number of reliable parcels the next synchronized packet may carry.
+	///
+	/// A [batch](Self::push_reliable_batch) is never split, so one larger than the limits is
+	/// still sent on its own, though only once no other reliable parcels are in flight, so that
+	/// it overflows the receive window of the other end by no more than itself.
+	fn max_reliable_parcel_count(&self) -> usize {
+		let batch_count = self.reliable_parcels.front().map_or(0, |parcel| parcel.parcel_count);
+		let mut send_window = self.send_window();
+		if send_window > 0 && self.sent_packets.iter().all(|packet| packet.parcel_count == 0) {
+			send_window = send_window.max(batch_count);
+		}
+		let max_per_packet = self.max_parcels_per_packet.map_or(usize::MAX, |max| max.max(batch_count));
+		send_window.min(max_per_packet)
+	}
+
+	/// Check whether there is synchronized data to send and the in-flight window permits it.
+	fn can_send_synchronized(&self) -> bool {
+		let has_parcels = self.reliable_parcels.front().is_some_and(|parcel| parcel.parcel_count <= self.max_reliable_parcel_count());
+		let has_data = has_parcels || !self.outgoing_stream.is_empty() || self.is_ping_requested;
+		let is_window_open = match self.sent_packets.front() {
+			Some(oldest) => PacketIndex::distance(self.next_packet_id, oldest.packet_id) < MAX_IN_FLIGHT_PACKETS,
+			None => true,
+		};
+		let is_congestion_window_open = self.congestion_control.is_none()
+			|| (self.sent_packets.len() as f64) < self.congestion_window.floor();
+		has_data && is_window_open && is_congestion_window_open
+	}
+
+	/// Check whether enough subsequent packets have been acknowledged to deem provided one lost.
+	#[inline]
+	fn is_lost_by_threshold(&self, packet: &SentPacket) -> bool {
+		let distance = PacketIndex::distance(self.acknowledged_packet_id, packet.packet_id);
+		packet.retransmission_count == 0 && (LOSS_PACKET_THRESHOLD .. u8::MAX / 2).contains(&distance)
+	}
+
+	/// Find the index of the first sent packet that is deemed lost.
+	fn find_lost_packet(&self, now: Instant) -> Option<usize> {
+		let rto = self.current_rto();
+		self.sent_packets.iter().position(|packet| {
+			packet.is_resend_forced || now >= packet.sent_time + rto || self.is_lost_by_threshold(packet)
+		})
+	}
+
+	/// Copy a lost packet into provided buffer, refreshing its acknowledgement state.
+	fn build_retransmitted_packet(&mut self, buffer: &mut [u8], index: usize, now: Instant) -> Result<usize, BuildPacketError> {
+		let (ack_packet_id, ack_packet_mask) = (self.received_packet_ack_id, self.received_packet_ack_mask);
+		let (receive_window, timestamp) = (self.receive_window(), self.timestamp(now));
+		let sent_packet = &mut self.sent_packets[index];
+		let byte_count = sent_packet.bytes.len();
+		if buffer.len() < byte_count {
+			return Err(BuildPacketError::InsufficientBuffer)
+		}
+
+		buffer[.. byte_count].copy_from_slice(&sent_packet.bytes);
+		let mut header = packet::get_header(buffer);
+		header.ack_packet_id = ack_packet_id;
+		header.ack_packet_mask = ack_packet_mask;
+		header.receive_window = receive_window;
+		header.timestamp = timestamp;
+		packet::write_header(buffer, header);
+		// The copy follows the latest transmission, to recognize it if looped back.
+		packet::write_header(&mut sent_packet.bytes, header);
+
+		let lost_sent_time = mem::replace(&mut sent_packet.sent_time, now);
+		sent_packet.retransmission_count += 1;
+		stats::add(&self.counters.retransmitted_packets, 1);
+		if sent_packet.is_resend_forced {
+			sent_packet.is_resend_forced = false;
+			return Ok(byte_count)
+		}
+		let packet_id = sent_packet.packet_id;
+		if byte_count > BLACK_HOLE_DATAGRAM_LENGTH {
+			self.on_long_packet_lost();
+		}
+		self.loss_rate += (1.0 - self.loss_rate) / 16.0;
+		self.reduce_congestion_window(lost_sent_time, now);
+		stats::add(&self.counters.lost_packets, 1);
+		// Only the latest losses are kept if the events are not polled.
+		if self.lost_packets.len() == MAX_IN_FLIGHT_PACKETS as usize {
+			self.lost_packets.pop_front();
+		}
+		self.lost_packets.push_back(packet_id);
+		Ok(byte_count)
+	}
+
+	/// Build a new synchronized packet out of queued reliable parcels and stream data.
+	fn build_synchronized_packet(&mut self, buffer: &mut [u8], now: Instant) -> Result<usize, BuildPacketError> {
+		let capacity = buffer.len() - size_of::<PacketHeader>();
+		let max_parcel_count = self.max_reliable_parcel_count();
+		let (buffer_pool, queue_latency) = (&*self.buffer_pool, &mut self.queue_latency);
+		let mut parcel_count = 0;
+		let (parcel_byte_count, prelude) = write_parcels(buffer, &mut self.reliable_parcels, capacity, max_parcel_count, |parcel| {
+			parcel_count += parcel.parcel_count;
+			recycle_parcel(parcel, now, buffer_pool, queue_latency)
+		})?;
+
+		let stream_byte_count = self.outgoing_stream.len()
+			.min(capacity - parcel_byte_count)
+			.min(MAX_SEGMENT_BYTE_COUNT);
+		let stream_segment = &mut packet::get_mut_data_segment(buffer)[parcel_byte_count ..];
+		for (target, byte) in stream_segment.iter_mut().zip(self.outgoing_stream.drain(.. stream_byte_count)) {
+			*target = byte;
+		}
+
+		let packet_id = self.next_packet_id;
+		let header = PacketHeader {
+			packet_id,
+			prelude,
+			.. self.header(SignalBits::synchronized(parcel_byte_count as u16, stream_byte_count as u16))
+		};
+		packet::write_header(buffer, header);
+		let byte_count = self.compress_packet(buffer, size_of::<PacketHeader>() + parcel_byte_count + stream_byte_count);
+
+		self.next_packet_id = packet_id.next();
+		if parcel_byte_count + stream_byte_count > 0 {
+			self.last_data_time = now;
+		}
+		self.max_sent_packet_length = self.max_sent_packet_length.max(byte_count);
+		let mut bytes = self.buffer_pool.acquire(byte_count);
+		bytes.extend_from_slice(&buffer[.. byte_count]);
+		self.sent_packets.push_back(SentPacket {
+			packet_id,
+			sent_time: now,
+			retransmission_count: 0,
+			is_resend_forced: false,
+			is_ping: mem::take(&mut self.is_ping_requested),
+			parcel_count,
+			bytes,
+		});
+		Ok(byte_count)
+	}
+
+	/// Build a new volatile packet out of queued volatile parcels.
+	fn build_volatile_packet(&mut self, buffer: &mut [u8], now: Instant) -> Result<usize, BuildPacketError> {
+		let capacity = buffer.len() - size_of::<PacketHeader>();
+		let max_parcel_count = self.max_parcels_per_packet.unwrap_or(usize::MAX);
+		let (buffer_pool, queue_latency) = (&*self.buffer_pool, &mut self.queue_latency);
+		let (parcel_byte_count, prelude) = write_parcels(buffer, &mut self.volatile_parcels, capacity, max_parcel_count, |parcel| {
+			recycle_parcel(parcel, now, buffer_pool, queue_latency)
+		})?;
+		let header = PacketHeader {
+			prelude,
+			.. self.header(SignalBits::volatile(parcel_byte_count as u16))
+		};
+		packet::write_header(buffer, header);
+		Ok(self.compress_packet(buffer, size_of::<PacketHeader>() + parcel_byte_count))
+	}
+
+	/// Compress the data segment of a built packet if that makes the packet smaller.
+	///
+	/// Returns the byte count of the resulting packet.
+	fn compress_packet(&self, buffer: &mut [u8], byte_count: usize) -> usize {
+		let compressor = match &self.compressor {
+			Some(compressor) => compressor,
+			None => return byte_count,
+		};
+		let data = &buffer[size_of::<PacketHeader>() .. byte_count];
+		if data.is_empty() || data.len() < self.min_compressed_length {
+			return byte_count
+		}
+
+		let mut compressed = Vec::with_capacity(data.len());
+		compressor.compress(data, &mut compressed);
+		if compressed.len() >= data.len() {
+			return byte_count
+		}
+
+		if packet::write_data(buffer, &compressed, 0).is_err() {
+			return byte_count
+		}
+		let mut header = packet::get_header(buffer);
+		header.signal.set_signal(Signal::Compressed);
+		packet::write_header(buffer, header);
+		size_of::<PacketHeader>() + compressed.len()
+	}
+
+	/// Limit provided buffer to the fixed maximum datagram length and the maximum datagram
+	/// length of the other end.
+	fn limit_buffer<'a>(&self, buffer: &'a mut [u8]) -> &'a mut [u8] {
+		let length = self.max_datagram_length.into_iter()
+			.chain(self.remote_max_datagram_length)
+			.chain(self.black_hole_length)
+			.fold(buffer.len(), usize::min);
+		&mut buffer[.. length]
+	}
+
+	/// Pad the built packet of provided length with zeroes up to the configured padded length,
+	/// counting it as sent.
+	///
+	/// Returns the length of the padded packet.
+	fn finish_packet(&self, buffer: &mut [u8], byte_count: usize) -> usize {
+		let byte_count = match self.padded_length {
+			Some(padded_length) if padded_length > byte_count => {
+				let padded_length = padded_length.min(buffer.len());
+				buffer[byte_count .. padded_length].fill(0);
+				padded_length
+			},
+			_ => byte_count,
+		};
+		self.count_sent_packet(byte_count);
+		byte_count
+	}
+
+	/// Count a built packet of provided length as sent.
+	#[inline]
+	fn count_sent_packet(&self, byte_count: usize) {
+		stats::add(&self.counters.sent_packets, 1);
+		stats::add(&self.counters.sent_bytes, byte_count as u64);
+	}
+
+	/// Construct a decompressed copy of provided compressed packet.
+	fn decompress_packet(&self, packet: &[u8], mut header: PacketHeader) -> Result<Vec<u8>, ProcessPacketError> {
+		let compressor = self.compressor.as_ref().ok_or(ProcessPacketError::MalformedPacket)?;
+		let mut decompressed = vec![0; size_of::<PacketHeader>()];
+		compressor.decompress(packet::get_data_segment(packet), &mut decompressed)?;
+		header.signal.clear_signal(Signal::Compressed);
+		packet::write_header(&mut decompressed, header);
+		Ok(decompressed)
+	}
+
+	/// Process an answer to the connection request.
+	fn process_handshake_packet(&mut self, packet: &[u8]) -> Result<(), ProcessPacketError> {
+		if !packet::is_valid_connectionless(packet) {
+			return Err(ProcessPacketError::MalformedPacket)
+		}
+		let header = packet::get_header(packet);
+		if header.prelude != self.handshake_id {
+			return Err(ProcessPacketError::UnexpectedPacket)
+		}
+
+		if header.signal.is_signal_set(Signal::ConnectionAccepted) {
+			let payload = packet::get_parcel_segment(packet);
+			let (connection_id, offset) = ConnectionId::from_bytes(payload)?;
+			if connection_id == 0 {
+				return Err(ProcessPacketError::MalformedPacket)
+			}
+			// Listeners predating the announcement of the maximum datagram length omit it.
+			if payload.len() > offset {
+				let (max_datagram_length, byte_count) = u16::from_bytes(&payload[offset ..])?;
+				self.remote_max_datagram_length = Some(max_datagram_length as usize);
+				self.accept_payload = payload[offset + byte_count ..].to_vec();
+			}
+			self.start_sequences(self.handshake_id, true);
+			self.connection_id = connection_id;
+			self.status = ConnectionStatus::Open;
+		} else if header.signal.is_signal_set(Signal::ConnectionClosed) {
+			self.process_close_packet(packet);
+		} else {
+			return Err(ProcessPacketError::UnexpectedPacket)
+		}
+		self.last_received_packet_time = self.clock.now();
+		Ok(())
+	}
+
+	/// Open a pending connection with the id of provided packet of an established connection,
+	/// received before the accept packet, and process it.
+	///
+	/// The packet id has to be within the in-flight window of the starting packet id the other
+	/// end derived from the handshake id, so that a stray packet of another connection, such as a
+	/// late one of an earlier session, is unlikely to open the connection.
+	fn process_early_packet(&mut self, packet: &[u8], received_at: Instant) -> Result<(), ProcessPacketError> {
+		if !packet::is_valid_connected(packet) {
+			return Err(ProcessPacketError::MalformedPacket)
+		}
+		let header = packet::get_header(packet);
+		if header.connection_id == 0 || header.signal.is_signal_set(Signal::ConnectionClosed) {
+			return Err(ProcessPacketError::UnexpectedPacket)
+		}
+		let remote_start_id = PacketIndex::from(self.handshake_id[1]);
+		if PacketIndex::distance(header.packet_id, remote_start_id) >= MAX_IN_FLIGHT_PACKETS {
+			return Err(ProcessPacketError::UnexpectedPacket)
+		}
+		self.start_sequences(self.handshake_id, true);
+		self.open(header.connection_id);
+		self.process_connected_packet(packet, received_at)
+	}
+
+	/// Close the connection for the reason carried by provided close or reject packet.
+	fn process_close_packet(&mut self, packet: &[u8]) {
+		let (reason, payload) = read_close_payload(packet);
+		self.status = ConnectionStatus::Closed;
+		self.close_reason = Some(reason);
+		self.close_payload = payload.to_vec();
+	}
+
+	/// Process a packet of an established connection.
+	fn process_connected_packet(&mut self, packet: &[u8], received_at: Instant) -> Result<(), ProcessPacketError> {
+		if !packet::is_valid_connected(packet) {
+			return Err(ProcessPacketError::MalformedPacket)
+		}
+		let header = packet::get_header(packet);
+		if header.connection_id != self.connection_id {
+			return Err(ProcessPacketError::UnexpectedPacket)
+		}
+		// Both ends share the connection id, so only the content tells own packets apart.
+		if header.signal.is_signal_set(Signal::Synchronized) && self.is_sent_packet(packet, header.packet_id) {
+			stats::add(&self.counters.looped_back_packets, 1);
+			return Err(ProcessPacketError::LoopedBackPacket)
+		}
+		if header.signal.is_signal_set(Signal::Compressed) {
+			let packet = self.decompress_packet(packet, header)?;
+			return self.process_connected_packet(&packet, received_at)
+		}
+
+		self.last_received_packet_time = self.clock.now();
+		self.remote_receive_window = header.receive_window;
+		self.update_jitter(header.timestamp, received_at);
+		self.process_acknowledgements(&header);
+		if header.signal.is_signal_set(Signal::ConnectionClosed) {
+			self.process_close_packet(packet);
+			return Ok(())
+		}
+
+		let is_queue_full = self.max_received_parcels.is_some_and(|max| self.received_parcels.len() >= max);
+		if header.signal.is_signal_set(Signal::Synchronized) {
+			// Parcels are refused without acknowledging the packet, the other end re-transmits it.
+			if is_queue_full && header.signal.get_parcel_byte_count() > 0 {
+				return Ok(())
+			}
+			if !self.can_buffer_stream_segment(header.packet_id, header.signal.get_stream_byte_count() as usize) {
+				return Ok(())
+			}
+		}
+
+		let parcel_segment = packet::get_parcel_segment(packet);
+		if !parcel_segment.is_empty() || !packet::get_stream_segment(packet).is_empty() {
+			self.last_data_time = self.last_received_packet_time;
+		}
+		if self.is_raw_mode && !header.signal.is_signal_set(Signal::Synchronized) {
+			if parcel_segment.is_empty() {
+				return Ok(())
+			}
+			if self.max_received_parcels.is_some_and(|max| self.received_raw_datagrams.len() >= max) {
+				stats::add(&self.counters.dropped_parcels, 1);
+			} else {
+				let mut datagram = self.buffer_pool.acquire(parcel_segment.len());
+				datagram.extend_from_slice(parcel_segment);
+				self.received_raw_datagrams.push_back(datagram);
+			}
+			return Ok(())
+		}
+
+		// The whole parcel segment is validated before the packet is acknowledged, so that a packet
+		// with an invalid parcel is refused as a whole, rather than losing the parcels past it.
+		let queued_count = self.received_parcels.len();
+		self.queue_received_parcels(parcel_segment, header.prelude, received_at)?;
+		if header.signal.is_signal_set(Signal::Synchronized) {
+			// Duplicates are acknowledged again, as the previous acknowledgement may have been lost.
+			if self.pending_ack_count == 0 {
+				self.pending_ack_time = self.last_received_packet_time;
+			}
+			self.pending_ack_count += 1;
+			if !self.register_received_packet(header.packet_id) {
+				stats::add(&self.counters.duplicate_packets, 1);
+				self.received_parcels.truncate(queued_count);
+				return Ok(())
+			}
+			self.process_stream_segment(header.packet_id, packet::get_stream_segment(packet));
+		}
+		if is_queue_full {
+			let dropped_count = self.received_parcels.len() - queued_count;
+			stats::add(&self.counters.dropped_parcels, dropped_count as u64);
+			self.received_parcels.truncate(queued_count);
+		}
+		Ok(())
+	}
+
+	/// Deserialize and queue all parcels of provided parcel segment.
+	///
+	/// Nothing is queued if any of the parcels is invalid.
+	fn queue_received_parcels(
+		&mut self,
+		mut parcel_segment: &[u8],
+		prelude: DataPrelude,
+		received_at: Instant,
+	) -> Result<(), ProcessPacketError> {
+		let queued_count = self.received_parcels.len();
+		while !parcel_segment.is_empty() {
+			match read_parcel::<P>(parcel_segment) {
+				Ok((parcel, byte_count)) => {
+					self.received_parcels.push_back((parcel, prelude, received_at));
+					parcel_segment = &parcel_segment[byte_count ..];
+				},
+				Err(error) => {
+					self.received_parcels.truncate(queued_count);
+					return Err(error)
+				},
+			}
+		}
+		Ok(())
+	}
+
+	/// Update the jitter with a packet of provided timestamp received at provided moment.
+	fn update_jitter(&mut self, timestamp: u16, received_at: Instant) {
+		if let Some((last_timestamp, last_received_at)) = self.last_arrival {
+			let sent_delta = timestamp.wrapping_sub(last_timestamp) as i16 as f64 / 1000.0;
+			let received_delta = match received_at.checked_duration_since(last_received_at) {
+				Some(delta) => delta.as_secs_f64(),
+				None => -last_received_at.duration_since(received_at).as_secs_f64(),
+			};
+			let difference = (received_delta - sent_delta).abs();
+			let jitter = self.jitter.unwrap_or(0.0);
+			self.jitter = Some(jitter + (difference - jitter) / 16.0);
+		}
+		self.last_arrival = Some((timestamp, received_at));
+	}
+
+	/// Drop all sent packets acknowledged by provided header.
+	///
+	/// The round-trip time is sampled from the latest acknowledged packet that was not
+	/// re-transmitted, as the acknowledgement of a re-transmitted packet is ambiguous.
+	fn process_acknowledgements(&mut self, header: &PacketHeader) {
+		if header.ack_packet_id > self.acknowledged_packet_id {
+			self.acknowledged_packet_id = header.ack_packet_id;
+		}
+		let mut latest_sent_time = None;
+		let mut acknowledged_count = 0;
+		let (long_packet_loss_count, is_short_packet_delivered) = (&mut self.long_packet_loss_count, &mut self.is_short_packet_delivered);
+		let now = self.clock.now();
+		let ping_results = &mut self.ping_results;
+		let delivered_packets = &mut self.delivered_packets;
+		let loss_rate = &mut self.loss_rate;
+		let buffer_pool = &self.buffer_pool;
+		self.sent_packets.retain_mut(|packet| {
+			let is_acknowledged = header.acknowledges(packet.packet_id);
+			if is_acknowledged {
+				*loss_rate -= *loss_rate / 16.0;
+				acknowledged_count += 1;
+				if packet.bytes.len() > BLACK_HOLE_DATAGRAM_LENGTH {
+					*long_packet_loss_count = 0;
+					*is_short_packet_delivered = false;
+				} else {
+					*is_short_packet_delivered = true;
+				}
+				if packet.is_ping {
+					// Only the latest results are kept if the events are not polled.
+					if ping_results.len() == MAX_IN_FLIGHT_PACKETS as usize {
+						ping_results.pop_front();
+					}
+					ping_results.push_back(now.saturating_duration_since(packet.sent_time));
+				}
+				if packet.retransmission_count == 0 {
+					latest_sent_time = latest_sent_time.max(Some(packet.sent_time));
+				}
+				// Only the latest deliveries are kept if the events are not polled.
+				if delivered_packets.len() == MAX_IN_FLIGHT_PACKETS as usize {
+					delivered_packets.pop_front();
+				}
+				delivered_packets.push_back(packet.packet_id);
+				buffer_pool.release(mem::take(&mut packet.bytes));
+			}
+			!is_acknowledged
+		});
+		if let Some(sent_time) = latest_sent_time {
+			let sample = now.saturating_duration_since(sent_time);
+			self.round_trip_time.add_sample(sample);
+			let lowest = self.round_trip_time_samples.map_or(sample, |(lowest, _)| lowest.min(sample));
+			self.round_trip_time_samples = Some((lowest, sample));
+		}
+		for _ in 0 .. acknowledged_count {
+			self.grow_congestion_window();
+		}
+	}
+
+	/// Count a lost packet longer than [`BLACK_HOLE_DATAGRAM_LENGTH`](BLACK_HOLE_DATAGRAM_LENGTH),
+	/// detecting a black hole once enough of them are lost while shorter ones are delivered.
+	fn on_long_packet_lost(&mut self) {
+		self.long_packet_loss_count += 1;
+		let is_detected = self.long_packet_loss_count >= BLACK_HOLE_LOSS_THRESHOLD && self.is_short_packet_delivered;
+		if is_detected && self.black_hole_length.is_none() {
+			self.black_hole_length = Some(BLACK_HOLE_DATAGRAM_LENGTH.max(size_of::<PacketHeader>() + P::MAX_BYTE_COUNT));
+			stats::add(&self.counters.black_holes, 1);
+		}
+	}
+
+	/// Grow the congestion window for a single acknowledged packet.
+	fn grow_congestion_window(&mut self) {
+		let queued_count = match self.congestion_control {
+			Some(CongestionControl::DelayBased) => self.queued_packet_estimate(),
+			_ => 0.0,
+		};
+		let growth = if self.is_slow_start() {
+			if queued_count > MAX_QUEUED_PACKETS {
+				self.slow_start_threshold = self.congestion_window;
+				0.0
+			} else {
+				1.0
+			}
+		} else if queued_count > MAX_QUEUED_PACKETS {
+			-1.0 / self.congestion_window
+		} else if queued_count >= MIN_QUEUED_PACKETS {
+			0.0
+		} else {
+			1.0 / self.congestion_window
+		};
+		self.congestion_window = (self.congestion_window + growth).clamp(1.0, MAX_IN_FLIGHT_PACKETS as f64);
+		if growth < 0.0 {
+			// A shrinking window must not fall back into slow start.
+			self.slow_start_threshold = self.slow_start_threshold.min(self.congestion_window);
+		}
+	}
+
+	/// Estimate the number of packets queued along the path from the round-trip time samples.
+	fn queued_packet_estimate(&self) -> f64 {
+		match self.round_trip_time_samples {
+			Some((lowest, latest)) if !latest.is_zero() => {
+				self.congestion_window * (1.0 - lowest.as_secs_f64() / latest.as_secs_f64())
+			},
+			_ => 0.0,
+		}
+	}
+
+	/// Halve the congestion window for a lost packet sent at provided moment, unless the window
+	/// was already reduced since.
+	fn reduce_congestion_window(&mut self, lost_sent_time: Instant, now: Instant) {
+		if self.congestion_reduction_time.is_some_and(|reduction_time| lost_sent_time <= reduction_time) {
+			return
+		}
+		self.congestion_window = (self.congestion_window / 2.0).max(1.0);
+		self.slow_start_threshold = self.congestion_window;
+		self.congestion_reduction_time = Some(now);
+	}
+
+	/// Simulate the other end acknowledging the sent packet with provided id, and no others.
+	#[cfg(test)]
+	pub(crate) fn inject_ack(&mut self, packet_id: PacketIndex) {
+		let header = PacketHeader {
+			ack_packet_id: packet_id,
+			ack_packet_mask: 0,
+			.. self.header(SignalBits::keep_alive())
+		};
+		self.process_acknowledgements(&header);
+	}
+
+	/// Check whether provided packet with provided id is the latest transmission of an
+	/// unacknowledged packet sent by this end, possibly padded.
+	fn is_sent_packet(&self, packet: &[u8], packet_id: PacketIndex) -> bool {
+		self.sent_packets.iter().any(|sent_packet| {
+			let byte_count = sent_packet.bytes.len();
+			sent_packet.packet_id == packet_id
+				&& packet.len() >= byte_count
+				&& packet[.. byte_count] == sent_packet.bytes[..]
+				&& packet[byte_count ..].iter().all(|&byte| byte == 0)
+		})
+	}
+
+	/// Record provided synchronized packet id as received.
+	///
+	/// Returns `false` if the packet has already been received.
+	fn register_received_packet(&mut self, packet_id: PacketIndex) -> bool {
+		if packet_id == self.received_packet_ack_id {
+			false
+		} else if packet_id > self.received_packet_ack_id {
+			let distance = PacketIndex::distance(packet_id, self.received_packet_ack_id) as u32;
+			let shifted_mask = self.received_packet_ack_mask.checked_shl(distance).unwrap_or(0);
+			let previous_ack_bit = 1u64.checked_shl(distance - 1).unwrap_or(0);
+			self.received_packet_ack_mask = shifted_mask | previous_ack_bit;
+			self.received_packet_ack_id = packet_id;
+			true
+		} else {
+			let distance = PacketIndex::distance(self.received_packet_ack_id, packet_id) as u32;
+			let packet_bit = 1u64.checked_shl(distance - 1).unwrap_or(0);
+			if packet_bit == 0 || self.received_packet_ack_mask & packet_bit == packet_bit {
+				false
+			} else {
+				self.received_packet_ack_mask |= packet_bit;
+				true
+			}
+		}
+	}
+
+	/// Check whether the stream segment of provided length, carried by the synchronized packet with
+	/// provided id, fits the [stream buffer](Self::set_max_stream_buffer_length).
+	fn can_buffer_stream_segment(&self, packet_id: PacketIndex, byte_count: usize) -> bool {
+		let max = match self.max_stream_buffer_length {
+			Some(max) if byte_count > 0 => max,
+			_ => return true,
+		};
+		if packet_id == self.next_stream_packet_id {
+			return self.incoming_stream.len() < max
+		}
+		let pending_byte_count: usize = self.pending_stream_segments.iter().map(|(_, segment)| segment.len()).sum();
+		self.incoming_stream.len() + pending_byte_count + byte_count <= max
+	}
+
+	/// Append the stream segment of a synchronized packet to the incoming stream, keeping the order.
+	fn process_stream_segment(&mut self, packet_id: PacketIndex, segment: &[u8]) {
+		if packet_id != self.next_stream_packet_id {
+			let mut bytes = self.buffer_pool.acquire(segment.len());
+			bytes.extend_from_slice(segment);
+			self.pending_stream_segments.push((packet_id, bytes));
+			return
+		}
+
+		let stream_len = self.incoming_stream.len();
+		self.incoming_stream.extend(segment);
+		self.next_stream_packet_id = packet_id.next();
+		while let Some(index) = self.pending_stream_segments
+			.iter()
+			.position(|(id, _)| *id == self.next_stream_packet_id)
+		{
+			let (_, segment) = self.pending_stream_segments.swap_remove(index);
+			self.incoming_stream.extend(&segment);
+			self.buffer_pool.release(segment);
+			self.next_stream_packet_id = self.next_stream_packet_id.next();
+		}
+		if self.incoming_stream.len() > stream_len {
+			self.is_stream_data_unreported = true;
+		}
+	}
+}
+
+/// Write a close or reject packet with provided header, carrying provided reason and payload.
+///
+/// The parcel byte count of the header is overwritten. Returns the byte count of the packet.
+pub(crate) fn write_close_packet(
+	buffer: &mut [u8],
+	mut header: PacketHeader,
+	reason: CloseReason,
+	payload: &[u8],
+) -> Result<usize, BuildPacketError> {
+	let payload_byte_count = 1 + payload.len();
+	if payload_byte_count > MAX_SEGMENT_BYTE_COUNT {
+		return Err(BuildPacketError::Serialization(SerializationError::BufferOverflow))
+	}
+	let byte_count = size_of::<PacketHeader>() + payload_byte_count;
+	if buffer.len() < byte_count {
+		return Err(BuildPacketError::InsufficientBuffer)
+	}
+
+	header.signal = SignalBits::close(payload_byte_count as u16);
+	packet::write_header(buffer, header);
+	packet::write_data(buffer, &[ reason.into() ], 0)?;
+	packet::write_data(buffer, payload, 1)?;
+	Ok(byte_count)
+}
+
+/// Read the reason and payload of a close or reject packet.
+///
+/// Packets without a payload carry no reason, which is read as
+/// [`Unspecified`](CloseReason::Unspecified).
+fn read_close_payload(packet: &[u8]) -> (CloseReason, &[u8]) {
+	match packet::get_parcel_segment(packet).split_first() {
+		Some((&code, payload)) => (code.into(), payload),
+		None => (CloseReason::Unspecified, &[]),
+	}
+}
+
+/// Deserialize the first parcel of provided segment received from the other end, checking its
+/// type id if the parcel type has one.
+///
+/// Returns the parcel and the number of bytes it took, including the type id.
+fn read_parcel<P: Parcel>(segment: &[u8]) -> Result<(P, usize), ProcessPacketError> {
+	let mut tag_byte_count = 0;
+	if let Some(type_id) = P::TYPE_ID {
+		let (received_type_id, byte_count) = u16::from_bytes(segment)?;
+		if received_type_id != type_id {
+			return Err(ProcessPacketError::UnexpectedParcelType(received_type_id))
+		}
+		tag_byte_count = byte_count;
+	}
+	let (parcel, byte_count) = P::from_bytes(&segment[tag_byte_count ..])?;
+	if byte_count == 0 {
+		return Err(SerializationError::UnexpectedValue.into())
+	}
+	Ok((parcel, tag_byte_count + byte_count))
+}
+
+/// Deserialize the parcels of provided segment written by this end, appending them along with
+/// provided prelude.
+fn read_own_parcels<P: Parcel>(mut segment: &[u8], prelude: DataPrelude, parcels: &mut Vec<(P, DataPrelude)>) -> Result<(), SerializationError> {
+	let tag_byte_count = P::TYPE_ID.map_or(0, |type_id| type_id.byte_count());
+	while !segment.is_empty() {
+		let (parcel, byte_count) = P::from_bytes(segment.get(tag_byte_count ..).unwrap_or_default())?;
+		if byte_count == 0 {
+			return Err(SerializationError::UnexpectedValue)
+		}
+		parcels.push((parcel, prelude));
+		segment = &segment[tag_byte_count + byte_count ..];
+	}
+	Ok(())
+}
+
+/// Sample the queue latency of a parcel written into a packet at provided moment, releasing its
+/// buffer to provided pool.
+fn recycle_parcel(parcel: OutgoingParcel, now: Instant, buffer_pool: &dyn BufferPool, queue_latency: &mut Option<Duration>) {
+	let sample = now.saturating_duration_since(parcel.queued_at);
+	*queue_latency = Some(match *queue_latency {
+		Some(latency) if sample >= latency => latency + (sample - latency) / 8,
+		Some(latency) => latency - (latency - sample) / 8,
+		None => sample,
+	});
+	buffer_pool.release(parcel.bytes);
+}
+
+/// Write as many of provided serialized parcels sharing a prelude into the data segment of the
+/// packet as fit, but no more than `max_parcel_count`. Written parcels are passed to
+/// `on_written` once popped.
+///
+/// Returns the number of written bytes and the prelude of the written parcels.
+fn write_parcels(
+	packet: &mut [u8],
+	parcels: &mut VecDeque<OutgoingParcel>,
+	capacity: usize,
+	max_parcel_count: usize,
+	mut on_written: impl FnMut(OutgoingParcel),
+) -> Result<(usize, DataPrelude), BuildPacketError> {
+	let capacity = capacity.min(MAX_SEGMENT_BYTE_COUNT);
+	let prelude = parcels.front().map_or([0; 4], |parcel| parcel.prelude);
+	let mut byte_count = 0;
+	let mut parcel_count = 0;
+	while let Some(parcel) = parcels.front() {
+		if parcel_count + parcel.parcel_count > max_parcel_count || parcel.prelude != prelude || byte_count + parcel.bytes.len() > capacity {
+			break
+		}
+		packet::write_data(packet, &parcel.bytes, byte_count)?;
+		byte_count += parcel.bytes.len();
+		parcel_count += parcel.parcel_count;
+		if let Some(parcel) = parcels.pop_front() {
+			on_written(parcel);
+		}
+	}
+	if byte_count == 0 && parcels.front().is_some_and(|parcel| parcel.parcel_count <= max_parcel_count) {
+		// The first parcel does not fit even an otherwise empty packet.
+		return Err(BuildPacketError::InsufficientBuffer)
+	}
+	Ok((byte_count, prelude))
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+	use super::super::clock::ManualClock;
+
+	const PACKET_SIZE: usize = 1200;
+
+	/// Build an accept packet answering provided request packet.
+	fn accept_request(request: &[u8], connection_id: ConnectionId, buffer: &mut [u8]) -> usize {
+		let handshake_id = packet::get_header(request).prelude;
+		packet::write_header(buffer, PacketHeader::accept_connection(handshake_id, 2));
+		connection_id.to_bytes(packet::get_mut_data_segment(buffer));
+		size_of::<PacketHeader>() + 2
+	}
+
+	/// Copy provided packet with its timestamp cleared, for comparing re-transmitted packets.
+	fn without_timestamp(packet: &[u8]) -> Vec<u8> {
+		let mut packet = packet.to_vec();
+		let header = packet::get_header(&packet);
+		packet::write_header(&mut packet, PacketHeader { timestamp: 0, .. header });
+		packet
+	}
+
+	/// A parcel that declares a smaller maximum size than it serializes to.
+	#[derive(Debug, PartialEq)]
+	struct Oversized(u32);
+
+	impl ByteSerialize for Oversized {
+		fn byte_count(&self) -> usize {
+			self.0.byte_count()
+		}
+
+		fn to_bytes(&self, bytes: &mut [u8]) {
+			self.0.to_bytes(bytes)
+		}
+
+		fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), SerializationError> {
+			u32::from_bytes(bytes).map(|(value, byte_count)| (Self(value), byte_count))
+		}
+	}
+
+	impl Parcel for Oversized {
+		const MAX_BYTE_COUNT: usize = 2;
+	}
+
+	/// A parcel tagged with provided type id.
+	#[derive(Debug, PartialEq)]
+	struct Tagged<const TYPE_ID: u16>(u32);
+
+	impl<const TYPE_ID: u16> ByteSerialize for Tagged<TYPE_ID> {
+		fn byte_count(&self) -> usize {
+			self.0.byte_count()
+		}
+
+		fn to_bytes(&self, bytes: &mut [u8]) {
+			self.0.to_bytes(bytes)
+		}
+
+		fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), SerializationError> {
+			u32::from_bytes(bytes).map(|(value, byte_count)| (Self(value), byte_count))
+		}
+	}
+
+	impl<const TYPE_ID: u16> Parcel for Tagged<TYPE_ID> {
+		const MAX_BYTE_COUNT: usize = 6;
+		const TYPE_ID: Option<u16> = Some(TYPE_ID);
+	}
+
+	#[test]
+	fn parcels_carry_their_type_id() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let mut client = Context::<Tagged<7>>::accept(1);
+		let mut server = Context::<Tagged<7>>::accept(1);
+		let mut mismatched = Context::<Tagged<8>>::accept(1);
+
+		client.push_reliable_parcel(Tagged(5)).unwrap();
+		client.push_reliable_parcel(Tagged(6)).unwrap();
+		let len = client.build_packet(&mut buffer).unwrap();
+		assert_eq!(len, size_of::<PacketHeader>() + 2 * 6);
+
+		server.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(server.pop_parcel(), Ok((Tagged(5), [0; 4])));
+		assert_eq!(server.pop_parcel(), Ok((Tagged(6), [0; 4])));
+		assert_eq!(mismatched.process_packet(&buffer[.. len]), Err(ProcessPacketError::UnexpectedParcelType(7)));
+		assert_eq!(mismatched.pop_parcel(), Err(ConnectionError::NoPendingParcels));
+	}
+
+	#[test]
+	fn packets_with_invalid_parcels_are_refused_as_a_whole() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let mut client = Context::<u32>::accept(1);
+		let mut server = Context::<u32>::accept(1);
+
+		client.push_reliable_parcel(1).unwrap();
+		client.push_reliable_parcel(2).unwrap();
+		let len = client.build_packet(&mut buffer).unwrap();
+
+		// Append a truncated third parcel.
+		let mut corrupted = buffer[.. len + 2].to_vec();
+		let mut header = packet::get_header(&corrupted);
+		header.signal.set_parcel_byte_count(10);
+		packet::write_header(&mut corrupted, header);
+		let sequence_state = server.sequence_state();
+		assert!(matches!(server.process_packet(&corrupted), Err(ProcessPacketError::Serialization(_))));
+		assert_eq!(server.pop_parcel(), Err(ConnectionError::NoPendingParcels));
+		assert_eq!(server.pending_ack_count(), 0);
+		assert_eq!(server.sequence_state(), sequence_state);
+
+		// The intact re-transmission delivers every parcel.
+		server.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(server.pop_parcel(), Ok((1, [0; 4])));
+		assert_eq!(server.pop_parcel(), Ok((2, [0; 4])));
+		assert_eq!(server.pending_ack_count(), 1);
+	}
+
+	#[test]
+	fn parcels_larger_than_max_byte_count_are_rejected() {
+		let mut context = Context::<Oversized>::accept(1);
+
+		assert_eq!(context.push_reliable_parcel(Oversized(1)), Err(ConnectionError::ParcelTooLarge));
+		assert_eq!(context.push_volatile_parcel(Oversized(1)), Err(ConnectionError::ParcelTooLarge));
+	}
+
+	#[test]
+	fn buffers_unable_to_hold_max_parcel_are_rejected() {
+		let mut buffer = vec![0; size_of::<PacketHeader>() + u32::MAX_BYTE_COUNT - 1];
+
+		let mut context = Context::<u32>::pending();
+		assert_eq!(context.build_request_packet(&mut buffer, &[]), Err(BuildPacketError::InsufficientBuffer));
+
+		let mut context = Context::<u32>::accept(1);
+		assert_eq!(context.build_packet(&mut buffer), Err(BuildPacketError::InsufficientBuffer));
+	}
+
+	#[test]
+	fn handshake_opens_connection() {
+		let mut request = vec![0; PACKET_SIZE];
+		let mut answer = vec![0; PACKET_SIZE];
+		let mut client = Context::<u32>::pending();
+
+		let request_len = client.build_request_packet(&mut request, b"request").unwrap();
+		let answer_len = accept_request(&request[.. request_len], 7, &mut answer);
+		client.process_packet(&answer[.. answer_len]).unwrap();
+
+		assert_eq!(client.status(), ConnectionStatus::Open);
+		assert_eq!(client.connection_id(), Some(7));
+		assert_eq!(client.remote_max_datagram_length(), None);
+	}
+
+	#[test]
+	fn handshake_starts_sequences_at_random_ids() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let mut starting_ids = Vec::new();
+		for _ in 0 .. 16 {
+			let mut client = Context::<u32>::pending();
+			let len = client.build_request_packet(&mut buffer, &[]).unwrap();
+			let request = buffer[.. len].to_vec();
+			let mut server = Context::<u32>::accept_handshake(7, packet::get_header(&request).prelude);
+			let len = accept_request(&request, 7, &mut buffer);
+			client.process_packet(&buffer[.. len]).unwrap();
+			starting_ids.push(client.sequence_state().next_packet_id);
+
+			client.push_reliable_parcel(1).unwrap();
+			let len = client.build_packet(&mut buffer).unwrap();
+			server.process_packet(&buffer[.. len]).unwrap();
+			assert_eq!(server.pop_parcel(), Ok((1, [0; 4])));
+
+			server.push_reliable_parcel(2).unwrap();
+			let len = server.build_packet(&mut buffer).unwrap();
+			client.process_packet(&buffer[.. len]).unwrap();
+			assert_eq!(client.pop_parcel(), Ok((2, [0; 4])));
+			assert_eq!(client.sequence_state().in_flight_count, 0);
+
+			let len = client.build_ack_packet(&mut buffer).unwrap();
+			server.process_packet(&buffer[.. len]).unwrap();
+			assert_eq!(server.sequence_state().in_flight_count, 0);
+		}
+		assert!(starting_ids.iter().any(|&packet_id| packet_id != starting_ids[0]));
+	}
+
+	#[test]
+	fn data_packet_opens_pending_connection() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let mut client = Context::<u32>::pending();
+		client.push_reliable_parcel(1).unwrap();
+		let len = client.build_request_packet(&mut buffer, &[]).unwrap();
+		let mut server = Context::<u32>::accept_handshake(7, packet::get_header(&buffer[.. len]).prelude);
+
+		// The accept packet is lost, the first data packet arrives instead.
+		server.push_reliable_parcel(42).unwrap();
+		let len = server.build_packet(&mut buffer).unwrap();
+		client.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(client.status(), ConnectionStatus::Open);
+		assert_eq!(client.connection_id(), Some(7));
+		assert_eq!(client.pop_parcel(), Ok((42, [0; 4])));
+
+		let len = client.build_packet(&mut buffer).unwrap();
+		server.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(server.pop_parcel(), Ok((1, [0; 4])));
+		assert_eq!(server.sequence_state().in_flight_count, 0);
+	}
+
+	#[test]
+	fn stray_data_packet_does_not_open_pending_connection() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let mut client = Context::<u32>::pending();
+		let len = client.build_request_packet(&mut buffer, &[]).unwrap();
+		let mut handshake_id = packet::get_header(&buffer[.. len]).prelude;
+
+		// A packet of another handshake starts far from the expected packet id.
+		handshake_id[1] = handshake_id[1].wrapping_add(128);
+		let mut server = Context::<u32>::accept_handshake(7, handshake_id);
+		server.push_reliable_parcel(42).unwrap();
+		let len = server.build_packet(&mut buffer).unwrap();
+		assert_eq!(client.process_packet(&buffer[.. len]), Err(ProcessPacketError::UnexpectedPacket));
+		assert_eq!(client.status(), ConnectionStatus::Pending);
+		assert_eq!(client.connection_id(), None);
+	}
+
+	#[test]
+	fn early_close_packet_does_not_open_pending_connection() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let mut client = Context::<u32>::pending();
+		let mut server = Context::<u32>::accept(7);
+		client.build_request_packet(&mut buffer, &[]).unwrap();
+
+		let len = server.close(&mut buffer).unwrap();
+		assert_eq!(client.process_packet(&buffer[.. len]), Err(ProcessPacketError::UnexpectedPacket));
+		assert_eq!(client.status(), ConnectionStatus::Pending);
+	}
+
+	#[test]
+	fn accept_packet_limits_packet_length() {
+		const REMOTE_LENGTH: usize = 1100;
+		let mut buffer = vec![0; PACKET_SIZE];
+		let mut client = Context::<u32>::pending();
+
+		let request_len = client.build_request_packet(&mut buffer, &[]).unwrap();
+		let handshake_id = packet::get_header(&buffer[.. request_len]).prelude;
+		packet::write_header(&mut buffer, PacketHeader::accept_connection(handshake_id, 4));
+		(7 as ConnectionId, REMOTE_LENGTH as u16).to_bytes(packet::get_mut_data_segment(&mut buffer));
+		client.process_packet(&buffer[.. size_of::<PacketHeader>() + 4]).unwrap();
+		assert_eq!(client.status(), ConnectionStatus::Open);
+		assert_eq!(client.remote_max_datagram_length(), Some(REMOTE_LENGTH));
+
+		for parcel in 0 .. 300 {
+			client.push_reliable_parcel(parcel).unwrap();
+		}
+		let len = client.build_packet(&mut buffer).unwrap();
+		assert!((REMOTE_LENGTH - 4 ..= REMOTE_LENGTH).contains(&len));
+
+		client.set_padding(Some(PACKET_SIZE));
+		assert_eq!(client.build_ack_packet(&mut buffer), Ok(REMOTE_LENGTH));
+	}
+
+	#[test]
+	fn fixed_max_datagram_length_limits_packet_length() {
+		const FIXED_LENGTH: usize = 1100;
+		let mut buffer = vec![0; PACKET_SIZE];
+		let mut context = Context::<u32>::accept(1);
+		context.set_max_datagram_length(Some(FIXED_LENGTH));
+
+		for parcel in 0 .. 300 {
+			context.push_reliable_parcel(parcel).unwrap();
+		}
+		let mut total_parcel_count = 0;
+		while total_parcel_count < 300 {
+			let len = context.build_packet(&mut buffer).unwrap();
+			assert!(len <= FIXED_LENGTH);
+			total_parcel_count += packet::get_parcel_segment(&buffer[.. len]).len() / 4;
+		}
+		context.set_padding(Some(PACKET_SIZE));
+		assert_eq!(context.build_ack_packet(&mut buffer), Ok(FIXED_LENGTH));
+
+		context.set_max_datagram_length(Some(size_of::<PacketHeader>()));
+		assert_eq!(context.build_packet(&mut buffer), Err(BuildPacketError::InsufficientBuffer));
+	}
+
+	#[test]
+	fn buffers_are_recycled_through_pool() {
+		use std::sync::atomic::{AtomicUsize, Ordering};
+
+		/// Pool counting the buffers it vends and recycles.
+		#[derive(Default)]
+		struct CountingPool {
+			acquired: AtomicUsize,
+			released: AtomicUsize,
+		}
+
+		impl BufferPool for CountingPool {
+			fn acquire(&self, capacity: usize) -> Vec<u8> {
+				self.acquired.fetch_add(1, Ordering::Relaxed);
+				Vec::with_capacity(capacity)
+			}
+
+			fn release(&self, _: Vec<u8>) {
+				self.released.fetch_add(1, Ordering::Relaxed);
+			}
+		}
+
+		let mut buffer = vec![0; PACKET_SIZE];
+		let pool = Arc::new(CountingPool::default());
+		let mut client = Context::<u32>::accept(1);
+		let mut server = Context::<u32>::accept(1);
+		client.set_buffer_pool(pool.clone());
+
+		client.push_reliable_parcel(1).unwrap();
+		client.push_reliable_batch(&[ 2, 3, ]).unwrap();
+		client.push_volatile_parcel(4).unwrap();
+		let len = client.build_packet(&mut buffer).unwrap();
+		server.process_packet(&buffer[.. len]).unwrap();
+		client.build_packet(&mut buffer).unwrap();
+		assert!(pool.acquired.load(Ordering::Relaxed) > pool.released.load(Ordering::Relaxed));
+
+		let len = server.build_ack_packet(&mut buffer).unwrap();
+		client.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(client.sequence_state().in_flight_count, 0);
+		assert_eq!(pool.acquired.load(Ordering::Relaxed), pool.released.load(Ordering::Relaxed));
+	}
+
+	#[test]
+	fn stats_are_read_through_handles() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let mut client = Context::<u32>::accept(1);
+		let mut server = Context::<u32>::accept(1);
+		let handle = server.stats_handle();
+
+		client.push_reliable_parcel(0).unwrap();
+		let len = client.build_packet(&mut buffer).unwrap();
+		server.process_packet(&buffer[.. len]).unwrap();
+		server.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(client.stats(), ConnectionStats { sent_packet_count: 1, sent_byte_count: len as u64, .. Default::default() });
+
+		let stats = std::thread::spawn(move || handle.snapshot()).join().unwrap();
+		assert_eq!(stats, ConnectionStats {
+			received_packet_count: 2,
+			received_byte_count: 2 * len as u64,
+			duplicate_packet_count: 1,
+			.. Default::default()
+		});
+		assert_eq!(stats, server.stats());
+	}
+
+	#[test]
+	fn black_hole_lowers_datagram_length() {
+		let mut buffer = vec![0; 1500];
+		let clock = ManualClock::new();
+		let mut client = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+		let mut server = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+
+		// The long packet is dropped by the path, while the short one passes.
+		for parcel in 0 .. 370 {
+			client.push_reliable_parcel(parcel).unwrap();
+		}
+		assert_eq!(client.build_packet(&mut buffer), Ok(1500));
+		let len = client.build_packet(&mut buffer).unwrap();
+		assert!(len <= BLACK_HOLE_DATAGRAM_LENGTH);
+		server.process_packet(&buffer[.. len]).unwrap();
+		let len = server.build_ack_packet(&mut buffer).unwrap();
+		client.process_packet(&buffer[.. len]).unwrap();
+
+		for _ in 0 .. BLACK_HOLE_LOSS_THRESHOLD {
+			assert!(!client.is_black_hole_detected());
+			clock.advance(client.current_rto());
+			assert_eq!(client.build_packet(&mut buffer), Ok(1500));
+		}
+		assert!(client.is_black_hole_detected());
+		assert_eq!(client.stats().black_hole_count, 1);
+
+		for parcel in 0 .. 370 {
+			client.push_reliable_parcel(parcel).unwrap();
+		}
+		assert_eq!(client.build_packet(&mut buffer), Ok(BLACK_HOLE_DATAGRAM_LENGTH));
+
+		// The long packet is still re-sent whole, delivering its parcels once the path recovers
+		clock.advance(client.current_rto());
+		let len = client.build_packet(&mut buffer).unwrap();
+		assert_eq!(len, 1500);
+		server.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(server.pop_parcel(), Ok((369, [0; 4])));
+		for parcel in 0 .. 369 {
+			assert_eq!(server.pop_parcel(), Ok((parcel, [0; 4])));
+		}
+	}
+
+	#[test]
+	fn lowered_datagram_length_keeps_lost_packets_whole() {
+		let mut buffer = vec![0; 1500];
+		let clock = ManualClock::new();
+		let mut client = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+		let mut server = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+
+		for parcel in 0 .. 370 {
+			client.push_reliable_parcel(parcel).unwrap();
+		}
+		assert_eq!(client.build_packet(&mut buffer), Ok(1500));
+		client.set_max_datagram_length(Some(1100));
+		client.push_reliable_parcel(370).unwrap();
+		clock.advance(client.current_rto());
+
+		let len = client.build_packet(&mut buffer).unwrap();
+		assert_eq!(len, 1500);
+		server.process_packet(&buffer[.. len]).unwrap();
+		let len = client.build_packet(&mut buffer).unwrap();
+		assert!(len <= 1100);
+		server.process_packet(&buffer[.. len]).unwrap();
+		for parcel in 0 ..= 370 {
+			assert_eq!(server.pop_parcel(), Ok((parcel, [0; 4])));
+		}
+	}
+
+	#[test]
+	fn stream_buffer_is_limited() {
+		const SEGMENT_LENGTH: usize = <u32 as Parcel>::MAX_BYTE_COUNT;
+		let mut buffer = vec![0; PACKET_SIZE];
+		let mut client = Context::<u32>::accept(1);
+		let mut server = Context::<u32>::accept(1);
+		client.set_max_datagram_length(Some(size_of::<PacketHeader>() + SEGMENT_LENGTH));
+		server.set_max_stream_buffer_length(Some(SEGMENT_LENGTH * 3 / 2));
+
+		client.write_bytes_to_stream(&[1; 3 * SEGMENT_LENGTH]).unwrap();
+		let packets: Vec<_> = (0 .. 3).map(|_| {
+			let len = client.build_packet(&mut buffer).unwrap();
+			assert_eq!(len, size_of::<PacketHeader>() + SEGMENT_LENGTH);
+			buffer[.. len].to_vec()
+		}).collect();
+
+		// Data past the gap fills the buffer, refusing further out of order segments.
+		server.process_packet(&packets[1]).unwrap();
+		server.process_packet(&packets[2]).unwrap();
+		assert_eq!(server.sequence_state().received_packet_ack_id, 1.into());
+
+		// The segment filling the gap is accepted, releasing the data past it.
+		server.process_packet(&packets[0]).unwrap();
+		assert_eq!(server.pending_incoming_stream_bytes(), 2 * SEGMENT_LENGTH);
+		server.process_packet(&packets[2]).unwrap();
+		assert_eq!(server.pending_incoming_stream_bytes(), 2 * SEGMENT_LENGTH);
+
+		let mut stream = vec![0; 3 * SEGMENT_LENGTH];
+		assert_eq!(server.read_from_stream(&mut stream), Ok(2 * SEGMENT_LENGTH));
+		server.process_packet(&packets[2]).unwrap();
+		assert_eq!(server.read_from_stream(&mut stream), Ok(SEGMENT_LENGTH));
+	}
+
+	#[test]
+	fn delay_based_congestion_control_follows_round_trip_time() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut client = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+		let mut server = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+		client.set_congestion_control(Some(CongestionControl::DelayBased));
+		client.write_bytes_to_stream(&[1; 128 * PACKET_SIZE]).unwrap();
+
+		let mut exchange = |client: &mut Context<u32>, round_trip_time| {
+			let mut packet_count = 0;
+			loop {
+				let len = client.build_packet(&mut buffer).unwrap();
+				if len == 0 {
+					break
+				}
+				server.process_packet(&buffer[.. len]).unwrap();
+				packet_count += 1;
+			}
+			clock.advance(round_trip_time);
+			let len = server.build_ack_packet(&mut buffer).unwrap();
+			client.process_packet(&buffer[.. len]).unwrap();
+			packet_count
+		};
+		for expected_count in [ 4, 8, 16, ].iter() {
+			assert_eq!(exchange(&mut client, Duration::from_millis(10)), *expected_count);
+		}
+		assert_eq!(client.congestion_window(), Some(32));
+
+		// Queueing along the path ends slow start and shrinks the window, without any losses.
+		assert_eq!(exchange(&mut client, Duration::from_millis(40)), 32);
+		assert!(!client.is_slow_start());
+		assert_eq!(client.congestion_window(), Some(31));
+		assert_eq!(client.stats().lost_packet_count, 0);
+	}
+
+	#[test]
+	fn ping_reports_round_trip_time() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut client = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+		let mut server = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+
+		client.ping().unwrap();
+		client.ping().unwrap();
+		let len = client.build_packet(&mut buffer).unwrap();
+		assert_eq!(len, size_of::<PacketHeader>());
+		assert!(packet::get_header(&buffer).signal.is_signal_set(Signal::Synchronized));
+		assert_eq!(client.build_packet(&mut buffer), Ok(0));
+		server.process_packet(&buffer[.. len]).unwrap();
+
+		clock.advance(Duration::from_millis(30));
+		let len = server.build_packet(&mut buffer).unwrap();
+		client.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(client.poll(), Some(ConnectionEvent::PacketDelivered(0.into())));
+		assert_eq!(client.poll(), Some(ConnectionEvent::PingResult(Duration::from_millis(30))));
+		assert_eq!(client.poll(), None);
+		assert_eq!(server.poll(), None);
+	}
+
+	#[test]
+	fn congestion_window_grows_exponentially_until_loss() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut client = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+		let mut server = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+		client.set_congestion_control(Some(CongestionControl::LossBased));
+		client.write_bytes_to_stream(&[1; 128 * PACKET_SIZE]).unwrap();
+
+		let build_all = |client: &mut Context<u32>| {
+			let mut buffer = vec![0; PACKET_SIZE];
+			let mut packets = Vec::new();
+			loop {
+				let len = client.build_packet(&mut buffer).unwrap();
+				if len == 0 {
+					return packets
+				}
+				packets.push(buffer[.. len].to_vec());
+			}
+		};
+		for expected_window in [ 4, 8, 16, ].iter() {
+			assert_eq!(client.congestion_window(), Some(*expected_window));
+			assert!(client.is_slow_start());
+			let packets = build_all(&mut client);
+			assert_eq!(packets.len(), *expected_window);
+			for packet in &packets {
+				server.process_packet(packet).unwrap();
+			}
+			clock.advance(Duration::from_millis(10));
+			let len = server.build_ack_packet(&mut buffer).unwrap();
+			client.process_packet(&buffer[.. len]).unwrap();
+		}
+
+		// The whole window is lost, yet it is only halved once.
+		assert_eq!(build_all(&mut client).len(), 32);
+		clock.advance(client.current_rto());
+		let retransmitted = build_all(&mut client);
+		assert_eq!(retransmitted.len(), 32);
+		assert_eq!(client.congestion_window(), Some(16));
+		assert!(!client.is_slow_start());
+
+		// Past slow start the window grows by a single packet per round-trip.
+		for packet in &retransmitted {
+			server.process_packet(packet).unwrap();
+		}
+		let len = server.build_ack_packet(&mut buffer).unwrap();
+		client.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(client.congestion_window(), Some(17));
+		assert_eq!(build_all(&mut client).len(), 17);
+	}
+
+	#[test]
+	fn pending_outgoing_bytes_count_queued_data() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let mut context = Context::<u32>::accept(1);
+		assert_eq!(context.pending_outgoing_bytes(), 0);
+
+		context.push_reliable_parcel(1).unwrap();
+		context.push_volatile_parcel(2).unwrap();
+		context.write_bytes_to_stream(b"data").unwrap();
+		assert_eq!(context.pending_outgoing_bytes(), 1u32.byte_count() + 2u32.byte_count() + 4);
+
+		context.build_packet(&mut buffer).unwrap();
+		context.build_packet(&mut buffer).unwrap();
+		assert_eq!(context.pending_outgoing_bytes(), 0);
+	}
+
+	#[test]
+	fn parcels_per_packet_are_limited() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let mut client = Context::<u32>::accept(1);
+		let mut server = Context::<u32>::accept(1);
+		client.set_max_parcels_per_packet(Some(2));
+
+		for parcel in 0 .. 3 {
+			client.push_reliable_parcel(parcel).unwrap();
+			client.push_volatile_parcel(parcel).unwrap();
+		}
+		let mut packet_lengths = Vec::new();
+		loop {
+			let len = client.build_packet(&mut buffer).unwrap();
+			if len == 0 {
+				break
+			}
+			packet_lengths.push(len - size_of::<PacketHeader>());
+			server.process_packet(&buffer[.. len]).unwrap();
+		}
+		assert_eq!(packet_lengths, [ 8, 4, 8, 4, ]);
+		assert_eq!(client.sent_packets.iter().map(|packet| packet.parcel_count).collect::<Vec<_>>(), [ 2, 1, ]);
+
+		let mut parcels = Vec::new();
+		while let Ok((parcel, _)) = server.pop_parcel() {
+			parcels.push(parcel);
+		}
+		parcels.sort_unstable();
+		assert_eq!(parcels, [ 0, 0, 1, 1, 2, 2, ]);
+	}
+
+	#[test]
+	fn losses_and_corruption_are_counted_separately() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut client = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+		let mut server = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+
+		client.push_reliable_parcel(0).unwrap();
+		client.build_packet(&mut buffer).unwrap();
+		clock.advance(RETRANSMISSION_TIMEOUT);
+		client.build_packet(&mut buffer).unwrap();
+		assert_eq!(client.resend_unacked(), 1);
+		client.build_packet(&mut buffer).unwrap();
+		assert_eq!(client.stats().retransmitted_packet_count, 2);
+		assert_eq!(client.stats().lost_packet_count, 1);
+
+		let truncated = &buffer[.. size_of::<PacketHeader>() - 1];
+		assert_eq!(server.process_packet(truncated), Err(ProcessPacketError::MalformedPacket));
+		assert_eq!(server.stats(), ConnectionStats { malformed_packet_count: 1, .. Default::default() });
+	}
+
+	#[test]
+	fn looped_back_packets_are_dropped() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut client = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+		let mut server = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+		client.set_padding(Some(PACKET_SIZE));
+
+		client.push_reliable_parcel(3).unwrap();
+		let len = client.build_packet(&mut buffer).unwrap();
+		let sequence_state = client.sequence_state();
+		assert_eq!(client.process_packet(&buffer[.. len]), Err(ProcessPacketError::LoopedBackPacket));
+
+		// Re-transmissions are recognized as well.
+		clock.advance(RETRANSMISSION_TIMEOUT);
+		let len = client.build_packet(&mut buffer).unwrap();
+		assert_eq!(client.stats().retransmitted_packet_count, 1);
+		assert_eq!(client.process_packet(&buffer[.. len]), Err(ProcessPacketError::LoopedBackPacket));
+		assert_eq!(client.stats().looped_back_packet_count, 2);
+		assert_eq!(client.sequence_state(), sequence_state);
+		assert_eq!(client.pop_parcel(), Err(ConnectionError::NoPendingParcels));
+
+		// The same packet is processed by the other end.
+		server.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(server.pop_parcel(), Ok((3, [0; 4])));
+		assert_eq!(server.stats().looped_back_packet_count, 0);
+	}
+
+	#[test]
+	fn steady_state_parcels_do_not_allocate() {
+		use super::super::pool::RecyclingPool;
+
+		let mut buffer = vec![0; PACKET_SIZE];
+		let pool = Arc::new(RecyclingPool::new(16));
+		let mut client = Context::<u32>::accept(1);
+		let mut server = Context::<u32>::accept(1);
+		client.set_buffer_pool(pool.clone());
+
+		let mut exchange = |parcel| {
+			client.push_volatile_parcel(parcel).unwrap();
+			client.push_reliable_batch(&[ parcel, parcel + 1, ]).unwrap();
+			let len = client.build_packet(&mut buffer).unwrap();
+			server.process_packet(&buffer[.. len]).unwrap();
+			let len = client.build_packet(&mut buffer).unwrap();
+			server.process_packet(&buffer[.. len]).unwrap();
+			let len = server.build_ack_packet(&mut buffer).unwrap();
+			client.process_packet(&buffer[.. len]).unwrap();
+		};
+		// Warm up the pool until enough buffers are in circulation.
+		for parcel in 0 .. 4 {
+			exchange(parcel);
+		}
+		let allocation_count = pool.allocation_count();
+		for parcel in 4 .. 100 {
+			exchange(parcel);
+		}
+		assert_eq!(pool.allocation_count(), allocation_count);
+	}
+
+	#[test]
+	fn parcels_queued_while_pending_are_sent_once_open() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let mut client = Context::<u32>::pending();
+		client.push_reliable_parcel(42).unwrap();
+		assert_eq!(client.push_volatile_parcel(43), Err(ConnectionError::InvalidState));
+		assert_eq!(client.build_packet(&mut buffer), Err(BuildPacketError::InvalidState));
+
+		let request_len = client.build_request_packet(&mut buffer, &[]).unwrap();
+		let handshake_id = packet::get_header(&buffer[.. request_len]).prelude;
+		let mut server = Context::<u32>::accept_handshake(7, handshake_id);
+		packet::write_header(&mut buffer, PacketHeader::accept_connection(handshake_id, 2));
+		(7 as ConnectionId).to_bytes(packet::get_mut_data_segment(&mut buffer));
+		client.process_packet(&buffer[.. size_of::<PacketHeader>() + 2]).unwrap();
+		assert_eq!(client.status(), ConnectionStatus::Open);
+
+		let len = client.build_packet(&mut buffer).unwrap();
+		server.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(server.pop_parcel(), Ok((42, [0; 4])));
+	}
+
+	#[test]
+	fn reliable_parcels_are_delivered_and_acknowledged() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let mut client = Context::<u32>::accept(3);
+		let mut server = Context::<u32>::accept(3);
+
+		client.push_reliable_parcel(0xDEAD_BEEF).unwrap();
+		client.push_reliable_parcel(17).unwrap();
+		let len = client.build_packet(&mut buffer).unwrap();
+		server.process_packet(&buffer[.. len]).unwrap();
+
+		assert_eq!(server.pop_parcel(), Ok((0xDEAD_BEEF, [0; 4])));
+		assert_eq!(server.pop_parcel(), Ok((17, [0; 4])));
+		assert_eq!(server.pop_parcel(), Err(ConnectionError::NoPendingParcels));
+		assert_eq!(client.sent_packets.len(), 1);
+
+		let len = server.build_packet(&mut buffer).unwrap();
+		client.process_packet(&buffer[.. len]).unwrap();
+
+		assert!(client.sent_packets.is_empty());
+		assert_eq!(client.build_packet(&mut buffer), Ok(0));
+	}
+
+	#[test]
+	fn reliable_batches_are_sent_in_one_packet() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let mut client = Context::<u32>::accept(3);
+		let mut server = Context::<u32>::accept(3);
+
+		client.push_reliable_parcel(1).unwrap();
+		client.push_reliable_batch(&[ 2, 3, 4, ]).unwrap();
+		// The batch is written whole, so it shares a packet with the preceding parcel.
+		let len = client.build_packet(&mut buffer).unwrap();
+		assert_eq!(len, size_of::<PacketHeader>() + 16);
+		server.process_packet(&buffer[.. len]).unwrap();
+
+		for expected in 1 ..= 4 {
+			assert_eq!(server.pop_parcel(), Ok((expected, [0; 4])));
+		}
+		assert_eq!(server.pop_parcel(), Err(ConnectionError::NoPendingParcels));
+	}
+
+	#[test]
+	fn reliable_batches_larger_than_max_byte_count_are_rejected() {
+		let mut context = Context::<u32>::accept(3);
+		let batch = vec![0; u32::MAX_BYTE_COUNT / 4 + 1];
+
+		assert_eq!(context.push_reliable_batch(&batch), Err(ConnectionError::BatchTooLarge));
+		assert_eq!(context.push_reliable_batch(&batch[1 ..]), Ok(()));
+		assert_eq!(context.reliable_parcels.len(), 1);
+	}
+
+	#[test]
+	fn sequence_state_tracks_sent_and_received_packets() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let mut client = Context::<u32>::accept(3);
+		let mut server = Context::<u32>::accept(3);
+
+		let initial = client.sequence_state();
+		assert_eq!(initial.next_packet_id, 0.into());
+		assert_eq!(initial.in_flight_count, 0);
+
+		for parcel in 0 .. 2 {
+			client.push_reliable_parcel(parcel).unwrap();
+			let len = client.build_packet(&mut buffer).unwrap();
+			server.process_packet(&buffer[.. len]).unwrap();
+		}
+		let client_state = client.sequence_state();
+		assert_eq!(client_state.next_packet_id, 2.into());
+		assert_eq!(client_state.in_flight_count, 2);
+		let server_state = server.sequence_state();
+		assert_eq!(server_state.received_packet_ack_id, 1.into());
+		assert_eq!(server_state.received_packet_ack_mask & 1, 1);
+
+		let len = server.build_packet(&mut buffer).unwrap();
+		client.process_packet(&buffer[.. len]).unwrap();
+		let client_state = client.sequence_state();
+		assert_eq!(client_state.acknowledged_packet_id, 1.into());
+		assert_eq!(client_state.in_flight_count, 0);
+	}
+
+	#[test]
+	fn duplicate_packets_are_dropped() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let mut client = Context::<u32>::accept(3);
+		let mut server = Context::<u32>::accept(3);
+
+		client.push_reliable_parcel(1).unwrap();
+		let len = client.build_packet(&mut buffer).unwrap();
+		server.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(server.sequence_state().duplicate_packet_count, 0);
+		server.process_packet(&buffer[.. len]).unwrap();
+
+		assert_eq!(server.pop_parcel(), Ok((1, [0; 4])));
+		assert_eq!(server.pop_parcel(), Err(ConnectionError::NoPendingParcels));
+		assert_eq!(server.sequence_state().duplicate_packet_count, 1);
+	}
+
+	#[test]
+	fn stream_is_reassembled_in_order() {
+		let mut first = vec![0; PACKET_SIZE];
+		let mut second = vec![0; PACKET_SIZE];
+		let mut client = Context::<u32>::accept(3);
+		let mut server = Context::<u32>::accept(3);
+
+		client.write_bytes_to_stream(b"Hello ").unwrap();
+		let first_len = client.build_packet(&mut first).unwrap();
+		client.write_bytes_to_stream(b"there!").unwrap();
+		let second_len = client.build_packet(&mut second).unwrap();
+
+		server.process_packet(&second[.. second_len]).unwrap();
+		let mut stream = [0; 16];
+		assert_eq!(server.read_from_stream(&mut stream), Ok(0));
+
+		server.process_packet(&first[.. first_len]).unwrap();
+		assert_eq!(server.read_from_stream(&mut stream), Ok(12));
+		assert_eq!(&stream[.. 12], b"Hello there!");
+	}
+
+	#[test]
+	fn stream_is_read_up_to_first_gap() {
+		let mut packets = vec![vec![0; PACKET_SIZE]; 3];
+		let mut lens = [0; 3];
+		let mut client = Context::<u32>::accept(3);
+		let mut server = Context::<u32>::accept(3);
+
+		for (index, chunk) in [ &b"abc"[..], b"def", b"ghi" ].iter().enumerate() {
+			client.write_bytes_to_stream(chunk).unwrap();
+			lens[index] = client.build_packet(&mut packets[index]).unwrap();
+		}
+
+		server.process_packet(&packets[0][.. lens[0]]).unwrap();
+		server.process_packet(&packets[2][.. lens[2]]).unwrap();
+		assert_eq!(server.pending_incoming_stream_bytes(), 3);
+		let mut stream = [0; 16];
+		assert_eq!(server.read_from_stream(&mut stream[.. 2]), Ok(2));
+		assert_eq!(server.pending_incoming_stream_bytes(), 1);
+		assert_eq!(server.read_from_stream(&mut stream[2 ..]), Ok(1));
+		assert_eq!(&stream[.. 3], b"abc");
+		assert_eq!(server.read_from_stream(&mut stream), Ok(0));
+
+		server.process_packet(&packets[1][.. lens[1]]).unwrap();
+		assert_eq!(server.pending_incoming_stream_bytes(), 6);
+		assert_eq!(server.read_from_stream(&mut stream), Ok(6));
+		assert_eq!(&stream[.. 6], b"defghi");
+	}
+
+	/// Run-length encoding of bytes, sufficient to test compression of repetitive data.
+	struct RunLengthCompressor;
+
+	impl Compressor for RunLengthCompressor {
+		fn compress(&self, data: &[u8], output: &mut Vec<u8>) {
+			for &byte in data {
+				match output.len() {
+					len if len >= 2 && output[len - 1] == byte && output[len - 2] < u8::MAX => output[len - 2] += 1,
+					_ => output.extend_from_slice(&[1, byte]),
+				}
+			}
+		}
+
+		fn decompress(&self, data: &[u8], output: &mut Vec<u8>) -> Result<(), SerializationError> {
+			if !data.len().is_multiple_of(2) {
+				return Err(SerializationError::UnexpectedValue)
+			}
+			for run in data.chunks(2) {
+				output.extend(std::iter::repeat_n(run[1], run[0] as usize));
+			}
+			Ok(())
+		}
+	}
+
+	#[test]
+	fn compressible_packets_are_compressed() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let mut client = Context::<u32>::accept(3);
+		let mut server = Context::<u32>::accept(3);
+		client.set_compressor(Some(Box::new(RunLengthCompressor)));
+		server.set_compressor(Some(Box::new(RunLengthCompressor)));
+
+		client.write_bytes_to_stream(&[0; 200]).unwrap();
+		let len = client.build_packet(&mut buffer).unwrap();
+		assert!(len < size_of::<PacketHeader>() + 200);
+		assert!(packet::get_header(&buffer).signal.is_signal_set(Signal::Compressed));
+
+		server.process_packet(&buffer[.. len]).unwrap();
+		let mut stream = [0xFF; 256];
+		assert_eq!(server.read_from_stream(&mut stream), Ok(200));
+		assert_eq!(&stream[.. 200], &[0; 200][..]);
+	}
+
+	#[test]
+	fn unacked_parcels_are_taken_from_lost_connection() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut context = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+		context.set_compressor(Some(Box::new(RunLengthCompressor)));
+
+		for _ in 0 .. 50 {
+			context.push_reliable_parcel(0).unwrap();
+		}
+		context.build_packet(&mut buffer).unwrap();
+		assert!(packet::get_header(&buffer).signal.is_signal_set(Signal::Compressed));
+		context.set_prelude([ 1, 0, 0, 0, ]);
+		context.push_reliable_parcel(7).unwrap();
+		context.build_packet(&mut buffer).unwrap();
+		context.push_reliable_parcel(8).unwrap();
+		assert_eq!(context.take_unacked_parcels(), Err(ConnectionError::InvalidState));
+
+		clock.advance(TIMEOUT);
+		context.update();
+		assert_eq!(context.status(), ConnectionStatus::Lost);
+		assert_eq!(context.push_reliable_parcel(9), Err(ConnectionError::ConnectionLost));
+		assert_eq!(context.push_volatile_parcel(9), Err(ConnectionError::ConnectionLost));
+		assert_eq!(context.write_bytes_to_stream(b"data"), Err(ConnectionError::ConnectionLost));
+		assert_eq!(context.build_packet(&mut buffer), Err(BuildPacketError::InvalidState));
+
+		let mut expected = vec![(0, [0; 4]); 50];
+		expected.extend([ (7, [ 1, 0, 0, 0, ]), (8, [ 1, 0, 0, 0, ]), ]);
+		assert_eq!(context.take_unacked_parcels(), Ok(expected));
+		assert_eq!(context.take_unacked_parcels(), Ok(Vec::new()));
+	}
+
+	#[test]
+	fn small_packets_are_not_compressed() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let mut client = Context::<u32>::accept(3);
+		let mut server = Context::<u32>::accept(3);
+		client.set_compressor(Some(Box::new(RunLengthCompressor)));
+		server.set_compressor(Some(Box::new(RunLengthCompressor)));
+
+		client.write_bytes_to_stream(&[0; MIN_COMPRESSED_LENGTH - 1]).unwrap();
+		let len = client.build_packet(&mut buffer).unwrap();
+		assert_eq!(len, size_of::<PacketHeader>() + MIN_COMPRESSED_LENGTH - 1);
+		assert!(!packet::get_header(&buffer).signal.is_signal_set(Signal::Compressed));
+		server.process_packet(&buffer[.. len]).unwrap();
+
+		client.set_min_compressed_length(0);
+		client.write_bytes_to_stream(&[0; 8]).unwrap();
+		let len = client.build_packet(&mut buffer).unwrap();
+		assert!(packet::get_header(&buffer).signal.is_signal_set(Signal::Compressed));
+		server.process_packet(&buffer[.. len]).unwrap();
+
+		let mut stream = [0xFF; 128];
+		assert_eq!(server.read_from_stream(&mut stream), Ok(MIN_COMPRESSED_LENGTH - 1 + 8));
+		assert!(stream[.. MIN_COMPRESSED_LENGTH - 1 + 8].iter().all(|&byte| byte == 0));
+	}
+
+	#[test]
+	fn incompressible_packets_are_not_compressed() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let mut client = Context::<u32>::accept(3);
+		let mut server = Context::<u32>::accept(3);
+		client.set_compressor(Some(Box::new(RunLengthCompressor)));
+		server.set_compressor(Some(Box::new(RunLengthCompressor)));
+
+		client.push_volatile_parcel(0x0102_0304).unwrap();
+		let len = client.build_packet(&mut buffer).unwrap();
+		assert_eq!(len, size_of::<PacketHeader>() + 4);
+		assert!(!packet::get_header(&buffer).signal.is_signal_set(Signal::Compressed));
+
+		server.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(server.pop_parcel(), Ok((0x0102_0304, [0; 4])));
+	}
+
+	#[test]
+	fn send_budget_limits_data_packets() {
+		const RATE: u64 = 10_000;
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut context = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+		context.set_send_rate(Some(RATE));
+		let max_budget = RATE as usize * SEND_BUDGET_BURST.as_millis() as usize / 1000;
+
+		context.write_bytes_to_stream(&[7; 3000]).unwrap();
+		let len = context.build_packet(&mut buffer).unwrap();
+		assert!(len > max_budget);
+
+		// The overdrawn budget holds back the remaining data until refilled.
+		assert_eq!(context.build_packet(&mut buffer), Ok(0));
+		let refill_delay = Duration::from_secs_f64((len - max_budget) as f64 / RATE as f64);
+		assert_eq!(context.next_update_deadline(), clock.now() + refill_delay);
+
+		clock.advance(refill_delay + Duration::from_millis(1));
+		assert_eq!(context.build_packet(&mut buffer), Ok(0));
+		context.update();
+		assert!(context.build_packet(&mut buffer).unwrap() > size_of::<PacketHeader>());
+		assert_eq!(context.build_packet(&mut buffer), Ok(0));
+
+		// Keep-alives do not draw from the budget.
+		clock.advance(KEEP_ALIVE_INTERVAL);
+		assert_eq!(context.build_packet(&mut buffer), Ok(size_of::<PacketHeader>()));
+
+		context.set_send_rate(None);
+		assert!(context.build_packet(&mut buffer).unwrap() > size_of::<PacketHeader>());
+	}
+
+	#[test]
+	fn connection_is_idle_without_application_data() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut client = Context::<u32>::accept_with_clock(3, Box::new(clock.clone()));
+		let mut server = Context::<u32>::accept_with_clock(3, Box::new(clock.clone()));
+		clock.advance(IDLE_THRESHOLD);
+		assert!(client.is_idle());
+		assert!(server.is_idle());
+
+		client.push_volatile_parcel(1).unwrap();
+		let len = client.build_packet(&mut buffer).unwrap();
+		server.process_packet(&buffer[.. len]).unwrap();
+		assert!(!client.is_idle());
+		assert!(!server.is_idle());
+
+		// Keep-alive packets do not count as application data.
+		clock.advance(IDLE_THRESHOLD);
+		let len = client.build_packet(&mut buffer).unwrap();
+		assert_eq!(len, size_of::<PacketHeader>());
+		server.process_packet(&buffer[.. len]).unwrap();
+		assert!(server.is_idle());
+		assert_eq!(server.status(), ConnectionStatus::Open);
+	}
+
+	#[test]
+	fn lost_packets_are_retransmitted_after_timeout() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut context = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+
+		context.push_reliable_parcel(5).unwrap();
+		let len = context.build_packet(&mut buffer).unwrap();
+		let original = buffer[.. len].to_vec();
+
+		clock.advance(RETRANSMISSION_TIMEOUT - Duration::from_millis(1));
+		assert_eq!(context.build_packet(&mut buffer), Ok(0));
+
+		clock.advance(Duration::from_millis(1));
+		assert_eq!(context.build_packet(&mut buffer), Ok(len));
+		// Only the timestamp is refreshed.
+		assert_eq!(packet::get_header(&buffer).timestamp, 1000);
+		assert_eq!(without_timestamp(&buffer[.. len]), without_timestamp(&original));
+		assert_eq!(context.sent_packets[0].retransmission_count, 1);
+	}
+
+	#[test]
+	fn retransmission_timeout_follows_round_trip_time() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut client = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+		let mut server = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+		assert_eq!(client.round_trip_time(), None);
+		assert_eq!(client.current_rto(), RETRANSMISSION_TIMEOUT);
+
+		client.push_reliable_parcel(1).unwrap();
+		let len = client.build_packet(&mut buffer).unwrap();
+		server.process_packet(&buffer[.. len]).unwrap();
+		clock.advance(Duration::from_millis(100));
+		let len = server.build_packet(&mut buffer).unwrap();
+		client.process_packet(&buffer[.. len]).unwrap();
+
+		assert_eq!(client.round_trip_time(), Some(Duration::from_millis(100)));
+		assert_eq!(client.current_rto(), Duration::from_millis(300));
+
+		client.set_rto_bounds(Duration::from_millis(10), Duration::from_millis(250));
+		assert_eq!(client.current_rto(), Duration::from_millis(250));
+		client.set_rto_bounds(Duration::from_millis(400), Duration::from_millis(400));
+		assert_eq!(client.current_rto(), Duration::from_millis(400));
+	}
+
+	#[test]
+	fn initial_retransmission_timeout_covers_high_latency() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut client = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+		let mut server = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+		client.set_initial_rto(Duration::from_millis(1500));
+		assert_eq!(client.current_rto(), Duration::from_millis(1500));
+
+		// One-way latency of 600 milliseconds.
+		client.push_reliable_parcel(1).unwrap();
+		let len = client.build_packet(&mut buffer).unwrap();
+		clock.advance(Duration::from_millis(600));
+		server.process_packet(&buffer[.. len]).unwrap();
+		let len = server.build_packet(&mut buffer).unwrap();
+		clock.advance(Duration::from_millis(600));
+		let mut keep_alive = vec![0; PACKET_SIZE];
+		assert_eq!(client.build_packet(&mut keep_alive), Ok(size_of::<PacketHeader>()));
+		assert_eq!(client.sent_packets[0].retransmission_count, 0);
+		client.process_packet(&buffer[.. len]).unwrap();
+
+		assert_eq!(client.round_trip_time(), Some(Duration::from_millis(1200)));
+		assert_eq!(client.current_rto(), MAX_RETRANSMISSION_TIMEOUT);
+	}
+
+	#[test]
+	fn retransmitted_packets_are_not_sampled() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut client = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+		let mut server = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+
+		client.push_reliable_parcel(1).unwrap();
+		client.build_packet(&mut buffer).unwrap();
+		clock.advance(RETRANSMISSION_TIMEOUT);
+		let len = client.build_packet(&mut buffer).unwrap();
+		server.process_packet(&buffer[.. len]).unwrap();
+		let len = server.build_packet(&mut buffer).unwrap();
+		client.process_packet(&buffer[.. len]).unwrap();
+
+		assert!(client.sent_packets.is_empty());
+		assert_eq!(client.round_trip_time(), None);
+	}
+
+	#[test]
+	fn parcels_carry_receive_timestamps() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut client = Context::<u32>::accept(1);
+		let mut server = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+
+		client.push_volatile_parcel(1).unwrap();
+		let len = client.build_packet(&mut buffer).unwrap();
+		let received_at = clock.now();
+		clock.advance(Duration::from_millis(10));
+		server.process_packet_received_at(&buffer[.. len], received_at).unwrap();
+		client.push_volatile_parcel(2).unwrap();
+		let len = client.build_packet(&mut buffer).unwrap();
+		server.process_packet(&buffer[.. len]).unwrap();
+
+		assert_eq!(server.pop_parcel_timestamped(), Ok((1, [0; 4], received_at)));
+		assert_eq!(server.pop_parcel_timestamped(), Ok((2, [0; 4], clock.now())));
+	}
+
+	#[test]
+	fn poll_reports_connection_events() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut client = Context::<u32>::pending_with_clock(Box::new(clock.clone()));
+		let mut server = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+		assert_eq!(client.poll(), None);
+
+		let len = client.build_request_packet(&mut buffer, &[]).unwrap();
+		let request = buffer[.. len].to_vec();
+		server.start_sequences(packet::get_header(&request).prelude, false);
+		let len = accept_request(&request, 1, &mut buffer);
+		client.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(client.poll(), Some(ConnectionEvent::StatusChanged(ConnectionStatus::Open)));
+		assert_eq!(client.poll(), None);
+
+		let packet_id = server.sequence_state().next_packet_id;
+		server.push_reliable_parcel(5).unwrap();
+		server.write_bytes_to_stream(b"data").unwrap();
+		let len = server.build_packet(&mut buffer).unwrap();
+		client.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(client.poll(), Some(ConnectionEvent::ParcelReceived(5, [0; 4])));
+		assert_eq!(client.poll(), Some(ConnectionEvent::StreamData(4)));
+		assert_eq!(client.poll(), None);
+
+		let len = client.build_ack_packet(&mut buffer).unwrap();
+		server.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(server.poll(), Some(ConnectionEvent::PacketDelivered(packet_id)));
+		assert_eq!(server.poll(), None);
+
+		let packet_id = client.sequence_state().next_packet_id;
+		client.push_reliable_parcel(6).unwrap();
+		client.build_packet(&mut buffer).unwrap();
+		clock.advance(client.current_rto());
+		client.build_packet(&mut buffer).unwrap();
+		assert_eq!(client.poll(), Some(ConnectionEvent::PacketLost(packet_id)));
+		assert_eq!(client.poll(), None);
+
+		clock.advance(TIMEOUT);
+		assert_eq!(client.poll(), Some(ConnectionEvent::StatusChanged(ConnectionStatus::Lost)));
+		assert_eq!(client.poll(), None);
+	}
+
+	#[test]
+	fn acknowledgements_piggyback_on_data_packets() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let mut client = Context::<u32>::accept(1);
+		let mut server = Context::<u32>::accept(1);
+
+		client.push_reliable_parcel(0).unwrap();
+		let len = client.build_packet(&mut buffer).unwrap();
+		server.process_packet(&buffer[.. len]).unwrap();
+		server.push_reliable_parcel(1).unwrap();
+		let len = server.build_packet(&mut buffer).unwrap();
+		assert_eq!(server.build_packet(&mut buffer[len ..]), Ok(0));
+
+		let header = packet::get_header(&buffer[.. len]);
+		assert!(header.signal.is_signal_set(Signal::Synchronized));
+		assert!(header.acknowledges(0.into()));
+		client.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(client.sequence_state().in_flight_count, 0);
+	}
+
+	#[test]
+	fn acknowledgements_are_sent_with_full_window() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut client = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+		let mut server = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+
+		// Distinct preludes send each parcel in a separate packet.
+		for index in 0 ..= MAX_IN_FLIGHT_PACKETS {
+			client.set_prelude([ index, 0, 0, 0, ]);
+			client.push_reliable_parcel(index as u32).unwrap();
+		}
+		for _ in 0 .. MAX_IN_FLIGHT_PACKETS {
+			assert!(client.build_packet(&mut buffer).unwrap() > size_of::<PacketHeader>());
+		}
+		assert_eq!(client.build_packet(&mut buffer), Ok(0));
+
+		server.push_reliable_parcel(7).unwrap();
+		let len = server.build_packet(&mut buffer).unwrap();
+		client.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(client.build_packet(&mut buffer), Ok(size_of::<PacketHeader>()));
+		assert_eq!(packet::get_header(&buffer).ack_packet_id, 0.into());
+		assert_eq!(client.build_packet(&mut buffer), Ok(0));
+
+		let mut control = [0; size_of::<PacketHeader>()];
+		assert_eq!(client.build_ack_packet(&mut control), Ok(size_of::<PacketHeader>()));
+		server.process_packet(&control).unwrap();
+		assert_eq!(server.sequence_state().in_flight_count, 0);
+	}
+
+	#[test]
+	fn acknowledgements_are_delayed() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut client = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+		let mut server = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+		client.set_ack_delay(2, Duration::from_millis(20));
+
+		// A single packet is acknowledged after the delay.
+		server.push_reliable_parcel(1).unwrap();
+		let len = server.build_packet(&mut buffer).unwrap();
+		client.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(client.build_packet(&mut buffer), Ok(0));
+		assert_eq!(client.next_update_deadline(), clock.now() + Duration::from_millis(20));
+		clock.advance(Duration::from_millis(20));
+		assert_eq!(client.build_packet(&mut buffer), Ok(size_of::<PacketHeader>()));
+
+		// Every second packet is acknowledged immediately.
+		for parcel in 2 ..= 3 {
+			server.set_prelude([ parcel as u8, 0, 0, 0, ]);
+			server.push_reliable_parcel(parcel).unwrap();
+			let len = server.build_packet(&mut buffer).unwrap();
+			client.process_packet(&buffer[.. len]).unwrap();
+		}
+		assert_eq!(client.next_update_deadline(), clock.now());
+		assert_eq!(client.build_packet(&mut buffer), Ok(size_of::<PacketHeader>()));
+
+		// Acknowledgements are carried by outgoing data.
+		server.push_reliable_parcel(4).unwrap();
+		let len = server.build_packet(&mut buffer).unwrap();
+		client.process_packet(&buffer[.. len]).unwrap();
+		client.push_reliable_parcel(5).unwrap();
+		let len = client.build_packet(&mut buffer).unwrap();
+		server.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(server.sequence_state().in_flight_count, 0);
+		assert_eq!(client.build_packet(&mut buffer), Ok(0));
+	}
+
+	#[test]
+	fn connections_are_closed_by_either_end() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let mut client = Context::<u32>::accept(1);
+		let mut server = Context::<u32>::accept(1);
+
+		server.push_reliable_parcel(1).unwrap();
+		let len = server.build_packet(&mut buffer).unwrap();
+		client.process_packet(&buffer[.. len]).unwrap();
+
+		let len = client.close(&mut buffer).unwrap();
+		assert_eq!(client.status(), ConnectionStatus::Closed);
+		assert_eq!(client.build_packet(&mut buffer), Err(BuildPacketError::InvalidState));
+		assert_eq!(client.pop_parcel(), Ok((1, [0; 4])));
+
+		server.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(server.status(), ConnectionStatus::Closed);
+		assert_eq!(server.close_reason(), Some(CloseReason::GracefulShutdown));
+		assert_eq!(server.sequence_state().in_flight_count, 0);
+		assert_eq!(server.close(&mut buffer), Err(BuildPacketError::InvalidState));
+	}
+
+	#[test]
+	fn full_parcel_queue_applies_backpressure() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let mut client = Context::<u32>::accept(1);
+		let mut server = Context::<u32>::accept(1);
+		server.set_max_received_parcels(Some(2));
+
+		// A single packet may exceed the limit
+		for parcel in 0 .. 3 {
+			client.push_volatile_parcel(parcel).unwrap();
+		}
+		let len = client.build_packet(&mut buffer).unwrap();
+		server.process_packet(&buffer[.. len]).unwrap();
+		client.push_volatile_parcel(3).unwrap();
+		let len = client.build_packet(&mut buffer).unwrap();
+		server.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(server.dropped_parcel_count(), 1);
+
+		client.push_reliable_parcel(4).unwrap();
+		let len = client.build_packet(&mut buffer).unwrap();
+		let reliable = buffer[.. len].to_vec();
+		server.process_packet(&reliable).unwrap();
+		assert_eq!(server.sequence_state().received_packet_ack_id, PacketIndex::from(u8::MAX));
+
+		for parcel in 0 .. 3 {
+			assert_eq!(server.pop_parcel(), Ok((parcel, [0; 4])));
+		}
+		server.process_packet(&reliable).unwrap();
+		assert_eq!(server.sequence_state().received_packet_ack_id, PacketIndex::from(0));
+		assert_eq!(server.pop_parcel(), Ok((4, [0; 4])));
+		assert_eq!(server.pop_parcel(), Err(ConnectionError::NoPendingParcels));
+		assert_eq!(server.dropped_parcel_count(), 1);
+	}
+
+	#[test]
+	fn reliable_parcels_respect_receive_window() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let mut client = Context::<u32>::accept(1);
+		let mut server = Context::<u32>::accept(1);
+		server.set_max_received_parcels(Some(2));
+
+		let len = server.build_ack_packet(&mut buffer).unwrap();
+		client.process_packet(&buffer[.. len]).unwrap();
+		for parcel in 0 .. 5 {
+			client.push_reliable_parcel(parcel).unwrap();
+		}
+		let len = client.build_packet(&mut buffer).unwrap();
+		assert_eq!(len, size_of::<PacketHeader>() + 2 * 4);
+		assert_eq!(client.build_packet(&mut buffer), Ok(0));
+		server.process_packet(&buffer[.. len]).unwrap();
+
+		// The acknowledgement closes the window
+		let len = server.build_ack_packet(&mut buffer).unwrap();
+		client.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(client.sequence_state().in_flight_count, 0);
+		assert_eq!(client.build_packet(&mut buffer), Ok(0));
+
+		assert_eq!(server.pop_parcel(), Ok((0, [0; 4])));
+		assert_eq!(server.pop_parcel(), Ok((1, [0; 4])));
+		let len = server.build_ack_packet(&mut buffer).unwrap();
+		client.process_packet(&buffer[.. len]).unwrap();
+		let len = client.build_packet(&mut buffer).unwrap();
+		server.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(server.pop_parcel(), Ok((2, [0; 4])));
+		assert_eq!(server.pop_parcel(), Ok((3, [0; 4])));
+		assert_eq!(server.pop_parcel(), Err(ConnectionError::NoPendingParcels));
+	}
+
+	#[test]
+	fn batches_respect_receive_window() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let mut client = Context::<u32>::accept(1);
+		let mut server = Context::<u32>::accept(1);
+		server.set_max_received_parcels(Some(2));
+
+		let len = server.build_ack_packet(&mut buffer).unwrap();
+		client.process_packet(&buffer[.. len]).unwrap();
+		client.push_reliable_parcel(0).unwrap();
+		client.push_reliable_batch(&[ 1, 2, 3, ]).unwrap();
+		let len = client.build_packet(&mut buffer).unwrap();
+		assert_eq!(len, size_of::<PacketHeader>() + 4);
+		assert_eq!(client.build_packet(&mut buffer), Ok(0));
+		server.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(server.pop_parcel(), Ok((0, [0; 4])));
+
+		// A batch larger than the whole window is sent on its own once nothing is in flight
+		let len = server.build_ack_packet(&mut buffer).unwrap();
+		client.process_packet(&buffer[.. len]).unwrap();
+		let len = client.build_packet(&mut buffer).unwrap();
+		assert_eq!(len, size_of::<PacketHeader>() + 3 * 4);
+		assert_eq!(client.sent_packets.back().unwrap().parcel_count, 3);
+		server.process_packet(&buffer[.. len]).unwrap();
+		for parcel in 1 ..= 3 {
+			assert_eq!(server.pop_parcel(), Ok((parcel, [0; 4])));
+		}
+		assert_eq!(server.dropped_parcel_count(), 0);
+	}
+
+	#[test]
+	fn large_receive_window_is_not_advertised_as_unlimited() {
+		let mut server = Context::<u32>::accept(1);
+		assert_eq!(server.receive_window(), u16::MAX);
+		server.set_max_received_parcels(Some(u16::MAX as usize));
+		assert_eq!(server.receive_window(), u16::MAX - 1);
+		server.set_max_received_parcels(Some(usize::MAX));
+		assert_eq!(server.receive_window(), u16::MAX - 1);
+	}
+
+	#[test]
+	fn connection_is_resumed_from_serialized_state() {
+		use crate::byte::Versioned;
+
+		let mut buffer = vec![0; PACKET_SIZE];
+		let mut client = Context::<u32>::accept(4);
+		let mut server = Context::<u32>::accept(4);
+		for parcel in 0 .. 3 {
+			client.push_reliable_parcel(parcel).unwrap();
+			let len = client.build_packet(&mut buffer).unwrap();
+			server.process_packet(&buffer[.. len]).unwrap();
+			server.push_reliable_parcel(parcel).unwrap();
+			let len = server.build_packet(&mut buffer).unwrap();
+			client.process_packet(&buffer[.. len]).unwrap();
+		}
+
+		let state = Versioned(server.resumable_state());
+		let mut bytes = vec![0; state.byte_count()];
+		state.to_bytes(&mut bytes);
+		let (Versioned(state), _) = Versioned::<ResumableState>::from_bytes(&bytes).unwrap();
+		assert_eq!(state, server.resumable_state());
+		let mut server = Context::<u32>::resume(state);
+		assert_eq!(server.status(), ConnectionStatus::Open);
+		assert_eq!(server.connection_id(), Some(4));
+
+		server.push_reliable_parcel(3).unwrap();
+		server.write_bytes_to_stream(b"resumed").unwrap();
+		let len = server.build_packet(&mut buffer).unwrap();
+		client.process_packet(&buffer[.. len]).unwrap();
+		let delivered: Vec<_> = std::iter::from_fn(|| client.pop_parcel().ok()).map(|(parcel, _)| parcel).collect();
+		assert_eq!(delivered, [ 0, 1, 2, 3, ]);
+		let mut stream = [0; 16];
+		assert_eq!(client.read_from_stream(&mut stream), Ok(7));
+
+		client.push_reliable_parcel(3).unwrap();
+		let len = client.build_packet(&mut buffer).unwrap();
+		server.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(server.pop_parcel(), Ok((3, [0; 4])));
+		assert_eq!(server.sequence_state().in_flight_count, 0);
+
+		bytes[2 .. 4].fill(0);
+		let error = Versioned::<ResumableState>::from_bytes(&bytes).unwrap_err();
+		assert_eq!(error.field(), Some("connection_id"));
+	}
+
+	#[test]
+	fn injected_acknowledgements_are_processed() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut context = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+		for index in 0 .. 3 {
+			context.set_prelude([ index, 0, 0, 0, ]);
+			context.push_reliable_parcel(index as u32).unwrap();
+			context.build_packet(&mut buffer).unwrap();
+		}
+		assert_eq!(context.sequence_state().in_flight_count, 3);
+		assert_eq!(context.round_trip_time(), None);
+
+		clock.advance(Duration::from_millis(40));
+		context.inject_ack(1.into());
+		assert_eq!(context.sequence_state().in_flight_count, 2);
+		assert_eq!(context.sequence_state().acknowledged_packet_id, 1.into());
+		assert_eq!(context.round_trip_time(), Some(Duration::from_millis(40)));
+		assert_eq!(context.poll(), Some(ConnectionEvent::PacketDelivered(1.into())));
+
+		context.inject_ack(0.into());
+		context.inject_ack(2.into());
+		assert_eq!(context.sequence_state().in_flight_count, 0);
+	}
+
+	#[test]
+	fn jitter_follows_transit_time_variation() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut client = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+		let mut server = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+
+		// Sent every 20 ms, with transit times of 10, 10 and 15 ms.
+		for (index, transit) in [ 10, 10, 15, ].iter().enumerate() {
+			server.push_volatile_parcel(index as u32).unwrap();
+			let len = server.build_packet(&mut buffer).unwrap();
+			let received_at = clock.now() + Duration::from_millis(*transit);
+			client.process_packet_received_at(&buffer[.. len], received_at).unwrap();
+			assert_eq!(client.jitter().is_some(), index > 0);
+			clock.advance(Duration::from_millis(20));
+		}
+		let jitter = client.jitter().unwrap().as_secs_f64();
+		assert!((jitter - 0.005 / 16.0).abs() < 1e-9);
+
+		// Timestamps wrap around.
+		clock.advance(Duration::from_millis(u16::MAX as u64 + 1));
+		server.push_volatile_parcel(3).unwrap();
+		let len = server.build_packet(&mut buffer).unwrap();
+		assert_eq!(packet::get_header(&buffer[.. len]).timestamp, 60);
+	}
+
+	#[test]
+	fn raw_datagrams_are_received() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let mut client = Context::<u32>::accept(1);
+		let mut server = Context::<u32>::accept(1);
+		client.set_raw_mode(true);
+
+		for payload in [ &b"hello"[..], b"world", ].iter() {
+			let len = server.build_raw_packet(&mut buffer, payload).unwrap();
+			client.process_packet(&buffer[.. len]).unwrap();
+		}
+		let len = server.build_ack_packet(&mut buffer).unwrap();
+		client.process_packet(&buffer[.. len]).unwrap();
+		server.push_reliable_parcel(42).unwrap();
+		let len = server.build_packet(&mut buffer).unwrap();
+		client.process_packet(&buffer[.. len]).unwrap();
+
+		let mut datagrams = vec![ b"earlier".to_vec() ];
+		assert_eq!(client.recv_raw_datagrams(&mut datagrams), 2);
+		assert_eq!(datagrams, [ b"earlier".to_vec(), b"hello".to_vec(), b"world".to_vec() ]);
+		assert_eq!(client.recv_raw_datagrams(&mut datagrams), 0);
+		assert_eq!(client.pop_parcel(), Ok((42, [0; 4])));
+
+		let len = server.build_raw_packet(&mut buffer, b"into").unwrap();
+		client.process_packet(&buffer[.. len]).unwrap();
+		let mut datagram = [0; 4];
+		assert_eq!(client.pop_raw_datagram_into(&mut datagram[.. 3]), Err(SerializationError::BufferOverflow.into()));
+		assert_eq!(client.pop_raw_datagram_into(&mut datagram), Ok(4));
+		assert_eq!(&datagram, b"into");
+		assert_eq!(client.pop_raw_datagram_into(&mut datagram), Err(ConnectionError::NoPendingParcels));
+
+		let payload = vec![0; MAX_SEGMENT_BYTE_COUNT + 1];
+		assert_eq!(server.build_raw_packet(&mut buffer, &payload), Err(BuildPacketError::InsufficientBuffer));
+	}
+
+	#[test]
+	fn qos_class_presets_settings() {
+		let mut context = Context::<u32>::accept(1);
+		context.set_qos_class(QosClass::Bulk);
+		assert!(context.is_pacing_enabled);
+		assert_eq!((context.ack_frequency, context.max_ack_delay), (4, Duration::from_millis(20)));
+
+		context.set_qos_class(QosClass::Realtime);
+		assert!(!context.is_pacing_enabled);
+		assert_eq!((context.ack_frequency, context.max_ack_delay), (1, Duration::ZERO));
+		assert_eq!(context.rto_bounds, (MIN_RETRANSMISSION_TIMEOUT, RETRANSMISSION_TIMEOUT));
+
+		context.set_qos_class(QosClass::Background);
+		context.set_pacing(false);
+		assert!(!context.is_pacing_enabled);
+		assert_eq!(context.current_rto(), RETRANSMISSION_TIMEOUT);
+		context.set_initial_rto(Duration::from_millis(10));
+		assert_eq!(context.current_rto(), Duration::from_millis(200));
+	}
+
+	#[test]
+	fn queue_latency_is_smoothed() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut context = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+		assert_eq!(context.queue_latency(), None);
+
+		context.push_reliable_parcel(0).unwrap();
+		clock.advance(Duration::from_millis(80));
+		context.build_packet(&mut buffer).unwrap();
+		assert_eq!(context.queue_latency(), Some(Duration::from_millis(80)));
+
+		context.push_volatile_parcel(1).unwrap();
+		context.build_packet(&mut buffer).unwrap();
+		assert_eq!(context.queue_latency(), Some(Duration::from_millis(70)));
+	}
+
+	#[test]
+	fn expired_volatile_parcels_are_dropped() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut client = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+		let mut server = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+
+		client.push_volatile_parcel_with_ttl(0, Duration::from_millis(50)).unwrap();
+		client.push_volatile_parcel_with_ttl(1, Duration::from_millis(200)).unwrap();
+		client.push_volatile_parcel(2).unwrap();
+		clock.advance(Duration::from_millis(100));
+		let len = client.build_packet(&mut buffer).unwrap();
+		assert_eq!(client.expired_parcel_count(), 1);
+
+		server.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(server.pop_parcel(), Ok((1, [0; 4])));
+		assert_eq!(server.pop_parcel(), Ok((2, [0; 4])));
+		assert_eq!(server.pop_parcel(), Err(ConnectionError::NoPendingParcels));
+	}
+
+	#[test]
+	fn bandwidth_is_estimated_from_window_and_loss() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut context = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+		assert_eq!(context.estimated_bandwidth(), None);
+
+		context.push_reliable_parcel(0).unwrap();
+		let len = context.build_packet(&mut buffer).unwrap();
+		clock.advance(Duration::from_millis(100));
+		context.inject_ack(0.into());
+		let lossless = context.estimated_bandwidth().unwrap();
+		assert_eq!(lossless, (MAX_IN_FLIGHT_PACKETS as usize * len * 10) as u64);
+
+		context.push_reliable_parcel(1).unwrap();
+		context.build_packet(&mut buffer).unwrap();
+		clock.advance(context.current_rto());
+		assert_eq!(context.build_packet(&mut buffer), Ok(len));
+		let lossy = context.estimated_bandwidth().unwrap();
+		assert!(lossy < lossless);
+		assert!(lossy > lossless * 15 / 16 - 1);
+
+		// The congestion window limits the estimate
+		context.set_congestion_control(Some(CongestionControl::LossBased));
+		let limited = context.estimated_bandwidth().unwrap();
+		let expected = lossy * INITIAL_CONGESTION_WINDOW as u64 / MAX_IN_FLIGHT_PACKETS as u64;
+		assert!(limited.abs_diff(expected) <= 1);
+	}
+
+	#[test]
+	fn data_packets_are_paced() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut context = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+		context.set_pacing(true);
+		assert_eq!(context.pacing_interval(), Duration::ZERO);
+
+		context.push_reliable_parcel(0).unwrap();
+		context.build_packet(&mut buffer).unwrap();
+		clock.advance(Duration::from_millis(130));
+		context.inject_ack(0.into());
+		assert_eq!(context.pacing_interval(), Duration::from_millis(2));
+
+		context.push_volatile_parcel(1).unwrap();
+		assert!(context.build_packet(&mut buffer).unwrap() > 0);
+		context.push_volatile_parcel(2).unwrap();
+		assert_eq!(context.build_packet(&mut buffer).unwrap(), 0);
+		assert_eq!(context.next_update_deadline(), clock.now() + Duration::from_millis(2));
+
+		clock.advance(Duration::from_millis(2));
+		assert_eq!(context.next_update_deadline(), clock.now());
+		assert!(context.build_packet(&mut buffer).unwrap() > 0);
+
+		// A congestion window spreads fewer packets over the round-trip
+		context.set_congestion_control(Some(CongestionControl::LossBased));
+		assert_eq!(context.pacing_interval(), Duration::from_millis(130) / INITIAL_CONGESTION_WINDOW as u32);
+
+		context.set_pacing(false);
+		context.push_volatile_parcel(3).unwrap();
+		assert!(context.build_packet(&mut buffer).unwrap() > 0);
+	}
+
+	#[test]
+	fn close_reason_is_delivered() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let mut client = Context::<u32>::accept(1);
+		let mut server = Context::<u32>::accept(1);
+		assert_eq!(client.close_reason(), None);
+
+		let len = server.close_with_reason(&mut buffer, CloseReason::Kicked, b"cheating").unwrap();
+		assert_eq!(server.close_reason(), Some(CloseReason::Kicked));
+		client.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(client.status(), ConnectionStatus::Closed);
+		assert_eq!(client.close_reason(), Some(CloseReason::Kicked));
+		assert_eq!(client.close_payload(), b"cheating");
+
+		// Close packets without a reason
+		let mut client = Context::<u32>::accept(1);
+		packet::write_header(&mut buffer, server.header(SignalBits::close(0)));
+		client.process_packet(&buffer[.. size_of::<PacketHeader>()]).unwrap();
+		assert_eq!(client.close_reason(), Some(CloseReason::Unspecified));
+		assert_eq!(client.close_payload(), b"");
+
+		for code in 0 ..= u8::MAX {
+			assert_eq!(u8::from(CloseReason::from(code)), code);
+		}
+	}
+
+	#[test]
+	fn unacknowledged_packets_are_resent_on_demand() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut context = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+
+		let mut originals = Vec::new();
+		for index in 0 .. 3 {
+			context.set_prelude([ index, 0, 0, 0, ]);
+			context.push_reliable_parcel(index as u32).unwrap();
+			let len = context.build_packet(&mut buffer).unwrap();
+			originals.push(buffer[.. len].to_vec());
+		}
+		assert_eq!(context.build_packet(&mut buffer), Ok(0));
+
+		clock.advance(Duration::from_millis(10));
+		assert_eq!(context.resend_unacked(), 3);
+		assert_eq!(context.next_update_deadline(), clock.now());
+		for original in originals.iter() {
+			let len = context.build_packet(&mut buffer).unwrap();
+			assert_eq!(without_timestamp(&buffer[.. len]), without_timestamp(original));
+		}
+		assert_eq!(context.build_packet(&mut buffer), Ok(0));
+		assert!(context.sent_packets.iter().all(|packet| packet.retransmission_count == 1));
+		assert_eq!(context.next_update_deadline(), clock.now() + context.current_rto());
+		assert_eq!(context.poll(), None);
+	}
+
+	#[test]
+	fn padding_is_not_delivered() {
+		let mut buffer = vec![0xFF; PACKET_SIZE];
+		let mut client = Context::<u32>::accept(1);
+		let mut server = Context::<u32>::accept(1);
+		client.set_padding(Some(256));
+
+		client.push_reliable_parcel(7).unwrap();
+		assert_eq!(client.build_packet(&mut buffer), Ok(256));
+		assert!(buffer[size_of::<PacketHeader>() + 4 .. 256].iter().all(|&byte| byte == 0));
+		server.process_packet(&buffer[.. 256]).unwrap();
+		assert_eq!(server.pop_parcel(), Ok((7, [0; 4])));
+		assert_eq!(server.pop_parcel(), Err(ConnectionError::NoPendingParcels));
+
+		client.set_padding(Some(2 * PACKET_SIZE));
+		client.write_bytes_to_stream(b"stream").unwrap();
+		assert_eq!(client.build_packet(&mut buffer), Ok(PACKET_SIZE));
+		server.process_packet(&buffer).unwrap();
+		let mut stream = [0; 16];
+		assert_eq!(server.read_from_stream(&mut stream), Ok(6));
+		assert_eq!(&stream[.. 6], b"stream");
+	}
+
+	#[test]
+	fn debug_dump_renders_connection_state() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut context = Context::<u32>::accept_with_clock(3, Box::new(clock.clone()));
+		context.push_reliable_parcel(1).unwrap();
+		context.build_packet(&mut buffer).unwrap();
+		context.push_volatile_parcel(2).unwrap();
+		clock.advance(Duration::from_millis(20));
+
+		let dump = context.debug_dump();
+		assert_eq!(dump, [
+			"status: Open",
+			"connection id: 3",
+			"round-trip time: unmeasured, rto: 1s",
+			"in-flight packets: 1 (next id 1, acknowledged id 255)",
+			&format!("received ack id: 255, mask: {:064b}", 0),
+			"outgoing: 0 reliable parcels, 1 volatile parcels, 0 stream bytes",
+			"incoming: 0 parcels, 0 stream bytes, 0 pending stream segments",
+			"last sent: 20ms ago, last received: 20ms ago",
+		].join("\n"));
+		assert_eq!(context.debug_dump(), dump);
+	}
+
+	#[test]
+	fn connection_is_lost_after_timeout() {
+		let clock = ManualClock::new();
+		let mut context = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+
+		clock.advance(TIMEOUT - Duration::from_millis(1));
+		context.update();
+		assert_eq!(context.status(), ConnectionStatus::Open);
+
+		clock.advance(Duration::from_millis(1));
+		context.update();
+		assert_eq!(context.status(), ConnectionStatus::Lost);
+		assert_eq!(context.loss_reason(), Some(LossReason::TimedOut));
+	}
+
+	#[test]
+	fn next_update_deadline_is_keep_alive_when_idle() {
+		let clock = ManualClock::new();
+		let context = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+
+		let deadline = context.next_update_deadline();
+		assert!(clock.now() + KEEP_ALIVE_INTERVAL / 2 <= deadline && deadline <= clock.now() + KEEP_ALIVE_INTERVAL);
+		assert_eq!(deadline, context.keep_alive_deadline());
+	}
+
+	#[test]
+	fn keep_alives_of_connections_are_spread() {
+		const CONNECTION_COUNT: usize = 100;
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut contexts: Vec<_> = (0 .. CONNECTION_COUNT)
+			.map(|_| Context::<u32>::accept_with_clock(1, Box::new(clock.clone())))
+			.collect();
+
+		// Count keep-alives sent within each tenth of the interval, none in its first half.
+		let mut counts = [0; 10];
+		for count in counts.iter_mut() {
+			clock.advance(KEEP_ALIVE_INTERVAL / 10);
+			for context in contexts.iter_mut() {
+				if context.build_packet(&mut buffer).unwrap() > 0 {
+					*count += 1;
+				}
+			}
+		}
+		assert_eq!(counts.iter().sum::<usize>(), CONNECTION_COUNT);
+		assert!(counts[.. 5].iter().all(|&count| count == 0), "{:?}", counts);
+		assert!(counts.iter().all(|&count| count < CONNECTION_COUNT / 2), "{:?}", counts);
+
+		// Once spread, keep-alives follow the full interval.
+		let phases: Vec<_> = contexts.iter().map(|context| context.last_sent_packet_time).collect();
+		clock.advance(KEEP_ALIVE_INTERVAL);
+		for (context, phase) in contexts.iter().zip(phases) {
+			assert_eq!(context.next_update_deadline(), phase + KEEP_ALIVE_INTERVAL);
+		}
+	}
+
+	#[test]
+	fn next_update_deadline_of_closed_connection_is_now() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut context = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+
+		context.close(&mut buffer).unwrap();
+		assert_eq!(context.status(), ConnectionStatus::Closed);
+		assert_eq!(context.next_update_deadline(), clock.now());
+		clock.advance(Duration::from_secs(1));
+		assert_eq!(context.next_update_deadline(), clock.now());
+	}
+
+	#[test]
+	fn next_update_deadline_accounts_for_retransmission() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut context = Context::<u32>::accept_with_clock(1, Box::new(clock.clone()));
+
+		context.push_reliable_parcel(5).unwrap();
+		assert_eq!(context.next_update_deadline(), clock.now());
+
+		context.build_packet(&mut buffer).unwrap();
+		assert_eq!(context.next_update_deadline(), clock.now() + RETRANSMISSION_TIMEOUT);
+	}
+
+	#[test]
+	fn next_update_deadline_of_pending_connection_is_request_resend() {
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut context = Context::<u32>::pending_with_clock(Box::new(clock.clone()));
+
+		context.set_request_backoff(Duration::from_secs(1), Duration::from_secs(3));
+		let mut previous_interval = Duration::ZERO;
+		for expected in [ 1, 2, 3, ].iter() {
+			context.build_request_packet(&mut buffer, &[]).unwrap();
+			let interval = context.next_update_deadline() - clock.now();
+			let expected = Duration::from_secs(*expected);
+			assert!(interval > previous_interval);
+			assert!(expected * 3 / 4 <= interval && interval <= expected);
+			previous_interval = interval;
+			clock.advance(interval);
+		}
+
+		// The timeout caps the deadline.
+		context.build_request_packet(&mut buffer, &[]).unwrap();
+		assert!(context.next_update_deadline() <= clock.now() + Duration::from_secs(3));
+		clock.advance(Duration::from_secs(5));
+		context.build_request_packet(&mut buffer, &[]).unwrap();
+		assert_eq!(context.next_update_deadline(), context.last_received_packet_time + TIMEOUT);
+	}
+
+	#[test]
+	fn pending_connection_gives_up_after_max_requests() {
+		const MAX_REQUEST_COUNT: u32 = 3;
+		let mut buffer = vec![0; PACKET_SIZE];
+		let clock = ManualClock::new();
+		let mut context = Context::<u32>::pending_with_clock(Box::new(clock.clone()));
+		context.set_max_request_count(Some(MAX_REQUEST_COUNT));
+
+		let mut request_count = 0;
+		while context.status() == ConnectionStatus::Pending {
+			match context.build_request_packet(&mut buffer, &[]) {
+				Ok(_) => request_count += 1,
+				Err(error) => assert_eq!(error, BuildPacketError::InvalidState),
+			}
+			clock.advance(context.next_update_deadline() - clock.now());
+			context.update();
+		}
+		assert_eq!(request_count, MAX_REQUEST_COUNT);
+		assert_eq!(context.loss_reason(), Some(LossReason::TooManyRequests));
+		assert!(clock.now() < context.last_received_packet_time + TIMEOUT);
 	}
 }
@@ -8,6 +8,11 @@ use std::io::Error as IoError;
 pub enum ConnectError {
 	Io(IoError),
 	PayloadTooLarge,
+	/// Failed to build the connection request packet.
+	Build(BuildPacketError),
+	/// The remote address is of a different family (IPv4 or IPv6) than the local address of the
+	/// transmitter.
+	AddressFamilyMismatch,
 }
 
 impl From<IoError> for ConnectError {
@@ -16,11 +21,19 @@ impl From<IoError> for ConnectError {
 	}
 }
 
+impl From<BuildPacketError> for ConnectError {
+	fn from(error: BuildPacketError) -> Self {
+		Self::Build(error)
+	}
+}
+
 impl std::fmt::Display for ConnectError {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
 		match self {
 			ConnectError::Io(error) => error.fmt(f),
 			ConnectError::PayloadTooLarge => write!(f, "payload too large"),
+			ConnectError::Build(error) => error.fmt(f),
+			ConnectError::AddressFamilyMismatch => write!(f, "the remote address family does not match the local one"),
 		}
 	}
 }
@@ -33,6 +46,8 @@ impl PartialEq for ConnectError {
 				_ => false,
 			},
 			Self::PayloadTooLarge => matches!(rhs, Self::PayloadTooLarge),
+			Self::Build(lhs_error) => matches!(rhs, Self::Build(rhs_error) if lhs_error == rhs_error),
+			Self::AddressFamilyMismatch => matches!(rhs, Self::AddressFamilyMismatch),
 		}
 	}
 }
@@ -41,6 +56,7 @@ impl Error for ConnectError {
 	fn source(&self) -> Option<&(dyn Error + 'static)> {
 		match self {
 			ConnectError::Io(error) => Some(error as &dyn Error),
+			ConnectError::Build(error) => Some(error as &dyn Error),
 			_ => None,
 		}
 	}
@@ -55,6 +71,14 @@ pub enum ConnectionError {
 	Serialization(SerializationError),
 	/// The connection was in an invalid state.
 	InvalidState,
+	/// The connection was [lost](super::context::ConnectionStatus::Lost), so no further data may
+	/// be queued, see [`Context::take_unacked_parcels`](super::context::Context::take_unacked_parcels).
+	ConnectionLost,
+	/// The parcel serializes to more than [`Parcel::MAX_BYTE_COUNT`](super::Parcel::MAX_BYTE_COUNT) bytes.
+	ParcelTooLarge,
+	/// The parcels of a batch together serialize to more than
+	/// [`Parcel::MAX_BYTE_COUNT`](super::Parcel::MAX_BYTE_COUNT) bytes.
+	BatchTooLarge,
 }
 
 impl From<SerializationError> for ConnectionError {
@@ -68,6 +92,9 @@ impl std::fmt::Display for ConnectionError {
 		match self {
 			ConnectionError::NoPendingParcels => write!(f, "no pending parcels to pop"),
 			ConnectionError::InvalidState => write!(f, "the connection was in an invalid state for given operation"),
+			ConnectionError::ConnectionLost => write!(f, "the connection was lost"),
+			ConnectionError::ParcelTooLarge => write!(f, "the parcel is larger than its maximum byte count"),
+			ConnectionError::BatchTooLarge => write!(f, "the parcel batch does not fit a single packet"),
 			ConnectionError::Serialization(error) => error.fmt(f),
 		}
 	}
@@ -99,9 +126,116 @@ impl std::fmt::Display for BuildPacketError {
 			Self::InsufficientBuffer => write!(f, "the supplied buffer is too small to hold a useful packet"),
 			Self::InvalidState => write!(f, "the connection is in a state that does not permit sending packets"),
 			Self::Serialization(error) => {
-				write!(f, "serialization error duing packet building: ");
+				write!(f, "serialization error duing packet building: ")?;
 				error.fmt(f)
 			},
 		}
 	}
 }
+
+impl From<SerializationError> for BuildPacketError {
+	fn from(error: SerializationError) -> Self {
+		Self::Serialization(error)
+	}
+}
+
+impl Error for BuildPacketError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			Self::Serialization(error) => Some(error as &dyn Error),
+			_ => None,
+		}
+	}
+}
+
+/// An error during invocation of [`Context::process_packet`](super::context::Context::process_packet).
+#[derive(Debug, PartialEq, Eq, PartialOrd)]
+pub enum ProcessPacketError {
+	/// The packet is not a valid GNet packet.
+	MalformedPacket,
+	/// The packet is a valid GNet packet, but is not associated with this connection.
+	UnexpectedPacket,
+	/// An error during deserialization of a parcel.
+	Serialization(SerializationError),
+	/// The connection was in an invalid state.
+	InvalidState,
+	/// The packet carries a parcel tagged with a different [type id](super::Parcel::TYPE_ID).
+	UnexpectedParcelType(u16),
+	/// The packet is one the connection sent itself, looped back by the network, see
+	/// [`ConnectionStats::looped_back_packet_count`](super::stats::ConnectionStats::looped_back_packet_count).
+	LoopedBackPacket,
+}
+
+impl From<SerializationError> for ProcessPacketError {
+	fn from(error: SerializationError) -> Self {
+		Self::Serialization(error)
+	}
+}
+
+impl std::fmt::Display for ProcessPacketError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+		match self {
+			Self::MalformedPacket => write!(f, "the packet is malformed"),
+			Self::UnexpectedPacket => write!(f, "the packet is not associated with the connection"),
+			Self::InvalidState => write!(f, "the connection is in a state that does not permit receiving packets"),
+			Self::UnexpectedParcelType(type_id) => write!(f, "the packet carries a parcel of unexpected type {}", type_id),
+			Self::LoopedBackPacket => write!(f, "the packet was sent by the connection itself"),
+			Self::Serialization(error) => {
+				write!(f, "serialization error during packet processing: ")?;
+				error.fmt(f)
+			},
+		}
+	}
+}
+
+impl Error for ProcessPacketError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			Self::Serialization(error) => Some(error as &dyn Error),
+			_ => None,
+		}
+	}
+}
+
+/// An error flushing a single connection during
+/// [`ConnectionListener::flush_all`](super::listen::ConnectionListener::flush_all).
+#[derive(Debug)]
+pub enum FlushError {
+	/// Failed to build a packet of the connection.
+	Build(BuildPacketError),
+	/// Failed to send a built packet of the connection.
+	Io(IoError),
+}
+
+impl From<BuildPacketError> for FlushError {
+	fn from(error: BuildPacketError) -> Self {
+		Self::Build(error)
+	}
+}
+
+impl From<IoError> for FlushError {
+	fn from(error: IoError) -> Self {
+		Self::Io(error)
+	}
+}
+
+impl std::fmt::Display for FlushError {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
+		match self {
+			Self::Build(error) => error.fmt(f),
+			Self::Io(error) => {
+				write!(f, "failed to send a packet: ")?;
+				error.fmt(f)
+			},
+		}
+	}
+}
+
+impl Error for FlushError {
+	fn source(&self) -> Option<&(dyn Error + 'static)> {
+		match self {
+			Self::Build(error) => Some(error as &dyn Error),
+			Self::Io(error) => Some(error as &dyn Error),
+		}
+	}
+}
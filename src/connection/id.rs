@@ -11,7 +11,7 @@ pub struct OutOfIdsError();
 
 /// Manager for [`ConnectionIds`](ConnectionId). Responsible for making sure
 /// there are no [`Connections`](super::connection::Connection) that share ids.
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct Allocator {
 	/// Largest ConnectionId in use.
 	last_id: ConnectionId,
@@ -52,15 +52,6 @@ impl Allocator {
 	}
 }
 
-impl Default for Allocator {
-	fn default() -> Self {
-		Self {
-			last_id: 0,
-			free_ids: Vec::new(),
-		}
-	}
-}
-
 impl std::fmt::Display for OutOfIdsError {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
 		write!(f, "Connection Id Allocator ran out of ids!")
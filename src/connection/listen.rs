@@ -1,159 +1,1100 @@
 //! Server-side connection acceptors.
 //!
-//! 
-
-#![cfg_attr(debug_assertions, allow(dead_code, unused_imports, unused_variables))]
+//! A [`ConnectionListener`](ConnectionListener) owns a [transmitter](Transmit) and the
+//! [contexts](Context) of all connections accepted through it. Received datagrams are dispatched
+//! to the connection they belong to, while connection requests are queued until the application
+//! decides whether to accept them.
+//!
+//! ## Multiple transmitters
+//! A listener may receive from several transmitters (for example sockets sharded with
+//! `SO_REUSEPORT`, or bound to different interfaces or ports), all feeding the same set of
+//! connections. Packets are routed by their connection id regardless of the transmitter they
+//! arrived on. Packets of a connection are sent through the transmitter its latest processed
+//! packet arrived on, initially the one its connection request arrived on.
+//!
+//! On Linux, [`bind_sharded`](ConnectionListener::bind_sharded) binds several sockets sharing a
+//! port. The kernel keeps routing datagrams of a remote address to the same socket, so each
+//! connection stays pinned to the socket its request arrived on.
+//!
+//! ## Half-open connections
+//! A connection request from the address of an open connection carrying a different handshake
+//! implies the other end restarted and forgot the connection. The request is queued as usual and
+//! once it is accepted, the old connection is deemed [lost](ConnectionStatus::Lost) with
+//! [`RemoteRestarted`](LossReason::RemoteRestarted) reason. A repeated request with the same
+//! handshake means the accept packet was lost, so it is answered again instead.
+//!
+//! Source addresses of requests are not validated, so anyone able to spoof the address of a client
+//! may send such a request. Until it is accepted the old connection is left intact, applications
+//! that accept requests without authenticating their payload however let a single spoofed
+//! datagram replace the connection of any client.
+//!
+//! ## Connectionless datagrams
+//! Received datagrams that belong to no connection and are not connection requests are queued as
+//! unconnected datagrams, which the application may [pop](ConnectionListener::pop_unconnected)
+//! and answer with [`send_unconnected`](ConnectionListener::send_unconnected). This allows
+//! stateless exchanges, such as server discovery or pings, to share the transmitters of the
+//! listener.
+//!
+//! ## Blocked addresses
+//! Datagrams from [blocked](ConnectionListener::block_addr) IP addresses are discarded right
+//! after they are received, before their headers are parsed, mitigating floods from abusive
+//! sources. Connections already accepted from a blocked address stop receiving packets, so they
+//! time out unless [closed](ConnectionListener::close_connection) explicitly.
+//!
+//! ## Recent remotes
+//! For monitoring, the listener tracks the source addresses of datagrams received within the
+//! last [`RECENT_REMOTE_WINDOW`](RECENT_REMOTE_WINDOW), whether or not they belong to a
+//! connection, see [`recent_remote_count`](ConnectionListener::recent_remote_count). A surge of
+//! distinct sources hints at scanning or flooding. At most
+//! [`MAX_RECENT_REMOTES`](MAX_RECENT_REMOTES) addresses are tracked, so the tracking itself can
+//! not exhaust memory.
+//!
+//! ## Expired requests
+//! Queued connection requests the application has not yet decided on expire once they are older
+//! than the [request timeout](ConnectionListener::set_request_timeout), as by then the requesting
+//! end has given up. Expired requests are dropped by
+//! [`prune_expired`](ConnectionListener::prune_expired), which servers should call periodically so
+//! that half-open requests do not accumulate. At most
+//! [`MAX_PENDING_REQUESTS`](MAX_PENDING_REQUESTS) requests are queued, further ones are dropped
+//! until the queue is drained, so a flood of requests can not exhaust memory in the meantime.
+//!
+//! ## Shutdown
+//! A [shut down](ConnectionListener::shutdown) listener closes all of its connections with the
+//! [`GracefulShutdown`](CloseReason::GracefulShutdown) reason and rejects every further
+//! connection request with the same reason, so that clients learn right away that they should
+//! not retry, rather than once their requests time out.
+//!
+//! ## Source address changes
+//! Connections are bound to the source address of their request, packets carrying the id of a
+//! connection that arrive from any other address are dropped rather than migrating the
+//! connection. Such packets hint at a NAT rebinding or an attempt to hijack the connection, so
+//! they are counted per connection, see
+//! [`source_mismatch_count`](ConnectionListener::source_mismatch_count), for the application to
+//! decide whether to flag or re-establish the connection.
+//!
+//! ## Dropped datagrams
+//! Every filter of the receive path that discards a datagram counts it, see
+//! [`drop_counts`](ConnectionListener::drop_counts), so that it is possible to tell which filter
+//! ate the packets of a misbehaving connection.
+//!
+//! ## Fair receiving
+//! By default every received packet is processed right away, so a single chatty connection may
+//! take up most of the work of a [`receive`](ConnectionListener::receive) call. With a
+//! [per-connection limit](ConnectionListener::set_max_packets_per_connection) set, packets of a
+//! connection past the limit are deferred to the following calls instead, which process deferred
+//! packets first. At most [`MAX_DEFERRED_PACKETS`](MAX_DEFERRED_PACKETS) packets are deferred per
+//! connection, further ones are dropped, to be re-sent by the remote end if reliable. Whether work
+//! remains after a call is told by [`deferred_packet_count`](ConnectionListener::deferred_packet_count).
 
 mod accept;
-// #[cfg(test)]
-// pub mod test;
+#[cfg(test)]
+mod test;
 
 pub use accept::*;
 
-use crate::endpoint::{Demux, Transmit, TransmitError, Open,};
+use crate::byte::ByteSerialize;
+use crate::endpoint::{Transmit, TransmitError};
 
-use super::connection::{Connection, ConnectionStatus};
-use super::id::{ConnectionId, Allocator as ConnectionIdAllocator,};
-use super::packet;
+use super::context::{self, Context, CloseReason, ConnectionEvent, ConnectionStatus, LossReason};
+use super::error::FlushError;
+use super::id::{ConnectionId, Allocator as ConnectionIdAllocator};
+use super::pool::{BufferPool, HeapPool};
+use super::packet::{self, DataPrelude, PacketHeader, Signal, MAX_SEGMENT_BYTE_COUNT};
 use super::Parcel;
 
-use std::io::Error as IoError;
-use std::marker::PhantomData;
-use std::net::{ToSocketAddrs, SocketAddr,};
-use std::time::Instant;
-use std::sync::{Arc, Mutex,};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io::{Error as IoError, ErrorKind as IoErrorKind};
+use std::mem::{self, size_of};
+use std::net::{IpAddr, SocketAddr};
+#[cfg(target_os = "linux")]
+use std::net::UdpSocket;
+use std::ops::Range;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Maximum number of unconnected datagrams queued by a listener, further ones are dropped.
+pub const MAX_UNCONNECTED_DATAGRAMS: usize = 256;
+
+/// Maximum number of connection requests queued by a listener, further ones are dropped.
+pub const MAX_PENDING_REQUESTS: usize = 256;
+
+/// Span of time a source address is deemed recent for after its latest received datagram.
+pub const RECENT_REMOTE_WINDOW: Duration = Duration::from_secs(60);
+
+/// Maximum number of recent source addresses tracked by a listener.
+pub const MAX_RECENT_REMOTES: usize = 4096;
+
+/// Shortest span of time between two sweeps of expired recent source addresses, once their
+/// maximum number is reached.
+const RECENT_REMOTE_SWEEP_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Maximum number of packets deferred per connection, further ones are dropped.
+pub const MAX_DEFERRED_PACKETS: usize = 256;
+
+/// Maximum ratio of the length of an accept packet to the length of the request it answers.
+///
+/// Source addresses of requests are not validated, so a listener sending much more than it
+/// received could be abused to flood a spoofed address. Requesting ends expecting a large
+/// [accept payload](ConnectionListener::try_accept_with_payload) may pad their request payload.
+pub const MAX_ACCEPT_AMPLIFICATION: usize = 3;
+
+/// Numbers of received datagrams a listener dropped, by the reason they were dropped for.
+///
+/// Obtained with [`ConnectionListener::drop_counts`](ConnectionListener::drop_counts).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct DropCounts {
+	/// Datagrams the transmitter reported as [malformed](TransmitError::MalformedPacket).
+	pub malformed: u64,
+	/// Datagrams from [blocked](ConnectionListener::block_addr) addresses.
+	pub blocked: u64,
+	/// Connection requests with a payload over the
+	/// [limit](ConnectionListener::set_max_request_payload).
+	pub oversized_request: u64,
+	/// Connection requests with [`MAX_PENDING_REQUESTS`](MAX_PENDING_REQUESTS) already queued.
+	pub request_overflow: u64,
+	/// Packets of a connection received from another source address than the one of the
+	/// connection, see [`source_mismatch_count`](ConnectionListener::source_mismatch_count).
+	pub source_mismatch: u64,
+	/// Packets their connection failed to process, for example malformed ones or ones of a closed
+	/// connection, see [`Context::process_packet`](Context::process_packet).
+	pub unprocessed: u64,
+	/// Packets over the [per-connection limit](ConnectionListener::set_max_packets_per_connection)
+	/// with [`MAX_DEFERRED_PACKETS`](MAX_DEFERRED_PACKETS) already deferred.
+	pub deferred_overflow: u64,
+	/// Datagrams belonging to no connection with
+	/// [`MAX_UNCONNECTED_DATAGRAMS`](MAX_UNCONNECTED_DATAGRAMS) already queued.
+	pub unconnected_overflow: u64,
+}
+
+/// Tuning applied to accepted connections, see
+/// [`ConnectionListener::set_connection_setup`](ConnectionListener::set_connection_setup).
+pub type ConnectionSetup<P> = Box<dyn Fn(&mut Context<P>) + Send>;
 
 /// A listener passively listens for new connections.
 ///
-/// The new connections are pending, letting the application
-/// decide whether to accept a particular new connection.
-#[derive(Debug)]
-pub struct ConnectionListener<T, P> where
-	T: Transmit,
-	P: Parcel,
-{
-	endpoint: E,
+/// The new connections are pending, letting the application decide whether to accept a
+/// particular new connection.
+///
+/// # Note
+/// The transmitters should be non-blocking, as [`receive`](Self::receive) reads datagrams until
+/// there are no more pending ones.
+pub struct ConnectionListener<T: Transmit, P: Parcel> {
+	transmitters: Vec<T>,
 	id_allocator: ConnectionIdAllocator,
-	packet_buffer: Vec<u8>,
-	request_packets: Vec<(usize, SocketAddr)>,
-	_message_type: PhantomData<P>,
+	connections: HashMap<ConnectionId, Remote<P>>,
+	requests: VecDeque<Request>,
+	/// Age after which queued connection requests are expired.
+	request_timeout: Duration,
+	/// Received datagrams that belong to no connection, along with their sources.
+	unconnected: VecDeque<(Vec<u8>, SocketAddr)>,
+	/// Addresses whose datagrams are discarded.
+	blocked_addrs: HashSet<IpAddr>,
+	/// Source addresses of received datagrams, along with the moments of their latest datagrams.
+	recent_remotes: HashMap<SocketAddr, Instant>,
+	/// Moment before which no recent source address can expire, so tracking is not swept.
+	recent_remotes_sweep_at: Option<Instant>,
+	/// Largest payload of a connection request that is queued.
+	max_request_payload: usize,
+	max_packets_per_connection: Option<usize>,
+	is_shut_down: bool,
+	drop_counts: DropCounts,
+	buffer_pool: Arc<dyn BufferPool>,
+	/// Tuning applied to every accepted connection.
+	connection_setup: Option<ConnectionSetup<P>>,
+	accept_handler: Option<AcceptHandler<P>>,
+	buffer: Vec<u8>,
 }
 
-impl<E, P> ConnectionListener<E, P> where
-	E: Transmit + Demux<ConnectionId> + Clone,
-	P: Parcel,
-{
-	// TODO: https://github.com/rust-lang/rust/issues/8995
-	// type AcceptFn = FnOnce(SocketAddr, &[u8]) -> AcceptDecision;
+/// A received connection request, awaiting the decision of the application.
+struct Request {
+	packet: Vec<u8>,
+	addr: SocketAddr,
+	/// Index of the transmitter the request arrived on.
+	transmitter_index: usize,
+	/// Moment the latest copy of the request was received.
+	received_at: Instant,
+}
 
-	/// Construct a new listener using provided endpoint.
-	#[inline]
-	pub fn new(endpoint: E) -> Self {
+/// An accepted connection along with its remote end.
+struct Remote<P: Parcel> {
+	context: Context<P>,
+	addr: SocketAddr,
+	/// Handshake id of the request the connection was accepted with.
+	handshake_id: DataPrelude,
+	/// Payload attached to the accept packet, sent again if the request is repeated.
+	accept_payload: Vec<u8>,
+	/// Index of the transmitter the packets of the connection are sent through.
+	transmitter_index: usize,
+	/// Packets received past the per-connection limit, to be processed by following calls.
+	deferred: VecDeque<DeferredPacket>,
+	/// Number of packets processed during the current receive call.
+	processed_count: usize,
+	/// Number of packets of the connection received from other source addresses.
+	source_mismatch_count: u64,
+	/// Latest other source address packets of the connection were received from.
+	last_mismatched_source: Option<SocketAddr>,
+}
+
+/// A received packet of an accepted connection, deferred to a following receive call.
+struct DeferredPacket {
+	packet: Vec<u8>,
+	/// Index of the transmitter the packet arrived on.
+	transmitter_index: usize,
+	received_at: Instant,
+}
+
+impl<T: Transmit, P: Parcel> ConnectionListener<T, P> {
+	/// Construct a new listener using provided transmitter.
+	pub fn new(transmitter: T) -> Self {
+		Self::with_transmitters(vec![transmitter])
+	}
+
+	/// Construct a new listener receiving from all provided transmitters.
+	///
+	/// # Panics
+	/// If no transmitters are provided.
+	pub fn with_transmitters(transmitters: Vec<T>) -> Self {
+		assert!(!transmitters.is_empty(), "a listener requires at least one transmitter");
+		let buffer_length = transmitters.iter().map(Transmit::max_datagram_length).max().unwrap();
 		Self {
-			endpoint,
+			transmitters,
 			id_allocator: Default::default(),
-			packet_buffer: Vec::with_capacity(E::MAX_FRAME_LENGTH),
-			request_packets: Vec::new(),
-			_message_type: PhantomData,
+			connections: HashMap::new(),
+			requests: VecDeque::new(),
+			request_timeout: context::TIMEOUT,
+			unconnected: VecDeque::new(),
+			blocked_addrs: HashSet::new(),
+			recent_remotes: HashMap::new(),
+			recent_remotes_sweep_at: None,
+			max_request_payload: MAX_SEGMENT_BYTE_COUNT,
+			max_packets_per_connection: None,
+			is_shut_down: false,
+			drop_counts: Default::default(),
+			buffer_pool: Arc::new(HeapPool),
+			connection_setup: None,
+			accept_handler: None,
+			buffer: vec![0; buffer_length],
+		}
+	}
+
+	/// Get the transmitters used by the listener.
+	#[inline]
+	pub fn transmitters(&self) -> &[T] {
+		&self.transmitters
+	}
+
+	/// Receive all pending datagrams of every transmitter.
+	///
+	/// Packets of accepted connections are processed by the connection they belong to, while
+	/// connection requests are queued to be [accepted](Self::try_accept), up to
+	/// [`MAX_PENDING_REQUESTS`](MAX_PENDING_REQUESTS) of them. Datagrams that belong to
+	/// no connection are queued as [unconnected](Self::pop_unconnected), up to
+	/// [`MAX_UNCONNECTED_DATAGRAMS`](MAX_UNCONNECTED_DATAGRAMS) of them.
+	///
+	/// # Returns
+	/// Number of received datagrams, not including ones from
+	/// [blocked addresses](Self::block_addr).
+	///
+	/// # Fairness
+	/// Packets [deferred](Self::set_max_packets_per_connection) by previous calls are processed
+	/// first, before any new datagrams are received. They are not included in the returned number.
+	///
+	/// # Accept handler
+	/// With an [accept handler](Self::set_accept_handler) set, the queued connection requests are
+	/// decided once all datagrams are received, invoking the handler with every accepted
+	/// connection.
+	pub fn receive(&mut self) -> Result<usize, TransmitError> {
+		let max_packets_per_connection = self.max_packets_per_connection;
+		let buffer_pool = &self.buffer_pool;
+		for remote in self.connections.values_mut() {
+			remote.processed_count = 0;
+			let deferred_count = remote.deferred.len();
+			let count = max_packets_per_connection.map_or(deferred_count, |max| max.min(deferred_count));
+			for _ in 0 .. count {
+				let deferred = remote.deferred.pop_front().unwrap();
+				if !remote.process_packet(&deferred.packet, deferred.transmitter_index, deferred.received_at) {
+					self.drop_counts.unprocessed += 1;
+				}
+				buffer_pool.release(deferred.packet);
+			}
 		}
+
+		let mut datagram_count = 0;
+		for transmitter_index in 0 .. self.transmitters.len() {
+			datagram_count += self.receive_from(transmitter_index)?;
+		}
+		self.accept_with_handler();
+		Ok(datagram_count)
 	}
 
 	/// Attempt to accept an incoming connection using provided predicate.
 	///
-	/// Will pop a single connection request from the endpoint, validate the packet and
-	/// invoke the predicate if the request is valid. If the predicate returns
-	/// [`AcceptDecision::Allow`](AcceptDecision::Allow) the function will return a newly
-	/// established [`Connection`](super::Connection), otherwise it will return
-	/// [`AcceptError::PredicateFail`](AcceptError::PredicateFail).
+	/// Will pop a single queued connection request and invoke the predicate with its source and
+	/// payload. If the predicate returns [`AcceptDecision::Allow`](AcceptDecision::Allow) the
+	/// request is answered and the id of the newly established connection is returned, otherwise
+	/// [`AcceptError::PredicateFail`](AcceptError::PredicateFail) is returned.
 	///
 	/// ## Notes
-	/// Does NOT block the calling thread, returning
-	/// [`AcceptError::NoPendingConnections`](AcceptError::NoPendingConnections)
-	/// if there are no pending connections remaining.
+	/// Does NOT receive any datagrams, returning
+	/// [`AcceptError::NoPendingConnections`](AcceptError::NoPendingConnections) if there are no
+	/// requests queued by [`receive`](Self::receive).
 	pub fn try_accept<F: FnOnce(SocketAddr, &[u8]) -> AcceptDecision>(
 		&mut self,
 		predicate: F,
-	) -> Result<Connection<E, P>, AcceptError> {
-		if self.request_packets.is_empty() {
-			self.recv_connectionless_packets()?;
-			if self.request_packets.is_empty() {
-				return Err(AcceptError::NoPendingConnections)
-			}
-		}
-		let (len, src) = self.request_packets.pop().unwrap();
-		let packet = &self.packet_buffer[self.packet_buffer.len() - len ..];
-		match predicate(src, packet::get_parcel_segment(packet)) {
+	) -> Result<ConnectionId, AcceptError> {
+		self.try_accept_with_payload(|src, payload, _| predicate(src, payload))
+	}
+
+	/// Attempt to accept an incoming connection using provided predicate, which may attach a
+	/// payload to the answer.
+	///
+	/// Same as [`try_accept`](Self::try_accept), but the predicate is also given a buffer to
+	/// write a payload into, for example an assigned player slot, sent along with the accept
+	/// packet to save a round trip. The requesting end reads it with
+	/// [`Context::accept_payload`](Context::accept_payload). The payload is ignored unless the
+	/// request is allowed.
+	///
+	/// The accept packet may not exceed [`MAX_SEGMENT_BYTE_COUNT`](MAX_SEGMENT_BYTE_COUNT) bytes
+	/// of data, nor the [maximum datagram length](Transmit::max_datagram_length) of the
+	/// transmitter that received the request, nor be more than
+	/// [`MAX_ACCEPT_AMPLIFICATION`](MAX_ACCEPT_AMPLIFICATION) times longer than the request.
+	/// Otherwise the request is dropped unanswered and
+	/// [`AcceptError::PayloadTooLarge`](AcceptError::PayloadTooLarge) is returned.
+	pub fn try_accept_with_payload<F: FnOnce(SocketAddr, &[u8], &mut Vec<u8>) -> AcceptDecision>(
+		&mut self,
+		predicate: F,
+	) -> Result<ConnectionId, AcceptError> {
+		let Request { packet: request, addr: src, transmitter_index, .. } = self.requests
+			.pop_front()
+			.ok_or(AcceptError::NoPendingConnections)?;
+		let handshake_id = packet::get_header(&request).prelude;
+		let transmitter = &self.transmitters[transmitter_index];
+		let mut accept_payload = Vec::new();
+		let decision = predicate(src, packet::get_parcel_segment(&request), &mut accept_payload);
+		let max_accept_length = request.len() * MAX_ACCEPT_AMPLIFICATION;
+		self.buffer_pool.release(request);
+		match decision {
 			AcceptDecision::Allow => {
-				Ok(Connection::opened(
-					self.endpoint.clone(),
-					self.id_allocator.allocate()?,
-					src,
-				))
+				let data_byte_count = ACCEPT_BYTE_COUNT + accept_payload.len();
+				let max_accept_length = max_accept_length.min(transmitter.max_datagram_length());
+				if data_byte_count > MAX_SEGMENT_BYTE_COUNT || size_of::<PacketHeader>() + data_byte_count > max_accept_length {
+					return Err(AcceptError::PayloadTooLarge)
+				}
+				let connection_id = self.id_allocator.allocate()?;
+				if let Err(error) = send_accept(transmitter, &mut self.buffer, handshake_id, connection_id, &accept_payload, src) {
+					self.id_allocator.free(connection_id);
+					return Err(error.into())
+				}
+				let restarted = self.connections
+					.values_mut()
+					.find(|remote| remote.addr == src && remote.context.status() == ConnectionStatus::Open);
+				if let Some(remote) = restarted {
+					remote.context.mark_lost(LossReason::RemoteRestarted);
+				}
+				let mut context = Context::accept_handshake(connection_id, handshake_id);
+				context.set_buffer_pool(self.buffer_pool.clone());
+				if let Some(setup) = &self.connection_setup {
+					setup(&mut context);
+				}
+				self.connections.insert(connection_id, Remote {
+					context,
+					addr: src,
+					handshake_id,
+					accept_payload,
+					transmitter_index,
+					deferred: VecDeque::new(),
+					processed_count: 0,
+					source_mismatch_count: 0,
+					last_mismatched_source: None,
+				});
+				Ok(connection_id)
 			},
 			AcceptDecision::Reject => {
-				todo!("Send reject packet")
+				packet::write_header(&mut self.buffer, PacketHeader::reject_connection(handshake_id, 0));
+				transmitter.send_to(&self.buffer[.. size_of::<PacketHeader>()], src)?;
+				Err(AcceptError::PredicateFail)
+			},
+			AcceptDecision::RejectWithReason(reason) => {
+				send_reject(transmitter, &mut self.buffer, handshake_id, reason, src)?;
+				Err(AcceptError::PredicateFail)
 			},
 			AcceptDecision::Ignore => Err(AcceptError::PredicateFail),
 		}
 	}
 
-	/// Inform the listener about a connection that was closed.
-	/// 
-	/// Note that the connection_id must have been assigned by the listener itself, in other
-	/// words the connection closed must have come from the result of
-	/// [`try_accept()`](ConnectionListener::try_accept).
-	pub fn connection_closed(&mut self, connection_id: ConnectionId) {
-		self.id_allocator.free(connection_id);
-		self.endpoint.block(connection_id);
-	}
-
-	/// Receive packets on the endpoint and populate packet buffer with connectionless ones.
-	fn recv_connectionless_packets(&mut self) -> Result<(), TransmitError> {
-		assert!(self.request_packets.is_empty());
-		self.packet_buffer.resize(E::MAX_FRAME_LENGTH, 0);
-		recv_filter_and_demux_all(&mut self.endpoint, &mut self.packet_buffer)?;
-
-		let packet_buffer = &mut self.packet_buffer;
-		let request_packets = &mut self.request_packets;
-		let (dgram_count, byte_count) = self.endpoint.get_buffered_counts(0);
-		packet_buffer.reserve(byte_count);
-		request_packets.reserve(dgram_count);
-		self.endpoint.process(0, |(dgram, src)| {
-			request_packets.push((dgram.len(), src));
-			packet_buffer.extend_from_slice(dgram);
+	/// Set the handler accepting connections as their requests are [received](Self::receive).
+	///
+	/// Unset by default, in which case requests are queued until the application polls them with
+	/// [`try_accept`](Self::try_accept). With a handler set, every request is decided right after
+	/// it is received, and the handler is notified of the accepted connection, after any
+	/// [connection setup](Self::set_connection_setup), so that per-client state may be set up
+	/// immediately. Requests that fail to be answered, for example as the listener ran out of
+	/// connection ids, are dropped as if lost, the requesting end re-sends them.
+	#[inline]
+	pub fn set_accept_handler(&mut self, handler: Option<AcceptHandler<P>>) {
+		self.accept_handler = handler;
+	}
+
+	/// Decide all queued connection requests with the accept handler, if one is set.
+	fn accept_with_handler(&mut self) {
+		let mut handler = match self.accept_handler.take() {
+			Some(handler) => handler,
+			None => return,
+		};
+		loop {
+			match self.try_accept(|addr, payload| handler.decide(addr, payload)) {
+				Ok(connection_id) => {
+					let remote = self.connections.get_mut(&connection_id).unwrap();
+					handler.on_accept(connection_id, &mut remote.context);
+				},
+				Err(AcceptError::NoPendingConnections) => break,
+				Err(_) => {},
+			}
+		}
+		self.accept_handler = Some(handler);
+	}
+
+	/// Get the source addresses of queued connection requests, along with the moments they were
+	/// received, in the order they are [accepted](Self::try_accept) in.
+	pub fn pending_requests(&self) -> impl Iterator<Item = (SocketAddr, Instant)> + '_ {
+		self.requests.iter().map(|request| (request.addr, request.received_at))
+	}
+
+	/// Set the age after which queued connection requests are expired.
+	///
+	/// Defaults to the connection [`TIMEOUT`](context::TIMEOUT), the span of time the requesting
+	/// end waits for an answer. Repeated requests refresh the age of the queued one.
+	#[inline]
+	pub fn set_request_timeout(&mut self, timeout: Duration) {
+		self.request_timeout = timeout;
+	}
+
+	/// Drop queued connection requests older than the
+	/// [request timeout](Self::set_request_timeout).
+	///
+	/// Unlike the timeout of accepted connections, which is tracked by their contexts, queued
+	/// requests are only expired by this method.
+	///
+	/// # Returns
+	/// Number of dropped requests.
+	pub fn prune_expired(&mut self) -> usize {
+		let now = Instant::now();
+		let request_count = self.requests.len();
+		let timeout = self.request_timeout;
+		let buffer_pool = &self.buffer_pool;
+		self.requests.retain_mut(|request| {
+			let is_expired = now.saturating_duration_since(request.received_at) >= timeout;
+			if is_expired {
+				buffer_pool.release(mem::take(&mut request.packet));
+			}
+			!is_expired
 		});
+		request_count - self.requests.len()
+	}
+
+	/// Pop the next received datagram that belongs to no connection, along with its source.
+	#[inline]
+	pub fn pop_unconnected(&mut self) -> Option<(Vec<u8>, SocketAddr)> {
+		self.unconnected.pop_front()
+	}
 
-		Ok(())
+	/// Send a datagram outside of any connection, through the first transmitter.
+	///
+	/// The data is sent as is, without a packet header, so it may follow any protocol the
+	/// receiving end understands.
+	#[inline]
+	pub fn send_unconnected(&self, data: &[u8], addr: SocketAddr) -> Result<usize, IoError> {
+		self.transmitters[0].send_to(data, addr)
+	}
+
+	/// Set the largest payload of a connection request the listener accepts.
+	///
+	/// Requests declaring a larger payload in their header are discarded when received, before
+	/// they are queued, so the application never processes them. Only limited by the packet format
+	/// by default.
+	#[inline]
+	pub fn set_max_request_payload(&mut self, byte_count: usize) {
+		self.max_request_payload = byte_count;
+	}
+
+	/// Set the maximum number of packets processed per connection by a single
+	/// [`receive`](Self::receive) call, or `None` for no limit, which is the default.
+	///
+	/// Packets of a connection past the limit are deferred to following calls, so that one chatty
+	/// connection can not delay processing of the others. Deferred packets keep the moment they
+	/// were received at, see [`Context::process_packet_received_at`](Context::process_packet_received_at).
+	#[inline]
+	pub fn set_max_packets_per_connection(&mut self, max: Option<usize>) {
+		self.max_packets_per_connection = max;
+	}
+
+	/// Set the pool queued datagrams are allocated from, also used by connections accepted from now
+	/// on, see [`Context::set_buffer_pool`](Context::set_buffer_pool).
+	///
+	/// Defaults to the [`HeapPool`](HeapPool). Buffers of connection requests are released to the
+	/// pool once [decided on](Self::try_accept), while the application may release
+	/// [unconnected datagrams](Self::pop_unconnected) once processed.
+	#[inline]
+	pub fn set_buffer_pool(&mut self, pool: Arc<dyn BufferPool>) {
+		self.buffer_pool = pool;
+	}
+
+	/// Set the tuning applied to every connection accepted from now on.
+	///
+	/// The setup is invoked with the context of each accepted connection before any of its packets
+	/// are processed, keeping the tuning of all connections of a server in one place. It runs after
+	/// the [buffer pool](Self::set_buffer_pool) is set, so it may override it. Individual
+	/// connections may be tuned further once accepted, through
+	/// [`connection_mut`](Self::connection_mut).
+	#[inline]
+	pub fn set_connection_setup(&mut self, setup: Option<ConnectionSetup<P>>) {
+		self.connection_setup = setup;
+	}
+
+	/// Discard all further datagrams received from provided IP address.
+	///
+	/// Returns `false` if the address was already blocked.
+	#[inline]
+	pub fn block_addr(&mut self, addr: IpAddr) -> bool {
+		self.blocked_addrs.insert(addr)
+	}
+
+	/// Stop discarding datagrams received from provided IP address.
+	///
+	/// Returns `false` if the address was not blocked.
+	#[inline]
+	pub fn unblock_addr(&mut self, addr: IpAddr) -> bool {
+		self.blocked_addrs.remove(&addr)
+	}
+
+	/// Check whether datagrams from provided IP address are discarded.
+	#[inline]
+	pub fn is_addr_blocked(&self, addr: IpAddr) -> bool {
+		self.blocked_addrs.contains(&addr)
+	}
+
+	/// Get the number of distinct source addresses datagrams were received from within the last
+	/// [`RECENT_REMOTE_WINDOW`](RECENT_REMOTE_WINDOW), including ones that never requested a
+	/// connection.
+	///
+	/// Saturates at [`MAX_RECENT_REMOTES`](MAX_RECENT_REMOTES). Datagrams from
+	/// [blocked addresses](Self::block_addr) are not accounted for.
+	pub fn recent_remote_count(&self) -> usize {
+		let now = Instant::now();
+		self.recent_remotes
+			.values()
+			.filter(|&&last_seen| now.saturating_duration_since(last_seen) < RECENT_REMOTE_WINDOW)
+			.count()
+	}
+
+	/// Get the context of an accepted connection.
+	#[inline]
+	pub fn connection(&self, connection_id: ConnectionId) -> Option<&Context<P>> {
+		self.connections.get(&connection_id).map(|remote| &remote.context)
+	}
+
+	/// Get the mutable context of an accepted connection.
+	#[inline]
+	pub fn connection_mut(&mut self, connection_id: ConnectionId) -> Option<&mut Context<P>> {
+		self.connections.get_mut(&connection_id).map(|remote| &mut remote.context)
+	}
+
+	/// Get the address of the remote end of an accepted connection.
+	#[inline]
+	pub fn remote_addr(&self, connection_id: ConnectionId) -> Option<SocketAddr> {
+		self.connections.get(&connection_id).map(|remote| remote.addr)
+	}
+
+	/// Get the index of the [transmitter](Self::transmitters) packets of an accepted connection
+	/// are sent through.
+	#[inline]
+	pub fn transmitter_index(&self, connection_id: ConnectionId) -> Option<usize> {
+		self.connections.get(&connection_id).map(|remote| remote.transmitter_index)
+	}
+
+	/// Get the number of packets [deferred](Self::set_max_packets_per_connection) by previous
+	/// [`receive`](Self::receive) calls, across all connections.
+	///
+	/// Zero once every received packet was processed. Otherwise the per-connection limit was hit
+	/// and more work remains, so the application may call [`receive`](Self::receive) again right
+	/// away rather than wait for the next frame.
+	pub fn deferred_packet_count(&self) -> usize {
+		self.connections.values().map(|remote| remote.deferred.len()).sum()
+	}
+
+	/// Get the numbers of received datagrams that were dropped, by the reason they were dropped
+	/// for.
+	#[inline]
+	pub fn drop_counts(&self) -> DropCounts {
+		self.drop_counts
+	}
+
+	/// Get the number of packets carrying the id of an accepted connection that were received
+	/// from another source address than the one of the connection, and dropped.
+	///
+	/// A growing count hints at the remote end being behind a NAT that rebound its address, or
+	/// at another host attempting to inject packets into the connection.
+	#[inline]
+	pub fn source_mismatch_count(&self, connection_id: ConnectionId) -> Option<u64> {
+		self.connections.get(&connection_id).map(|remote| remote.source_mismatch_count)
+	}
+
+	/// Get the latest other source address packets carrying the id of an accepted connection
+	/// were received from, see [`source_mismatch_count`](Self::source_mismatch_count).
+	#[inline]
+	pub fn last_mismatched_source(&self, connection_id: ConnectionId) -> Option<SocketAddr> {
+		self.connections.get(&connection_id)?.last_mismatched_source
+	}
+
+	/// Render a human-readable snapshot of an accepted connection, see
+	/// [`Context::debug_dump`](Context::debug_dump).
+	pub fn debug_dump(&self, connection_id: ConnectionId) -> Option<String> {
+		let remote = self.connections.get(&connection_id)?;
+		Some(format!(
+			"remote: {} (transmitter {})\n{}",
+			remote.addr,
+			remote.transmitter_index,
+			remote.context.debug_dump(),
+		))
 	}
-}
 
-impl<T, D, P> ConnectionListener<Arc<(T, D)>, P> where
-	T: Transmit,
-	D: Demux<ConnectionId>,
-	P: Parcel,
-{
-	/// Create a new `ConnectionListener` using provided [transmitter](Transmit) and default
-	/// [demultiplexer](Demux). 
-	pub fn with_transmitter(transmitter: T) -> Self
-	where
-		D: Default,
-	{
-		Self::new((transmitter, D::default()))
+	/// Iterate over the ids of all accepted connections.
+	pub fn connection_ids(&self) -> impl Iterator<Item = ConnectionId> + '_ {
+		self.connections.keys().copied()
 	}
 
-	/// Create a new `ConnectionListener` using default [transmitter](Transmit) bound to provided
-	/// address and provided [demultiplexer](Demux).
-	pub fn open_with_demultiplexer<A>(addr: A, demultiplexer: D) -> Result<Self, IoError>
-	where
-		A: ToSocketAddrs,
-		T: Open,
-	{
-		Ok(Self::new((T::open(addr)?, demultiplexer)))
+	/// Remove an accepted connection, freeing its id to be reused.
+	pub fn remove_connection(&mut self, connection_id: ConnectionId) -> Option<Context<P>> {
+		let remote = self.connections.remove(&connection_id)?;
+		self.id_allocator.free(connection_id);
+		Some(remote.context)
+	}
+
+	/// Remove all connections that were [lost](ConnectionStatus::Lost) or
+	/// [closed](ConnectionStatus::Closed), freeing their ids to be reused.
+	///
+	/// Every connection is [updated](Context::update) first, so that connections which timed out
+	/// are removed as well. Long-running servers should call this periodically, as nothing else
+	/// removes such connections.
+	///
+	/// # Returns
+	/// Ids of the removed connections, for the application to clean up any state of its own.
+	pub fn reap_dead_connections(&mut self) -> Vec<ConnectionId> {
+		let dead_ids: Vec<_> = self.connections
+			.iter_mut()
+			.filter_map(|(&connection_id, remote)| {
+				remote.context.update();
+				let status = remote.context.status();
+				matches!(status, ConnectionStatus::Lost | ConnectionStatus::Closed).then_some(connection_id)
+			})
+			.collect();
+		for &connection_id in &dead_ids {
+			self.remove_connection(connection_id);
+		}
+		dead_ids
+	}
+
+	/// Close an accepted connection, informing the remote end, and remove it, freeing its id to
+	/// be reused.
+	///
+	/// Unlike [`remove_connection`](Self::remove_connection) the remote end learns of the closure
+	/// right away, instead of once the connection times out. The close packet is only sent if the
+	/// connection is [open](ConnectionStatus::Open), see
+	/// [`Context::close_with_reason`](Context::close_with_reason).
+	pub fn close_connection(&mut self, connection_id: ConnectionId, reason: CloseReason) -> Option<Context<P>> {
+		let remote = self.connections.get_mut(&connection_id)?;
+		if remote.context.status() == ConnectionStatus::Open {
+			let datagram_length = self.transmitters[remote.transmitter_index].max_datagram_length();
+			// The close packet is not acknowledged, if it fails to be sent the remote end times out.
+			if let Ok(len) = remote.context.close_with_reason(&mut self.buffer[.. datagram_length], reason, &[]) {
+				let _ = self.transmitters[remote.transmitter_index].send_to(&self.buffer[.. len], remote.addr);
+			}
+		}
+		self.remove_connection(connection_id)
+	}
+
+	/// Stop accepting connections and close all accepted ones.
+	///
+	/// Every accepted connection is [closed](Self::close_connection) with the
+	/// [`GracefulShutdown`](CloseReason::GracefulShutdown) reason, the close packets are sent
+	/// before the method returns. Queued connection requests, as well as all requests
+	/// [received](Self::receive) from now on, are rejected with the same reason. Unconnected
+	/// datagrams are still received and may be answered.
+	///
+	/// Calling the method again closes nothing more, as there are no connections left.
+	///
+	/// # Returns
+	/// Number of closed connections.
+	pub fn shutdown(&mut self) -> usize {
+		self.is_shut_down = true;
+		while let Some(request) = self.requests.pop_front() {
+			let handshake_id = packet::get_header(&request.packet).prelude;
+			let transmitter = &self.transmitters[request.transmitter_index];
+			// Requests that fail to be rejected are rejected once they are re-sent.
+			let _ = send_reject(transmitter, &mut self.buffer, handshake_id, CloseReason::GracefulShutdown, request.addr);
+			self.buffer_pool.release(request.packet);
+		}
+		let connection_ids: Vec<_> = self.connections.keys().copied().collect();
+		for &connection_id in &connection_ids {
+			self.close_connection(connection_id, CloseReason::GracefulShutdown);
+		}
+		connection_ids.len()
 	}
 
-	/// Create a new `ConnectionListener` using provided [transmitter](Transmit) and [demultiplexer](Demux).
+	/// Check whether the listener was [shut down](Self::shutdown).
 	#[inline]
-	pub fn with_transmitter_and_demultiplexer(transmitter: T, demultiplexer: D) -> Self {
-		Self::new((transmitter, demultiplexer))
+	pub fn is_shut_down(&self) -> bool {
+		self.is_shut_down
+	}
+
+	/// Get the next event of any accepted connection, along with the id of the connection.
+	///
+	/// Aggregates [`Context::poll`](Context::poll) of all connections, so a single loop can
+	/// follow deliveries, losses and received data of every connection. Returns `None` once no
+	/// connection has more events, call again after [receiving](Self::receive) more datagrams.
+	pub fn poll(&mut self) -> Option<(ConnectionId, ConnectionEvent<P>)> {
+		self.connections
+			.iter_mut()
+			.find_map(|(&connection_id, remote)| remote.context.poll().map(|event| (connection_id, event)))
 	}
+
+	/// Build and send all packets that are due for every [open](ConnectionStatus::Open)
+	/// connection.
+	///
+	/// Consecutive packets of a single connection of equal length are sent as one burst with
+	/// [`send_segments_to`](Transmit::send_segments_to), the rest of the packets of all
+	/// connections sharing a transmitter are sent together with
+	/// [`send_batch_to`](Transmit::send_batch_to), reducing the number of system calls where
+	/// the transmitter supports it.
+	///
+	/// # Returns
+	/// Number of sent datagrams. If any connection failed to build or send a packet, all such
+	/// failures are returned instead, after every other connection has been flushed.
+	pub fn flush_all(&mut self) -> Result<usize, Vec<(ConnectionId, FlushError)>> {
+		let mut errors = Vec::new();
+		let mut bytes = Vec::new();
+		let mut datagrams = Vec::new();
+		for (&connection_id, remote) in self.connections.iter_mut() {
+			if remote.context.status() != ConnectionStatus::Open {
+				continue
+			}
+			let datagram_length = self.transmitters[remote.transmitter_index].max_datagram_length();
+			loop {
+				let offset = bytes.len();
+				bytes.resize(offset + datagram_length, 0);
+				match remote.context.build_packet(&mut bytes[offset ..]) {
+					Ok(0) => {
+						bytes.truncate(offset);
+						break
+					},
+					Ok(len) => {
+						bytes.truncate(offset + len);
+						datagrams.push((connection_id, remote.transmitter_index, remote.addr, offset .. offset + len));
+					},
+					Err(error) => {
+						bytes.truncate(offset);
+						errors.push((connection_id, error.into()));
+						break
+					},
+				}
+			}
+		}
+
+		let mut sent_count = 0;
+		for (transmitter_index, transmitter) in self.transmitters.iter().enumerate() {
+			let queued: Vec<_> = datagrams.iter().filter(|(_, index, _, _)| *index == transmitter_index).collect();
+			let mut connection_ids = Vec::new();
+			let mut batch = Vec::new();
+			let mut index = 0;
+			while index < queued.len() {
+				let (connection_id, _, addr, ref range) = *queued[index];
+				let burst = &queued[index .. index + segment_run_length(&queued[index ..])];
+				index += burst.len();
+				if burst.len() == 1 {
+					connection_ids.push(connection_id);
+					batch.push((&bytes[range.clone()], addr));
+					continue
+				}
+
+				let end = burst[burst.len() - 1].3.end;
+				match transmitter.send_segments_to(&bytes[range.start .. end], range.len(), addr) {
+					Ok(byte_count) => {
+						let burst_sent_count = burst
+							.iter()
+							.take_while(|(_, _, _, datagram)| datagram.end - range.start <= byte_count)
+							.count();
+						sent_count += burst_sent_count;
+						if burst_sent_count < burst.len() {
+							let error = IoError::new(IoErrorKind::WriteZero, "not all datagrams were sent");
+							errors.push((connection_id, error.into()));
+						}
+					},
+					Err(error) => errors.push((connection_id, error.into())),
+				}
+			}
+
+			let mut index = 0;
+			while index < batch.len() {
+				match transmitter.send_batch_to(&batch[index ..]) {
+					Ok(0) => {
+						let error = IoError::new(IoErrorKind::WriteZero, "no datagrams were sent");
+						errors.push((connection_ids[index], error.into()));
+						index += 1;
+					},
+					Ok(count) => {
+						sent_count += count;
+						index += count;
+					},
+					Err(error) => {
+						errors.push((connection_ids[index], error.into()));
+						index += 1;
+					},
+				}
+			}
+		}
+
+		if errors.is_empty() {
+			Ok(sent_count)
+		} else {
+			Err(errors)
+		}
+	}
+}
+
+#[cfg(target_os = "linux")]
+impl<P: Parcel> ConnectionListener<UdpSocket, P> {
+	/// Construct a new listener receiving from `shard_count` non-blocking sockets all bound to
+	/// provided address with `SO_REUSEPORT`, see
+	/// [`bind_reuse_port`](crate::endpoint::bind_reuse_port).
+	///
+	/// The kernel spreads connection requests across the sockets, while [`receive`](Self::receive)
+	/// pulls from all of them. If the port of the address is 0, all sockets share the port picked
+	/// for the first one.
+	///
+	/// # Panics
+	/// If `shard_count` is 0.
+	///
+	/// # Platform support
+	/// Only available on Linux.
+	pub fn bind_sharded(addr: SocketAddr, shard_count: usize) -> Result<Self, IoError> {
+		assert!(shard_count > 0, "a listener requires at least one transmitter");
+		let first = crate::endpoint::bind_reuse_port(addr)?;
+		let addr = first.local_addr()?;
+		let mut sockets = vec![first];
+		for _ in 1 .. shard_count {
+			sockets.push(crate::endpoint::bind_reuse_port(addr)?);
+		}
+		for socket in &sockets {
+			socket.set_nonblocking(true)?;
+		}
+		Ok(Self::with_transmitters(sockets))
+	}
+}
+
+impl<T: Transmit, P: Parcel> ConnectionListener<T, P> {
+	/// Receive all pending datagrams of the transmitter with provided index.
+	fn receive_from(&mut self, transmitter_index: usize) -> Result<usize, TransmitError> {
+		let mut datagram_count = 0;
+		loop {
+			let (len, src, received_at) = match self.transmitters[transmitter_index].try_recv_from_timestamped(&mut self.buffer) {
+				Ok(result) => result,
+				Err(TransmitError::NoPendingPackets) => return Ok(datagram_count),
+				Err(TransmitError::MalformedPacket) => {
+					self.drop_counts.malformed += 1;
+					continue
+				},
+				Err(error) => return Err(error),
+			};
+			if self.blocked_addrs.contains(&src.ip()) {
+				self.drop_counts.blocked += 1;
+				continue
+			}
+			datagram_count += 1;
+			self.track_remote(src, received_at);
+
+			let datagram = &self.buffer[.. len];
+			if packet::is_valid(datagram) {
+				let header = packet::get_header(datagram);
+				if header.connection_id == 0 && header.signal.is_signal_set(Signal::ConnectionRequest) {
+					if header.signal.get_parcel_byte_count() as usize > self.max_request_payload {
+						self.drop_counts.oversized_request += 1;
+						continue
+					}
+					if self.is_shut_down {
+						let transmitter = &self.transmitters[transmitter_index];
+						let _ = send_reject(transmitter, &mut self.buffer, header.prelude, CloseReason::GracefulShutdown, src);
+						continue
+					}
+					let request = Request { packet: self.copy_datagram(len), addr: src, transmitter_index, received_at };
+					self.process_request(request);
+					continue
+				}
+				if let Some(remote) = self.connections.get_mut(&header.connection_id) {
+					if remote.addr != src {
+						remote.source_mismatch_count += 1;
+						remote.last_mismatched_source = Some(src);
+						self.drop_counts.source_mismatch += 1;
+						continue
+					}
+					if self.max_packets_per_connection.is_none_or(|max| remote.processed_count < max) {
+						if !remote.process_packet(datagram, transmitter_index, received_at) {
+							self.drop_counts.unprocessed += 1;
+						}
+					} else if remote.deferred.len() < MAX_DEFERRED_PACKETS {
+						let mut packet = self.buffer_pool.acquire(len);
+						packet.extend_from_slice(datagram);
+						remote.deferred.push_back(DeferredPacket { packet, transmitter_index, received_at });
+					} else {
+						self.drop_counts.deferred_overflow += 1;
+					}
+					continue
+				}
+			}
+			if self.unconnected.len() < MAX_UNCONNECTED_DATAGRAMS {
+				let datagram = self.copy_datagram(len);
+				self.unconnected.push_back((datagram, src));
+			} else {
+				self.drop_counts.unconnected_overflow += 1;
+			}
+		}
+	}
+
+	/// Copy the received datagram of provided length into a buffer of the pool.
+	fn copy_datagram(&self, len: usize) -> Vec<u8> {
+		let mut datagram = self.buffer_pool.acquire(len);
+		datagram.extend_from_slice(&self.buffer[.. len]);
+		datagram
+	}
+
+	/// Record a datagram received from provided source address at provided moment.
+	fn track_remote(&mut self, src: SocketAddr, received_at: Instant) {
+		if self.recent_remotes.len() == MAX_RECENT_REMOTES && !self.recent_remotes.contains_key(&src) {
+			if self.recent_remotes_sweep_at.is_some_and(|sweep_at| received_at < sweep_at) {
+				return
+			}
+			// Moments of the latest datagrams only move forward, so none of the addresses expires
+			// before the earliest one kept by this sweep does.
+			let mut earliest = received_at;
+			self.recent_remotes.retain(|_, last_seen| {
+				let is_recent = received_at.saturating_duration_since(*last_seen) < RECENT_REMOTE_WINDOW;
+				if is_recent {
+					earliest = earliest.min(*last_seen);
+				}
+				is_recent
+			});
+			self.recent_remotes_sweep_at = Some((earliest + RECENT_REMOTE_WINDOW).max(received_at + RECENT_REMOTE_SWEEP_INTERVAL));
+			if self.recent_remotes.len() == MAX_RECENT_REMOTES {
+				return
+			}
+		}
+		let last_seen = self.recent_remotes.entry(src).or_insert(received_at);
+		*last_seen = received_at.max(*last_seen);
+	}
+
+	/// Queue a received connection request, answering repeated requests of open connections.
+	fn process_request(&mut self, request: Request) {
+		let (src, transmitter_index) = (request.addr, request.transmitter_index);
+		let handshake_id = packet::get_header(&request.packet).prelude;
+		let queued = self.requests
+			.iter_mut()
+			.find(|queued| queued.addr == src && packet::get_header(&queued.packet).prelude == handshake_id);
+		if let Some(queued) = queued {
+			queued.received_at = queued.received_at.max(request.received_at);
+			self.buffer_pool.release(request.packet);
+			return
+		}
+
+		let existing = self.connections
+			.iter()
+			.find(|(_, remote)| remote.addr == src && remote.context.status() == ConnectionStatus::Open);
+		match existing {
+			Some((&connection_id, remote)) if remote.handshake_id == handshake_id => {
+				// The accept packet was lost, a failure to resend it is recovered by the next request.
+				let transmitter = &self.transmitters[transmitter_index];
+				let _ = send_accept(transmitter, &mut self.buffer, handshake_id, connection_id, &remote.accept_payload, src);
+				self.buffer_pool.release(request.packet);
+			},
+			// A different handshake may mean the remote restarted, which is only trusted once the
+			// request is accepted, as the request may be spoofed.
+			_ if self.requests.len() < MAX_PENDING_REQUESTS => self.requests.push_back(request),
+			_ => {
+				self.drop_counts.request_overflow += 1;
+				self.buffer_pool.release(request.packet);
+			},
+		}
+	}
+}
+
+impl<P: Parcel> Remote<P> {
+	/// Process a packet of the connection, received on the transmitter with provided index.
+	///
+	/// Returns `false` if the connection failed to process the packet, which is dropped.
+	fn process_packet(&mut self, packet: &[u8], transmitter_index: usize, received_at: Instant) -> bool {
+		self.processed_count += 1;
+		let is_processed = self.context.process_packet_received_at(packet, received_at).is_ok();
+		if is_processed {
+			self.transmitter_index = transmitter_index;
+		}
+		is_processed
+	}
+}
+
+/// Send a packet rejecting the connection request with provided handshake id for provided reason.
+fn send_reject<T: Transmit>(
+	transmitter: &T,
+	buffer: &mut [u8],
+	handshake_id: DataPrelude,
+	reason: CloseReason,
+	addr: SocketAddr,
+) -> Result<usize, IoError> {
+	let header = PacketHeader::reject_connection(handshake_id, 0);
+	let len = context::write_close_packet(buffer, header, reason, &[])
+		.expect("the buffer holds a packet header and the reason code");
+	transmitter.send_to(&buffer[.. len], addr)
+}
+
+/// Get the number of leading datagrams that may be sent as a single segmented burst.
+///
+/// A burst consists of consecutive datagrams of a single connection, laid out contiguously, all of
+/// the same length except for the last one, which may be shorter.
+fn segment_run_length(datagrams: &[&(ConnectionId, usize, SocketAddr, Range<usize>)]) -> usize {
+	let (connection_id, _, _, ref first) = *datagrams[0];
+	let mut end = first.end;
+	let mut count = 1;
+	for (next_id, _, _, range) in datagrams[1 ..].iter().copied() {
+		if *next_id != connection_id || range.start != end || range.len() > first.len() {
+			break
+		}
+		end = range.end;
+		count += 1;
+		if range.len() < first.len() {
+			break
+		}
+	}
+	count
+}
+
+/// Number of bytes of the data of an accept packet preceding the accept payload.
+const ACCEPT_BYTE_COUNT: usize = size_of::<ConnectionId>() + size_of::<u16>();
+
+/// Send a packet accepting the connection request with provided handshake id.
+///
+/// Along with the connection id, the packet announces the maximum datagram length of the
+/// transmitter, so that the requesting end does not send packets larger than that, followed by
+/// provided accept payload.
+pub(crate) fn send_accept<T: Transmit>(
+	transmitter: &T,
+	buffer: &mut [u8],
+	handshake_id: DataPrelude,
+	connection_id: ConnectionId,
+	accept_payload: &[u8],
+	addr: SocketAddr,
+) -> Result<usize, IoError> {
+	let max_datagram_length = transmitter.max_datagram_length().min(u16::MAX as usize) as u16;
+	let payload_byte_count = ACCEPT_BYTE_COUNT + accept_payload.len();
+	packet::write_header(buffer, PacketHeader::accept_connection(handshake_id, payload_byte_count as u16));
+	let data = packet::get_mut_data_segment(buffer);
+	(connection_id, max_datagram_length).to_bytes(data);
+	data[ACCEPT_BYTE_COUNT .. payload_byte_count].copy_from_slice(accept_payload);
+	transmitter.send_to(&buffer[.. size_of::<PacketHeader>() + payload_byte_count], addr)
 }
@@ -1,66 +1,135 @@
 //! Data structures used for processing connection requests.
 
-use crate::protocol::id::OutOfIdsError;
+use crate::endpoint::TransmitError;
 
-use super::TransmitError;
+use super::super::context::{CloseReason, Context};
+use super::super::id::{ConnectionId, OutOfIdsError};
+use super::super::Parcel;
 
+use std::io::Error as IoError;
 use std::net::SocketAddr;
 
 /// An error raised trying to accept an incoming connection.
-#[derive(Debug, PartialEq)]
+#[derive(Debug)]
 pub enum AcceptError {
-	/// Something happened attempting to read an incoming packet
-	Transmit(TransmitError),
-	/// The pending connection sent an invalid request packet and was dropped
-	/// There may still be other connections to accept
-	/// Contains the address of the source of the invalid request
-	InvalidRequest(SocketAddr),
+	/// Something happened attempting to send the answer to the connection request.
+	Io(IoError),
 	/// There are no more connection ids to assign.
 	OutOfIds,
-	/// The pending connection failed the provided predicate
-	/// There may still be other connections to accept
+	/// The pending connection failed the provided predicate.
+	/// There may still be other connections to accept.
 	PredicateFail,
-	/// There were no connections to accept
+	/// There were no connections to accept.
 	NoPendingConnections,
+	/// The accept payload made the answer too long, see
+	/// [`try_accept_with_payload`](super::ConnectionListener::try_accept_with_payload).
+	PayloadTooLarge,
 }
 
 /// A possible result of acceptor function.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AcceptDecision {
-	/// Allow the new connection. The [`try_accept()`](ConnectionListener::try_accept)
-	/// will return a new connection.
+	/// Allow the new connection. The [`try_accept()`](super::ConnectionListener::try_accept)
+	/// will return the id of the new connection.
 	Allow,
 	/// Actively refuse the new connection, sending a packet informing the client of the decision.
 	Reject,
+	/// Same as [`Reject`](Self::Reject), but the client is informed of the contained reason for
+	/// the decision, see [`Context::close_reason`](super::super::context::Context::close_reason).
+	RejectWithReason(CloseReason),
 	/// Ignore the request. The client will not be informed of the failure to connect.
 	Ignore,
 }
 
+/// Callbacks accepting connections as their requests are received, see
+/// [`ConnectionListener::set_accept_handler`](super::ConnectionListener::set_accept_handler).
+pub struct AcceptHandler<P: Parcel> {
+	decide: DecideFn,
+	on_accept: OnAcceptFn<P>,
+}
+
+type DecideFn = Box<dyn FnMut(SocketAddr, &[u8]) -> AcceptDecision + Send>;
+type OnAcceptFn<P> = Box<dyn FnMut(ConnectionId, &mut Context<P>) + Send>;
+
+impl<P: Parcel> AcceptHandler<P> {
+	/// Construct a handler out of a predicate deciding every connection request, like the one of
+	/// [`try_accept`](super::ConnectionListener::try_accept), and a callback invoked with every
+	/// accepted connection.
+	pub fn new<D, A>(decide: D, on_accept: A) -> Self
+	where
+		D: FnMut(SocketAddr, &[u8]) -> AcceptDecision + Send + 'static,
+		A: FnMut(ConnectionId, &mut Context<P>) + Send + 'static,
+	{
+		Self { decide: Box::new(decide), on_accept: Box::new(on_accept) }
+	}
+
+	/// Decide a connection request from provided address carrying provided payload.
+	#[inline]
+	pub(super) fn decide(&mut self, addr: SocketAddr, payload: &[u8]) -> AcceptDecision {
+		(self.decide)(addr, payload)
+	}
+
+	/// Notify the handler of an accepted connection.
+	#[inline]
+	pub(super) fn on_accept(&mut self, connection_id: ConnectionId, context: &mut Context<P>) {
+		(self.on_accept)(connection_id, context)
+	}
+}
+
+impl From<IoError> for AcceptError {
+	fn from(error: IoError) -> Self {
+		Self::Io(error)
+	}
+}
+
 impl From<TransmitError> for AcceptError {
 	fn from(error: TransmitError) -> Self {
-		if let TransmitError::NoPendingPackets = error {
-			Self::NoPendingConnections
-		} else {
-			Self::Transmit(error)
+		match error {
+			TransmitError::Io(error) => Self::Io(error),
+			_ => Self::NoPendingConnections,
 		}
 	}
 }
 
 impl From<OutOfIdsError> for AcceptError {
-	fn from(err: OutOfIdsError) -> Self {
+	fn from(_: OutOfIdsError) -> Self {
 		Self::OutOfIds
 	}
 }
 
+impl PartialEq for AcceptError {
+	fn eq(&self, rhs: &Self) -> bool {
+		match (self, rhs) {
+			(Self::Io(lhs_error), Self::Io(rhs_error)) => lhs_error.kind() == rhs_error.kind(),
+			(Self::OutOfIds, Self::OutOfIds) => true,
+			(Self::PredicateFail, Self::PredicateFail) => true,
+			(Self::NoPendingConnections, Self::NoPendingConnections) => true,
+			(Self::PayloadTooLarge, Self::PayloadTooLarge) => true,
+			_ => false,
+		}
+	}
+}
+
 impl std::fmt::Display for AcceptError {
 	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> Result<(), std::fmt::Error> {
 		match self {
-			Self::Transmit(error) => error.fmt(f),
-			Self::InvalidRequest(addr) => write!(f, "got incorrect connection request from {}", addr),
+			Self::Io(error) => {
+				write!(f, "failed to answer the connection request: ")?;
+				error.fmt(f)
+			},
 			Self::OutOfIds => write!(f, "ran out of connection ids to assign"),
 			Self::PredicateFail => write!(f, "connection request was denied"),
 			Self::NoPendingConnections => write!(f, "no connections were requested"),
+			Self::PayloadTooLarge => write!(f, "the accept payload does not fit the answer"),
 		}
 	}
 }
 
-impl std::error::Error for AcceptError {}
+impl std::error::Error for AcceptError {
+	fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+		match self {
+			Self::Io(error) => Some(error),
+			_ => None,
+		}
+	}
+}
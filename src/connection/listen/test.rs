@@ -1,60 +1,809 @@
 //! Listener unit tests.
 
-use crate::packet;
-use crate::packet::DataPrelude;
-use crate::connection::{Connection, PendingConnectionError};
 use super::*;
-use std::mem::size_of;
-
-/// Test that a [`ConnectionListener`](ConnectionListener) is able to accept new connections
-/// using provided server and client endpoint implementations.
-pub fn test_accept<S, C>(
-	(listener, listener_addr): (S, SocketAddr),
-	(client, client_addr): (C, SocketAddr),
-) where
-	S: Transmit + Clone,
-	C: Transmit + std::fmt::Debug, 
-{
-	assert_eq!(S::PACKET_BYTE_COUNT, C::PACKET_BYTE_COUNT);
-	assert_eq!(S::RESERVED_BYTE_COUNT, C::RESERVED_BYTE_COUNT);
-	const REQUEST_DATA: &[u8] = b"GNET CONNECTION REQUEST";
-
-	let server = ConnectionListener::<S, ()>::new(listener);
-	let client_con = Connection::<C, ()>::connect(client, listener_addr, REQUEST_DATA.to_vec())
-		.expect("Failed to begin establishing client connection!");
-
-	let accept_result = server.try_accept(|addr, payload| -> AcceptDecision {
-		if addr == client_addr && payload == REQUEST_DATA {
-			AcceptDecision::Allow
-		} else {
-			AcceptDecision::Reject
-		}
-	});
+use super::super::error::{BuildPacketError, ConnectionError};
+
+use std::cell::Cell;
+use std::net::UdpSocket;
+
+const PACKET_SIZE: usize = 1200;
+
+/// Bind a non-blocking socket on the loopback interface.
+fn bind(port: u16) -> (UdpSocket, SocketAddr) {
+	let addr = SocketAddr::from(([ 127, 0, 0, 1, ], port));
+	let socket = UdpSocket::bind(addr).unwrap();
+	socket.set_nonblocking(true).unwrap();
+	(socket, addr)
+}
+
+/// Receive a single datagram, waiting for it to arrive.
+fn recv(socket: &UdpSocket, buffer: &mut [u8]) -> usize {
+	socket.set_nonblocking(false).unwrap();
+	let (len, _) = socket.recv_from(buffer).unwrap();
+	socket.set_nonblocking(true).unwrap();
+	len
+}
+
+/// Receive on the listener until at least `count` datagrams arrive.
+fn receive_at_least<T: Transmit, P: Parcel>(listener: &mut ConnectionListener<T, P>, count: usize) {
+	let mut received = 0;
+	while received < count {
+		received += listener.receive().unwrap();
+	}
+}
 
-	accept_result.expect("Failed to accept a connection!");
-	client_con.try_promote().expect("Failed to promote client connection!");
+/// Request a connection from the listener, returning the pending client context.
+fn request(client: &UdpSocket, listener_addr: SocketAddr, payload: &[u8]) -> Context<u32> {
+	let mut buffer = vec![0; PACKET_SIZE];
+	let mut context = Context::pending();
+	let len = context.build_request_packet(&mut buffer, payload).unwrap();
+	client.send_to(&buffer[.. len], listener_addr).unwrap();
+	context
 }
 
-/// Test that a [`ConnectionListener`](ConnectionListener) is able to deny new connections
-/// using provided server and client endpoint implementations.
-pub fn test_deny<S, C>(
-	(listener, listener_addr): (S, SocketAddr),
-	(client, client_addr): (C, SocketAddr),
-) where
-	S: Transmit + Clone + std::fmt::Debug,
-	C: Transmit + std::fmt::Debug,
-{
-	assert_eq!(S::PACKET_BYTE_COUNT, C::PACKET_BYTE_COUNT);
-	assert_eq!(S::RESERVED_BYTE_COUNT, C::RESERVED_BYTE_COUNT);
+#[test]
+fn listener_accepts_connections() {
+	let (server, server_addr) = bind(10010);
+	let (client, client_addr) = bind(10011);
+	let mut listener = ConnectionListener::<_, u32>::new(server);
+	let mut buffer = vec![0; PACKET_SIZE];
+
+	assert_eq!(listener.try_accept(|_, _| AcceptDecision::Allow), Err(AcceptError::NoPendingConnections));
+	let mut context = request(&client, server_addr, b"request");
+	receive_at_least(&mut listener, 1);
+
+	let connection_id = listener.try_accept(|addr, payload| {
+		assert_eq!(addr, client_addr);
+		assert_eq!(payload, b"request");
+		AcceptDecision::Allow
+	}).unwrap();
+	assert_eq!(listener.remote_addr(connection_id), Some(client_addr));
+	assert!(listener.debug_dump(connection_id).unwrap().starts_with("remote: 127.0.0.1:10011 (transmitter 0)\nstatus: Open\n"));
+
+	let len = recv(&client, &mut buffer);
+	context.process_packet(&buffer[.. len]).unwrap();
+	assert_eq!(context.status(), ConnectionStatus::Open);
+	assert_eq!(context.connection_id(), Some(connection_id));
+
+	context.push_reliable_parcel(7).unwrap();
+	let len = context.build_packet(&mut buffer).unwrap();
+	client.send_to(&buffer[.. len], server_addr).unwrap();
+	receive_at_least(&mut listener, 1);
+	assert_eq!(listener.connection_mut(connection_id).unwrap().pop_parcel(), Ok((7, [0; 4])));
+	assert_eq!(listener.connection_mut(connection_id).unwrap().pop_parcel(), Err(ConnectionError::NoPendingParcels));
+}
+
+#[test]
+fn listener_attaches_payload_to_accept_packet() {
+	let (server, server_addr) = bind(10075);
+	let (client, _) = bind(10076);
+	let mut listener = ConnectionListener::<_, u32>::new(server);
+	let mut buffer = vec![0; PACKET_SIZE];
+
+	let mut context = request(&client, server_addr, &[]);
+	receive_at_least(&mut listener, 1);
+	let connection_id = listener.try_accept_with_payload(|_, _, payload| {
+		payload.extend_from_slice(b"slot 3");
+		AcceptDecision::Allow
+	}).unwrap();
+	let len = recv(&client, &mut buffer);
+	context.process_packet(&buffer[.. len]).unwrap();
+	assert_eq!(context.connection_id(), Some(connection_id));
+	assert_eq!(context.accept_payload(), b"slot 3");
+
+	// The answer to a short request may not be much longer than it.
+	let request_payload = [0; 16];
+	let max_payload_len = (size_of::<PacketHeader>() + request_payload.len()) * MAX_ACCEPT_AMPLIFICATION
+		- size_of::<PacketHeader>() - ACCEPT_BYTE_COUNT;
+	let mut context = request(&client, server_addr, &request_payload);
+	receive_at_least(&mut listener, 1);
+	let result = listener.try_accept_with_payload(|_, _, payload| {
+		payload.resize(max_payload_len + 1, 7);
+		AcceptDecision::Allow
+	});
+	assert_eq!(result, Err(AcceptError::PayloadTooLarge));
 
-	let server = ConnectionListener::<S, ()>::new(listener);
-	let client_con = Connection::<C, ()>::connect(client, listener_addr, vec![])
-		.expect("Failed to begin establishing client connection!");
+	let len = context.build_request_packet(&mut buffer, &request_payload).unwrap();
+	client.send_to(&buffer[.. len], server_addr).unwrap();
+	receive_at_least(&mut listener, 1);
+	listener.try_accept_with_payload(|_, _, payload| {
+		payload.resize(max_payload_len, 7);
+		AcceptDecision::Allow
+	}).unwrap();
+	let len = recv(&client, &mut buffer);
+	assert_eq!(len, (size_of::<PacketHeader>() + request_payload.len()) * MAX_ACCEPT_AMPLIFICATION);
+	context.process_packet(&buffer[.. len]).unwrap();
+	assert_eq!(context.accept_payload(), &vec![7; max_payload_len][..]);
 
-	let accept_result = server.try_accept(|_, _| -> AcceptDecision {
-		AcceptDecision::Reject
+	// Nor longer than a datagram of the transmitter, however long the request.
+	let request_payload = [0; 512];
+	let max_datagram_length = client.max_datagram_length();
+	let max_payload_len = max_datagram_length - size_of::<PacketHeader>() - ACCEPT_BYTE_COUNT;
+	let mut context = request(&client, server_addr, &request_payload);
+	receive_at_least(&mut listener, 1);
+	let result = listener.try_accept_with_payload(|_, _, payload| {
+		payload.resize(max_payload_len + 1, 7);
+		AcceptDecision::Allow
 	});
+	assert_eq!(result, Err(AcceptError::PayloadTooLarge));
+
+	let len = context.build_request_packet(&mut buffer, &request_payload).unwrap();
+	client.send_to(&buffer[.. len], server_addr).unwrap();
+	receive_at_least(&mut listener, 1);
+	listener.try_accept_with_payload(|_, _, payload| {
+		payload.resize(max_payload_len, 7);
+		AcceptDecision::Allow
+	}).unwrap();
+	let len = recv(&client, &mut buffer);
+	assert_eq!(len, max_datagram_length);
+	context.process_packet(&buffer[.. len]).unwrap();
+	assert_eq!(context.accept_payload(), &vec![7; max_payload_len][..]);
+}
+
+#[test]
+fn listener_rejects_connections() {
+	let (server, server_addr) = bind(10012);
+	let (client, _) = bind(10013);
+	let mut listener = ConnectionListener::<_, u32>::new(server);
+	let mut buffer = vec![0; PACKET_SIZE];
+
+	let mut context = request(&client, server_addr, &[]);
+	receive_at_least(&mut listener, 1);
+	assert_eq!(listener.try_accept(|_, _| AcceptDecision::Reject), Err(AcceptError::PredicateFail));
+
+	let len = recv(&client, &mut buffer);
+	context.process_packet(&buffer[.. len]).unwrap();
+	assert_eq!(context.status(), ConnectionStatus::Closed);
+	assert_eq!(context.close_reason(), Some(CloseReason::Unspecified));
+	assert_eq!(listener.connection_ids().count(), 0);
+
+	let mut context = request(&client, server_addr, &[]);
+	receive_at_least(&mut listener, 1);
+	let decision = AcceptDecision::RejectWithReason(CloseReason::ServerFull);
+	assert_eq!(listener.try_accept(|_, _| decision), Err(AcceptError::PredicateFail));
+
+	let len = recv(&client, &mut buffer);
+	context.process_packet(&buffer[.. len]).unwrap();
+	assert_eq!(context.status(), ConnectionStatus::Closed);
+	assert_eq!(context.close_reason(), Some(CloseReason::ServerFull));
+}
+
+#[test]
+fn flush_all_sends_packets_of_every_connection() {
+	let (server, server_addr) = bind(10014);
+	let clients = [ bind(10015), bind(10016), ];
+	let mut listener = ConnectionListener::<_, u32>::new(server);
+	let mut buffer = vec![0; PACKET_SIZE];
+
+	let mut contexts = Vec::new();
+	for (client, _) in clients.iter() {
+		let mut context = request(client, server_addr, &[]);
+		receive_at_least(&mut listener, 1);
+		let connection_id = listener.try_accept(|_, _| AcceptDecision::Allow).unwrap();
+		let len = recv(client, &mut buffer);
+		context.process_packet(&buffer[.. len]).unwrap();
+		listener.connection_mut(connection_id).unwrap().push_reliable_parcel(connection_id as u32).unwrap();
+		contexts.push(context);
+	}
+
+	assert_eq!(listener.flush_all().unwrap(), 2);
+	for ((client, _), context) in clients.iter().zip(contexts.iter_mut()) {
+		let len = recv(client, &mut buffer);
+		context.process_packet(&buffer[.. len]).unwrap();
+		let connection_id = context.connection_id().unwrap() as u32;
+		assert_eq!(context.pop_parcel(), Ok((connection_id, [0; 4])));
+	}
+	assert_eq!(listener.flush_all().unwrap(), 0);
+}
+
+/// A socket counting the segmented bursts and the batches it sends.
+struct CountingSocket {
+	socket: UdpSocket,
+	burst_count: Cell<usize>,
+	batch_count: Cell<usize>,
+}
+
+impl Transmit for CountingSocket {
+	fn max_datagram_length(&self) -> usize {
+		self.socket.max_datagram_length()
+	}
+
+	fn send_to(&self, data: &[u8], addr: SocketAddr) -> Result<usize, IoError> {
+		self.socket.send_to(data, addr)
+	}
+
+	fn send_segments_to(&self, data: &[u8], segment_length: usize, addr: SocketAddr) -> Result<usize, IoError> {
+		self.burst_count.set(self.burst_count.get() + 1);
+		self.socket.send_segments_to(data, segment_length, addr)
+	}
+
+	fn send_batch_to(&self, datagrams: &[(&[u8], SocketAddr)]) -> Result<usize, IoError> {
+		self.batch_count.set(self.batch_count.get() + 1);
+		self.socket.send_batch_to(datagrams)
+	}
+
+	fn try_recv_from(&self, buffer: &mut [u8]) -> Result<(usize, SocketAddr), TransmitError> {
+		self.socket.try_recv_from(buffer)
+	}
+}
+
+#[test]
+fn flush_all_sends_bursts_of_a_connection_as_segments() {
+	let (server, server_addr) = bind(10077);
+	let clients = [ bind(10078), bind(10079), ];
+	let socket = CountingSocket { socket: server, burst_count: Cell::new(0), batch_count: Cell::new(0) };
+	let mut listener = ConnectionListener::<_, u32>::new(socket);
+	let mut buffer = vec![0; PACKET_SIZE];
+
+	let mut contexts = Vec::new();
+	for (client, _) in clients.iter() {
+		let mut context = request(client, server_addr, &[]);
+		receive_at_least(&mut listener, 1);
+		listener.try_accept(|_, _| AcceptDecision::Allow).unwrap();
+		let len = recv(client, &mut buffer);
+		context.process_packet(&buffer[.. len]).unwrap();
+		contexts.push(context);
+	}
+
+	// The stream fills two packets and a shorter third one, the parcel a single packet.
+	let stream: Vec<u8> = (0 .. 2 * PACKET_SIZE).map(|index| index as u8).collect();
+	let stream_id = contexts[0].connection_id().unwrap();
+	let parcel_id = contexts[1].connection_id().unwrap();
+	listener.connection_mut(stream_id).unwrap().write_bytes_to_stream(&stream).unwrap();
+	listener.connection_mut(parcel_id).unwrap().push_reliable_parcel(7).unwrap();
+
+	assert_eq!(listener.flush_all().unwrap(), 4);
+	assert_eq!(listener.transmitters()[0].burst_count.get(), 1);
+	assert_eq!(listener.transmitters()[0].batch_count.get(), 1);
+
+	for _ in 0 .. 3 {
+		let len = recv(&clients[0].0, &mut buffer);
+		contexts[0].process_packet(&buffer[.. len]).unwrap();
+	}
+	let mut received = vec![0; stream.len()];
+	assert_eq!(contexts[0].read_from_stream(&mut received), Ok(stream.len()));
+	assert_eq!(received, stream);
+
+	let len = recv(&clients[1].0, &mut buffer);
+	contexts[1].process_packet(&buffer[.. len]).unwrap();
+	assert_eq!(contexts[1].pop_parcel(), Ok((7, [0; 4])));
+}
+
+/// A transmitter whose datagrams are too small to hold the largest parcel.
+struct SmallDatagramSocket(UdpSocket);
+
+impl Transmit for SmallDatagramSocket {
+	fn max_datagram_length(&self) -> usize {
+		64
+	}
+
+	fn send_to(&self, data: &[u8], addr: SocketAddr) -> Result<usize, IoError> {
+		self.0.send_to(data, addr)
+	}
+
+	fn try_recv_from(&self, buffer: &mut [u8]) -> Result<(usize, SocketAddr), TransmitError> {
+		self.0.try_recv_from(buffer)
+	}
+}
+
+#[test]
+fn flush_all_reports_every_failed_connection() {
+	let (server, server_addr) = bind(10017);
+	let clients = [ bind(10018).0, bind(10024).0, ];
+	let mut listener = ConnectionListener::<_, u32>::new(SmallDatagramSocket(server));
+
+	let mut connection_ids = Vec::new();
+	for client in clients.iter() {
+		request(client, server_addr, &[]);
+		receive_at_least(&mut listener, 1);
+		connection_ids.push(listener.try_accept(|_, _| AcceptDecision::Allow).unwrap());
+	}
+
+	let mut errors = listener.flush_all().unwrap_err();
+	errors.sort_by_key(|(connection_id, _)| *connection_id);
+	connection_ids.sort();
+	assert_eq!(errors.len(), 2);
+	for ((connection_id, error), expected_id) in errors.into_iter().zip(connection_ids) {
+		assert_eq!(connection_id, expected_id);
+		assert!(matches!(error, FlushError::Build(BuildPacketError::InsufficientBuffer)));
+	}
+}
+
+#[test]
+fn listener_routes_packets_from_any_transmitter() {
+	let (first, first_addr) = bind(10019);
+	let (second, second_addr) = bind(10020);
+	let (client, _) = bind(10021);
+	let mut listener = ConnectionListener::<_, u32>::with_transmitters(vec![first, second]);
+	let mut buffer = vec![0; PACKET_SIZE];
+
+	let mut context = request(&client, first_addr, &[]);
+	receive_at_least(&mut listener, 1);
+	let connection_id = listener.try_accept(|_, _| AcceptDecision::Allow).unwrap();
+	assert_eq!(listener.transmitter_index(connection_id), Some(0));
+	let len = recv(&client, &mut buffer);
+	context.process_packet(&buffer[.. len]).unwrap();
+
+	context.push_reliable_parcel(3).unwrap();
+	let len = context.build_packet(&mut buffer).unwrap();
+	client.send_to(&buffer[.. len], second_addr).unwrap();
+	receive_at_least(&mut listener, 1);
+	assert_eq!(listener.connection_mut(connection_id).unwrap().pop_parcel(), Ok((3, [0; 4])));
+	assert_eq!(listener.transmitter_index(connection_id), Some(1));
+
+	// The acknowledgement is sent through the transmitter the latest packet arrived on.
+	assert_eq!(listener.flush_all().unwrap(), 1);
+	client.set_nonblocking(false).unwrap();
+	let (len, src) = client.recv_from(&mut buffer).unwrap();
+	assert_eq!(src, second_addr);
+	context.process_packet(&buffer[.. len]).unwrap();
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn sharded_listener_pins_connections_to_sockets() {
+	let server_addr = SocketAddr::from(([ 127, 0, 0, 1, ], 10072));
+	let mut listener = ConnectionListener::<_, u32>::bind_sharded(server_addr, 4).unwrap();
+	assert_eq!(listener.transmitters().len(), 4);
+	assert!(listener.transmitters().iter().all(|socket| socket.local_addr().unwrap() == server_addr));
+	let mut buffer = vec![0; PACKET_SIZE];
+
+	let clients: Vec<_> = (0 .. 8).map(|_| UdpSocket::bind(SocketAddr::from(([ 127, 0, 0, 1, ], 0))).unwrap()).collect();
+	let mut contexts: Vec<_> = clients.iter().map(|client| request(client, server_addr, &[])).collect();
+	receive_at_least(&mut listener, clients.len());
+	for _ in 0 .. clients.len() {
+		listener.try_accept(|_, _| AcceptDecision::Allow).unwrap();
+	}
+	let mut indices = Vec::new();
+	for (client, context) in clients.iter().zip(&mut contexts) {
+		let (len, src) = client.recv_from(&mut buffer).unwrap();
+		assert_eq!(src, server_addr);
+		context.process_packet(&buffer[.. len]).unwrap();
+
+		let connection_id = context.connection_id().unwrap();
+		indices.push((connection_id, listener.transmitter_index(connection_id).unwrap()));
+		context.push_reliable_parcel(1).unwrap();
+		let len = context.build_packet(&mut buffer).unwrap();
+		client.send_to(&buffer[.. len], server_addr).unwrap();
+	}
+	receive_at_least(&mut listener, clients.len());
+
+	// Later packets of each connection arrive at the socket its request arrived at.
+	for (connection_id, transmitter_index) in indices {
+		assert_eq!(listener.transmitter_index(connection_id), Some(transmitter_index));
+		assert_eq!(listener.connection_mut(connection_id).unwrap().pop_parcel(), Ok((1, [0; 4])));
+	}
+}
+
+#[test]
+fn restarted_remote_loses_half_open_connection() {
+	let (server, server_addr) = bind(10022);
+	let (client, _) = bind(10023);
+	let mut listener = ConnectionListener::<_, u32>::new(server);
+	let mut buffer = vec![0; PACKET_SIZE];
+
+	let mut context = request(&client, server_addr, &[]);
+	receive_at_least(&mut listener, 1);
+	let connection_id = listener.try_accept(|_, _| AcceptDecision::Allow).unwrap();
+	recv(&client, &mut buffer);
+
+	// The accept packet is answered again for a repeated request.
+	let len = context.build_request_packet(&mut buffer, &[]).unwrap();
+	client.send_to(&buffer[.. len], server_addr).unwrap();
+	receive_at_least(&mut listener, 1);
+	let len = recv(&client, &mut buffer);
+	context.process_packet(&buffer[.. len]).unwrap();
+	assert_eq!(context.connection_id(), Some(connection_id));
+	assert_eq!(listener.try_accept(|_, _| AcceptDecision::Allow), Err(AcceptError::NoPendingConnections));
+	assert_eq!(listener.connection(connection_id).unwrap().status(), ConnectionStatus::Open);
+
+	// A request with a new handshake may be spoofed, so it is not trusted unless accepted.
+	request(&client, server_addr, &[]);
+	receive_at_least(&mut listener, 1);
+	assert_eq!(listener.connection(connection_id).unwrap().status(), ConnectionStatus::Open);
+	assert_eq!(listener.try_accept(|_, _| AcceptDecision::Ignore), Err(AcceptError::PredicateFail));
+	assert_eq!(listener.connection(connection_id).unwrap().status(), ConnectionStatus::Open);
 
-	assert_eq!(accept_result, Err(AcceptError::PredicateFail));
-	assert_eq!(client_con.try_promote(), Err(PendingConnectionError::Rejected));
-}
\ No newline at end of file
+	// An accepted request with a new handshake means the client restarted.
+	request(&client, server_addr, &[]);
+	receive_at_least(&mut listener, 1);
+	let restarted_id = listener.try_accept(|_, _| AcceptDecision::Allow).unwrap();
+	assert_ne!(restarted_id, connection_id);
+	let lost = listener.connection(connection_id).unwrap();
+	assert_eq!(lost.status(), ConnectionStatus::Lost);
+	assert_eq!(lost.loss_reason(), Some(LossReason::RemoteRestarted));
+	assert_eq!(listener.connection(restarted_id).unwrap().status(), ConnectionStatus::Open);
+}
+
+#[test]
+fn listener_exchanges_unconnected_datagrams() {
+	let (server, server_addr) = bind(10025);
+	let (client, client_addr) = bind(10026);
+	let mut listener = ConnectionListener::<_, u32>::new(server);
+	let mut buffer = vec![0; PACKET_SIZE];
+
+	client.send_to(b"ping", server_addr).unwrap();
+	receive_at_least(&mut listener, 1);
+	assert_eq!(listener.try_accept(|_, _| AcceptDecision::Allow), Err(AcceptError::NoPendingConnections));
+	assert_eq!(listener.pop_unconnected(), Some((b"ping".to_vec(), client_addr)));
+	assert_eq!(listener.pop_unconnected(), None);
+
+	assert_eq!(listener.send_unconnected(b"pong", client_addr).unwrap(), 4);
+	let len = recv(&client, &mut buffer);
+	assert_eq!(&buffer[.. len], b"pong");
+}
+
+#[test]
+fn closed_connection_informs_remote_and_frees_id() {
+	let (server, server_addr) = bind(10032);
+	let (client, _) = bind(10033);
+	let mut listener = ConnectionListener::<_, u32>::new(server);
+	let mut buffer = vec![0; PACKET_SIZE];
+
+	let mut context = request(&client, server_addr, &[]);
+	receive_at_least(&mut listener, 1);
+	let connection_id = listener.try_accept(|_, _| AcceptDecision::Allow).unwrap();
+	let len = recv(&client, &mut buffer);
+	context.process_packet(&buffer[.. len]).unwrap();
+	assert_eq!(context.status(), ConnectionStatus::Open);
+
+	let closed = listener.close_connection(connection_id, CloseReason::Kicked).unwrap();
+	assert_eq!(closed.status(), ConnectionStatus::Closed);
+	assert!(listener.connection(connection_id).is_none());
+	assert!(listener.close_connection(connection_id, CloseReason::Kicked).is_none());
+
+	let len = recv(&client, &mut buffer);
+	context.process_packet(&buffer[.. len]).unwrap();
+	assert_eq!(context.status(), ConnectionStatus::Closed);
+	assert_eq!(context.close_reason(), Some(CloseReason::Kicked));
+}
+
+#[test]
+fn listener_polls_events_of_every_connection() {
+	let (server, server_addr) = bind(10034);
+	let (client, _) = bind(10035);
+	let mut listener = ConnectionListener::<_, u32>::new(server);
+	let mut buffer = vec![0; PACKET_SIZE];
+
+	let mut context = request(&client, server_addr, &[]);
+	receive_at_least(&mut listener, 1);
+	let connection_id = listener.try_accept(|_, _| AcceptDecision::Allow).unwrap();
+	let len = recv(&client, &mut buffer);
+	context.process_packet(&buffer[.. len]).unwrap();
+	assert_eq!(listener.poll(), None);
+
+	let packet_id = listener.connection(connection_id).unwrap().sequence_state().next_packet_id;
+	listener.connection_mut(connection_id).unwrap().push_reliable_parcel(1).unwrap();
+	assert_eq!(listener.flush_all().unwrap(), 1);
+	let len = recv(&client, &mut buffer);
+	context.process_packet(&buffer[.. len]).unwrap();
+
+	context.push_reliable_parcel(2).unwrap();
+	let len = context.build_packet(&mut buffer).unwrap();
+	client.send_to(&buffer[.. len], server_addr).unwrap();
+	receive_at_least(&mut listener, 1);
+	assert_eq!(listener.poll(), Some((connection_id, ConnectionEvent::PacketDelivered(packet_id))));
+	assert_eq!(listener.poll(), Some((connection_id, ConnectionEvent::ParcelReceived(2, [0; 4]))));
+	assert_eq!(listener.poll(), None);
+}
+
+#[test]
+fn listener_discards_datagrams_from_blocked_addresses() {
+	let (server, server_addr) = bind(10036);
+	let blocked_addr = SocketAddr::from(([ 127, 0, 0, 2, ], 10037));
+	let blocked = UdpSocket::bind(blocked_addr).unwrap();
+	let (client, client_addr) = bind(10038);
+	let mut listener = ConnectionListener::<_, u32>::new(server);
+	assert!(listener.block_addr(blocked_addr.ip()));
+	assert!(!listener.block_addr(blocked_addr.ip()));
+	assert!(listener.is_addr_blocked(blocked_addr.ip()));
+
+	// Datagrams arrive in order on the loopback interface.
+	request(&blocked, server_addr, &[]);
+	request(&client, server_addr, &[]);
+	receive_at_least(&mut listener, 1);
+	let mut sources = Vec::new();
+	while let Ok(connection_id) = listener.try_accept(|addr, _| {
+		sources.push(addr);
+		AcceptDecision::Allow
+	}) {
+		assert_eq!(listener.remote_addr(connection_id), Some(client_addr));
+	}
+	assert_eq!(sources, [ client_addr ]);
+	assert_eq!(listener.drop_counts(), DropCounts { blocked: 1, .. Default::default() });
+
+	assert!(listener.unblock_addr(blocked_addr.ip()));
+	assert!(!listener.unblock_addr(blocked_addr.ip()));
+	request(&blocked, server_addr, &[]);
+	receive_at_least(&mut listener, 1);
+	let connection_id = listener.try_accept(|_, _| AcceptDecision::Allow).unwrap();
+	assert_eq!(listener.remote_addr(connection_id), Some(blocked_addr));
+}
+
+#[test]
+fn listener_counts_recent_remotes() {
+	let (server, server_addr) = bind(10039);
+	let (first, _) = bind(10040);
+	let (second, _) = bind(10041);
+	let mut listener = ConnectionListener::<_, u32>::new(server);
+	assert_eq!(listener.recent_remote_count(), 0);
+
+	first.send_to(b"ping", server_addr).unwrap();
+	first.send_to(b"ping", server_addr).unwrap();
+	request(&second, server_addr, &[]);
+	receive_at_least(&mut listener, 3);
+	assert_eq!(listener.recent_remote_count(), 2);
+}
+
+#[test]
+fn listener_sweeps_recent_remotes_lazily() {
+	let (server, _) = bind(10080);
+	let mut listener = ConnectionListener::<_, u32>::new(server);
+	let start = Instant::now();
+	let addr = |index: usize| SocketAddr::from(([ 10, 0, (index >> 8) as u8, index as u8, ], 1));
+	for index in 0 .. MAX_RECENT_REMOTES {
+		listener.track_remote(addr(index), start);
+	}
+
+	// A sweep expiring none of the addresses is not repeated until the earliest one expires
+	listener.track_remote(addr(MAX_RECENT_REMOTES), start + Duration::from_secs(1));
+	assert_eq!(listener.recent_remotes.len(), MAX_RECENT_REMOTES);
+	assert_eq!(listener.recent_remotes_sweep_at, Some(start + RECENT_REMOTE_WINDOW));
+	listener.track_remote(addr(0), start + Duration::from_secs(2));
+	listener.track_remote(addr(MAX_RECENT_REMOTES), start + Duration::from_secs(3));
+	assert_eq!(listener.recent_remotes_sweep_at, Some(start + RECENT_REMOTE_WINDOW));
+
+	listener.track_remote(addr(MAX_RECENT_REMOTES), start + RECENT_REMOTE_WINDOW);
+	assert_eq!(listener.recent_remotes.len(), 2);
+	assert!(listener.recent_remotes.contains_key(&addr(0)));
+	assert!(listener.recent_remotes.contains_key(&addr(MAX_RECENT_REMOTES)));
+}
+
+#[test]
+fn listener_prunes_expired_requests() {
+	let (server, server_addr) = bind(10044);
+	let (client, client_addr) = bind(10045);
+	let mut listener = ConnectionListener::<_, u32>::new(server);
+
+	request(&client, server_addr, &[]);
+	receive_at_least(&mut listener, 1);
+	assert_eq!(listener.pending_requests().map(|(addr, _)| addr).collect::<Vec<_>>(), [ client_addr ]);
+	assert_eq!(listener.prune_expired(), 0);
+
+	listener.set_request_timeout(Duration::ZERO);
+	assert_eq!(listener.prune_expired(), 1);
+	assert_eq!(listener.pending_requests().count(), 0);
+	assert_eq!(listener.try_accept(|_, _| AcceptDecision::Allow), Err(AcceptError::NoPendingConnections));
+}
+
+#[test]
+fn listener_discards_oversized_requests() {
+	let (server, server_addr) = bind(10042);
+	let (client, _) = bind(10043);
+	let mut listener = ConnectionListener::<_, u32>::new(server);
+	listener.set_max_request_payload(4);
+
+	request(&client, server_addr, b"oversized");
+	request(&client, server_addr, b"fits");
+	receive_at_least(&mut listener, 2);
+	let mut payloads = Vec::new();
+	while listener.try_accept(|_, payload| {
+		payloads.push(payload.to_vec());
+		AcceptDecision::Ignore
+	}) != Err(AcceptError::NoPendingConnections) {}
+	assert_eq!(payloads, [ b"fits".to_vec() ]);
+	assert_eq!(listener.drop_counts(), DropCounts { oversized_request: 1, .. Default::default() });
+	assert_eq!(listener.pop_unconnected(), None);
+	assert_eq!(listener.recent_remote_count(), 1);
+	assert!(listener.connection_ids().next().is_none());
+}
+
+#[test]
+fn listener_limits_pending_requests() {
+	let (server, server_addr) = bind(10081);
+	let (client, _) = bind(10082);
+	let mut listener = ConnectionListener::<_, u32>::new(server);
+
+	for _ in 0 ..= MAX_PENDING_REQUESTS {
+		request(&client, server_addr, &[]);
+		receive_at_least(&mut listener, 1);
+	}
+	assert_eq!(listener.pending_requests().count(), MAX_PENDING_REQUESTS);
+	assert_eq!(listener.drop_counts(), DropCounts { request_overflow: 1, .. Default::default() });
+
+	assert_eq!(listener.try_accept(|_, _| AcceptDecision::Ignore), Err(AcceptError::PredicateFail));
+	request(&client, server_addr, &[]);
+	receive_at_least(&mut listener, 1);
+	assert_eq!(listener.pending_requests().count(), MAX_PENDING_REQUESTS);
+	assert_eq!(listener.drop_counts().request_overflow, 1);
+}
+
+#[test]
+fn listener_defers_packets_past_per_connection_limit() {
+	let (server, server_addr) = bind(10056);
+	let (client, _) = bind(10057);
+	let mut listener = ConnectionListener::<_, u32>::new(server);
+	let mut buffer = vec![0; PACKET_SIZE];
+	listener.set_max_packets_per_connection(Some(1));
+
+	let mut context = request(&client, server_addr, &[]);
+	receive_at_least(&mut listener, 1);
+	let connection_id = listener.try_accept(|_, _| AcceptDecision::Allow).unwrap();
+	let len = recv(&client, &mut buffer);
+	context.process_packet(&buffer[.. len]).unwrap();
+
+	for parcel in 0 .. 3 {
+		context.push_reliable_parcel(parcel).unwrap();
+		let len = context.build_packet(&mut buffer).unwrap();
+		client.send_to(&buffer[.. len], server_addr).unwrap();
+	}
+	receive_at_least(&mut listener, 3);
+	assert_eq!(listener.deferred_packet_count(), 2);
+	let connection = listener.connection_mut(connection_id).unwrap();
+	assert_eq!(connection.pending_parcel_count(), 1);
+	assert_eq!(connection.pop_parcel(), Ok((0, [0; 4])));
+	assert_eq!(connection.pending_parcel_count(), 0);
+	assert_eq!(connection.pop_parcel(), Err(ConnectionError::NoPendingParcels));
+
+	assert_eq!(listener.receive(), Ok(0));
+	assert_eq!(listener.deferred_packet_count(), 1);
+	assert_eq!(listener.connection_mut(connection_id).unwrap().pop_parcel(), Ok((1, [0; 4])));
+	listener.set_max_packets_per_connection(None);
+	assert_eq!(listener.receive(), Ok(0));
+	assert_eq!(listener.deferred_packet_count(), 0);
+	assert_eq!(listener.connection_mut(connection_id).unwrap().pop_parcel(), Ok((2, [0; 4])));
+}
+
+#[test]
+fn listener_counts_packets_from_mismatched_sources() {
+	let (server, server_addr) = bind(10058);
+	let (client, client_addr) = bind(10059);
+	let (other, other_addr) = bind(10060);
+	let mut listener = ConnectionListener::<_, u32>::new(server);
+	let mut buffer = vec![0; PACKET_SIZE];
+
+	let mut context = request(&client, server_addr, &[]);
+	receive_at_least(&mut listener, 1);
+	let connection_id = listener.try_accept(|_, _| AcceptDecision::Allow).unwrap();
+	let len = recv(&client, &mut buffer);
+	context.process_packet(&buffer[.. len]).unwrap();
+	assert_eq!(listener.source_mismatch_count(connection_id), Some(0));
+	assert_eq!(listener.last_mismatched_source(connection_id), None);
+
+	context.push_reliable_parcel(7).unwrap();
+	let len = context.build_packet(&mut buffer).unwrap();
+	other.send_to(&buffer[.. len], server_addr).unwrap();
+	receive_at_least(&mut listener, 1);
+	assert_eq!(listener.source_mismatch_count(connection_id), Some(1));
+	assert_eq!(listener.last_mismatched_source(connection_id), Some(other_addr));
+	assert_eq!(listener.remote_addr(connection_id), Some(client_addr));
+	assert_eq!(listener.connection_mut(connection_id).unwrap().pop_parcel(), Err(ConnectionError::NoPendingParcels));
+	assert_eq!(listener.drop_counts(), DropCounts { source_mismatch: 1, .. Default::default() });
+
+	// The listener has no compressor to decompress the packet with.
+	let mut header = packet::get_header(&buffer[.. len]);
+	header.signal.set_signal(Signal::Compressed);
+	packet::write_header(&mut buffer, header);
+	client.send_to(&buffer[.. len], server_addr).unwrap();
+	receive_at_least(&mut listener, 1);
+	assert_eq!(listener.drop_counts().unprocessed, 1);
+}
+
+#[test]
+fn shut_down_listener_closes_connections_and_rejects_requests() {
+	let (server, server_addr) = bind(10061);
+	let (first, _) = bind(10062);
+	let (second, _) = bind(10063);
+	let mut listener = ConnectionListener::<_, u32>::new(server);
+	let mut buffer = vec![0; PACKET_SIZE];
+
+	let mut connected = request(&first, server_addr, &[]);
+	receive_at_least(&mut listener, 1);
+	listener.try_accept(|_, _| AcceptDecision::Allow).unwrap();
+	let len = recv(&first, &mut buffer);
+	connected.process_packet(&buffer[.. len]).unwrap();
+	let mut queued = request(&second, server_addr, &[]);
+	receive_at_least(&mut listener, 1);
+
+	assert!(!listener.is_shut_down());
+	assert_eq!(listener.shutdown(), 1);
+	assert!(listener.is_shut_down());
+	assert!(listener.connection_ids().next().is_none());
+	for (socket, context) in [ (&first, &mut connected), (&second, &mut queued), ] {
+		let len = recv(socket, &mut buffer);
+		context.process_packet(&buffer[.. len]).unwrap();
+		assert_eq!(context.status(), ConnectionStatus::Closed);
+		assert_eq!(context.close_reason(), Some(CloseReason::GracefulShutdown));
+	}
+
+	let mut late = request(&second, server_addr, &[]);
+	receive_at_least(&mut listener, 1);
+	assert_eq!(listener.try_accept(|_, _| AcceptDecision::Allow), Err(AcceptError::NoPendingConnections));
+	let len = recv(&second, &mut buffer);
+	late.process_packet(&buffer[.. len]).unwrap();
+	assert_eq!(late.close_reason(), Some(CloseReason::GracefulShutdown));
+	assert_eq!(listener.shutdown(), 0);
+}
+
+#[test]
+fn connection_setup_tunes_accepted_connections() {
+	let (server, server_addr) = bind(10064);
+	let (client, _) = bind(10065);
+	let mut listener = ConnectionListener::<_, u32>::new(server);
+	listener.set_connection_setup(Some(Box::new(|context| context.set_send_rate(Some(64_000)))));
+
+	let mut connection_ids = Vec::new();
+	for _ in 0 .. 2 {
+		request(&client, server_addr, &[]);
+		receive_at_least(&mut listener, 1);
+		connection_ids.push(listener.try_accept(|_, _| AcceptDecision::Allow).unwrap());
+	}
+	for &connection_id in &connection_ids {
+		assert_eq!(listener.connection(connection_id).unwrap().send_rate(), Some(64_000));
+	}
+
+	// Accepted connections may be tuned individually.
+	listener.connection_mut(connection_ids[0]).unwrap().set_send_rate(None);
+	assert_eq!(listener.connection(connection_ids[0]).unwrap().send_rate(), None);
+	assert_eq!(listener.connection(connection_ids[1]).unwrap().send_rate(), Some(64_000));
+}
+
+#[test]
+fn accept_handler_accepts_connections_on_receive() {
+	use std::sync::{Arc, Mutex};
+
+	let (server, server_addr) = bind(10066);
+	let (client, client_addr) = bind(10067);
+	let (denied_client, _) = bind(10068);
+	let mut listener = ConnectionListener::<_, u32>::new(server);
+	let mut buffer = vec![0; PACKET_SIZE];
+
+	let accepted = Arc::new(Mutex::new(Vec::new()));
+	let handler_accepted = accepted.clone();
+	listener.set_accept_handler(Some(AcceptHandler::new(
+		|_, payload| if payload == b"deny" { AcceptDecision::Reject } else { AcceptDecision::Allow },
+		move |connection_id, context| {
+			context.set_send_rate(Some(64_000));
+			handler_accepted.lock().unwrap().push(connection_id);
+		},
+	)));
+
+	let mut context = request(&client, server_addr, &[]);
+	let mut denied_context = request(&denied_client, server_addr, b"deny");
+	receive_at_least(&mut listener, 2);
+	assert_eq!(listener.pending_requests().count(), 0);
+
+	let accepted = accepted.lock().unwrap().clone();
+	assert_eq!(accepted.len(), 1);
+	assert_eq!(listener.remote_addr(accepted[0]), Some(client_addr));
+	assert_eq!(listener.connection(accepted[0]).unwrap().send_rate(), Some(64_000));
+
+	let len = recv(&client, &mut buffer);
+	context.process_packet(&buffer[.. len]).unwrap();
+	assert_eq!(context.connection_id(), Some(accepted[0]));
+
+	let len = recv(&denied_client, &mut buffer);
+	denied_context.process_packet(&buffer[.. len]).unwrap();
+	assert_eq!(denied_context.status(), ConnectionStatus::Closed);
+}
+
+#[test]
+fn dead_connections_are_reaped() {
+	let (server, server_addr) = bind(10069);
+	let (client, _) = bind(10070);
+	let (other_client, _) = bind(10071);
+	let mut listener = ConnectionListener::<_, u32>::new(server);
+
+	let mut connection_ids = Vec::new();
+	for client in [ &client, &other_client, ].iter() {
+		request(client, server_addr, &[]);
+		receive_at_least(&mut listener, 1);
+		connection_ids.push(listener.try_accept(|_, _| AcceptDecision::Allow).unwrap());
+	}
+	assert!(listener.reap_dead_connections().is_empty());
+
+	listener.connection_mut(connection_ids[0]).unwrap().mark_lost(LossReason::TimedOut);
+	assert_eq!(listener.reap_dead_connections(), [ connection_ids[0], ]);
+	assert!(listener.connection(connection_ids[0]).is_none());
+	assert_eq!(listener.connection_ids().collect::<Vec<_>>(), [ connection_ids[1], ]);
+}
@@ -4,6 +4,7 @@
 //! Packets consist of 2 parts:
 //! - `Header` with technical information.
 //! - `Payload` with user data.
+//!
 //! The payload itself may consist of:
 //! - One or more instances of [`Parcel`](super::Parcel) implementations.
 //! - Part of a data stream.
@@ -15,11 +16,16 @@ use std::cmp::{Ordering, PartialOrd};
 use std::mem::size_of;
 use std::num::Wrapping;
 
+use crate::byte::{ByteSerialize, SerializationError};
+
 use super::id::ConnectionId;
 
 /// Networked data is preluded with this fixed-size user-data.
 pub type DataPrelude = [u8; 4];
 
+/// Maximum number of bytes a single data segment (parcel or stream) of a packet may hold.
+pub const MAX_SEGMENT_BYTE_COUNT: usize = 0x7FF;
+
 /// An identifying index of the packet, used to order packets.
 #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct PacketIndex(Wrapping<u8>);
@@ -39,14 +45,17 @@ mod signal {
 		ConnectionAccepted,
 		/// This packet's id field is valid and should be acknowledged.
 		Synchronized,
+		/// The data segment of this packet is compressed.
+		// (parcel and stream bytes count the decompressed data)
+		Compressed,
 	}
 
 	/// Compacted bitpatterns for signalling protocol-level information.
 	///
 	/// Consists of:
-	/// | bit(s) | 31-27      | 25           | 24                | 23               | 22                 | 21-11           | 10-0         |
-	/// |--------|------------|--------------|-------------------|------------------|--------------------|-----------------|--------------|
-	/// | value  | `[zeroes]` | synchronized | connection_accept | connection_close | connection_request | parcel(s) bytes | stream bytes |
+	/// | bit(s) | 31-27      | 26         | 25           | 24                | 23               | 22                 | 21-11           | 10-0         |
+	/// |--------|------------|------------|--------------|-------------------|------------------|--------------------|-----------------|--------------|
+	/// | value  | `[zeroes]` | compressed | synchronized | connection_accept | connection_close | connection_request | parcel(s) bytes | stream bytes |
 	#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 	pub struct SignalBits(u32);
 
@@ -54,8 +63,9 @@ mod signal {
 	const CONNECTION_CLOSE_BIT: u32 = 1 << 23;
 	const CONNECTION_ACCEPT_BIT: u32 = 1 << 24;
 	const SYNCHRONIZED_BIT: u32 = 1 << 25;
+	const COMPRESSED_BIT: u32 = 1 << 26;
 
-	const ZERO_BITS: u32 = 0xFFFF << 26;
+	const ZERO_BITS: u32 = 0xFFFF << 27;
 
 	const BYTE_COUNT_BITS: u32 = 0x7FF;
 	const FULL_BYTE_COUNT_BITS: u32 = BYTE_COUNT_BITS << 11 | BYTE_COUNT_BITS;
@@ -71,6 +81,7 @@ mod signal {
 				Signal::ConnectionClosed => self.0 |= CONNECTION_CLOSE_BIT,
 				Signal::ConnectionAccepted => self.0 |= CONNECTION_ACCEPT_BIT,
 				Signal::Synchronized => self.0 |= SYNCHRONIZED_BIT,
+				Signal::Compressed => self.0 |= COMPRESSED_BIT,
 			}
 		}
 
@@ -84,6 +95,7 @@ mod signal {
 				Signal::ConnectionClosed => self.0 &= !CONNECTION_CLOSE_BIT,
 				Signal::ConnectionAccepted => self.0 &= !CONNECTION_ACCEPT_BIT,
 				Signal::Synchronized => self.0 &= !SYNCHRONIZED_BIT,
+				Signal::Compressed => self.0 &= !COMPRESSED_BIT,
 			}
 		}
 
@@ -97,6 +109,7 @@ mod signal {
 				Signal::ConnectionClosed => (self.0 & CONNECTION_CLOSE_BIT) == CONNECTION_CLOSE_BIT,
 				Signal::ConnectionAccepted => (self.0 & CONNECTION_ACCEPT_BIT) == CONNECTION_ACCEPT_BIT,
 				Signal::Synchronized => (self.0 & SYNCHRONIZED_BIT) == SYNCHRONIZED_BIT,
+				Signal::Compressed => (self.0 & COMPRESSED_BIT) == COMPRESSED_BIT,
 			}
 		}
 
@@ -132,6 +145,18 @@ mod signal {
 			(self.0 & FULL_BYTE_COUNT_BITS) == 0
 		}
 
+		/// Construct signal bits from their raw bitpattern.
+		#[inline]
+		pub(super) fn from_bits(bits: u32) -> Self {
+			Self(bits)
+		}
+
+		/// Get the raw bitpattern of the signal bits.
+		#[inline]
+		pub(super) fn bits(self) -> u32 {
+			self.0
+		}
+
 		/// Create a *KeepAlive* protocol bitpattern.
 		///
 		/// KeepAlive packets contain no payload, they simply signal update the connection timing.
@@ -140,26 +165,35 @@ mod signal {
 			Self(0)
 		}
 
+		/// Create a bitpattern of a packet closing an established connection.
+		///
+		/// The payload of close packets explains the reason of the closure.
+		#[inline]
+		pub fn close(payload_byte_count: u16) -> Self {
+			debug_assert_eq!(payload_byte_count & BYTE_COUNT_BITS as u16, payload_byte_count);
+			Self(CONNECTION_CLOSE_BIT | ((payload_byte_count as u32) << 11))
+		}
+
 		/// Create a bitpattern associated with a connection request.
 		#[inline]
 		pub fn request_connection(payload_byte_count: u16) -> Self {
 			// Since the payload length is passed from library code, this should be safe.
 			debug_assert_eq!(payload_byte_count & BYTE_COUNT_BITS as u16, payload_byte_count);
-			Self(CONNECTION_REQUEST_BIT | (payload_byte_count << 11) as u32)
+			Self(CONNECTION_REQUEST_BIT | ((payload_byte_count as u32) << 11))
 		}
 
 		/// Create a bitpattern associated with a packet that is informing of the connection being rejected.
 		#[inline]
 		pub fn reject_connection(payload_byte_count: u16) -> Self {
 			debug_assert_eq!(payload_byte_count & BYTE_COUNT_BITS as u16, payload_byte_count);
-			Self(CONNECTION_CLOSE_BIT | (payload_byte_count << 11) as u32)
+			Self(CONNECTION_CLOSE_BIT | ((payload_byte_count as u32) << 11))
 		}
 
 		/// Create a bitpattern associated with a packet that is informing of the newly established connection.
 		#[inline]
 		pub fn accept_connection(payload_byte_count: u16) -> Self {
 			debug_assert_eq!(payload_byte_count & BYTE_COUNT_BITS as u16, payload_byte_count);
-			Self(CONNECTION_ACCEPT_BIT | (payload_byte_count << 11) as u32)
+			Self(CONNECTION_ACCEPT_BIT | ((payload_byte_count as u32) << 11))
 		}
 
 		/// Create a bitpattern associated with an volatile (unsynchronized) packet with given parcel length.
@@ -184,6 +218,7 @@ mod signal {
 		pub fn is_valid_connectionless(&self) -> bool {
 			const CRITICAL_BITS: u32 =
 				ZERO_BITS
+				| COMPRESSED_BIT
 				| SYNCHRONIZED_BIT
 				| CONNECTION_ACCEPT_BIT
 				| CONNECTION_CLOSE_BIT
@@ -205,7 +240,7 @@ mod signal {
 				| CONNECTION_ACCEPT_BIT
 				| CONNECTION_CLOSE_BIT
 				| CONNECTION_REQUEST_BIT;
-			matches!(self.0 & CRITICAL_BITS, 0 | SYNCHRONIZED_BIT)
+			matches!(self.0 & CRITICAL_BITS, 0 | SYNCHRONIZED_BIT | CONNECTION_CLOSE_BIT)
 		}
 
 		/// Check that a given bitpattern is a valid in GNet protocol context.
@@ -239,12 +274,23 @@ mod signal {
 
 			assert_eq!(bits.0, 0x0008000B);
 		}
+
+		#[test]
+		fn connectionless_payload_sizes_are_correct() {
+			assert_eq!(SignalBits::request_connection(37).get_parcel_byte_count(), 37);
+			assert_eq!(SignalBits::reject_connection(0x7FF).get_parcel_byte_count(), 0x7FF);
+			assert_eq!(SignalBits::accept_connection(2).get_parcel_byte_count(), 2);
+		}
 	}
 }
 
 pub use signal::{Signal, SignalBits};
 
 /// Header associated with each sent network packet.
+///
+/// The in-memory layout of the header is platform-dependent, so headers are always read and
+/// written with [`get_header`](get_header) and [`write_header`](write_header), which encode all
+/// multi-byte fields in little-endian byte order.
 #[derive(Debug, Clone, Copy, Eq)]
 #[repr(C)]
 pub struct PacketHeader {
@@ -254,6 +300,12 @@ pub struct PacketHeader {
 	pub packet_id: PacketIndex,
 	/// Id of the latest acknowledged packet by the other end.
 	pub ack_packet_id: PacketIndex,
+	/// Number of further parcels the sender of the packet is able to receive, `u16::MAX` if
+	/// there is no limit.
+	pub receive_window: u16,
+	/// Moment the packet was sent in milliseconds, since an arbitrary moment chosen by the
+	/// sender, wrapping.
+	pub timestamp: u16,
 	/// Bitmask of 64 acks for preceding packets (64 packets before `ack_packet_id`).
 	pub ack_packet_mask: u64,
 	/// Control signals for the connection.
@@ -270,6 +322,8 @@ impl PacketHeader {
 			signal: Default::default(),
 			packet_id: 0.into(),
 			ack_packet_id: 0.into(),
+			receive_window: 0,
+			timestamp: 0,
 			ack_packet_mask: 0,
 			prelude: [0; 4],
 		}
@@ -371,7 +425,7 @@ impl Ord for PacketIndex {
 	fn cmp(&self, other: &Self) -> Ordering {
 		match self.0 - other.0 {
 			Wrapping(0) => Ordering::Equal,
-			x if x.0 < std::u8::MAX / 2 => Ordering::Greater,
+			x if x.0 < u8::MAX / 2 => Ordering::Greater,
 			_ => Ordering::Less,
 		}
 	}
@@ -384,6 +438,30 @@ impl From<u8> for PacketIndex {
 	}
 }
 
+impl ByteSerialize for PacketIndex {
+	#[inline]
+	fn byte_count(&self) -> usize {
+		size_of::<u8>()
+	}
+
+	#[inline]
+	fn to_bytes(&self, bytes: &mut [u8]) {
+		self.0.0.to_bytes(bytes)
+	}
+
+	#[inline]
+	fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), SerializationError> {
+		u8::from_bytes(bytes).map(|(index, byte_count)| (index.into(), byte_count))
+	}
+}
+
+impl std::fmt::Display for PacketIndex {
+	#[inline]
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		self.0.fmt(f)
+	}
+}
+
 impl PacketIndex {
 	/// Get the next index.
 	#[inline]
@@ -391,6 +469,12 @@ impl PacketIndex {
 		Self(self.0 + Wrapping(1))
 	}
 
+	/// Get the previous index.
+	#[inline]
+	pub fn previous(self) -> Self {
+		Self(self.0 - Wrapping(1))
+	}
+
 	/// Get the number of indices between to and from (to - from).
 	#[inline]
 	pub fn distance(to: Self, from: Self) -> u8 {
@@ -401,7 +485,7 @@ impl PacketIndex {
 impl PartialOrd for PacketHeader {
 	#[inline]
 	fn partial_cmp(&self, rhs: &Self) -> Option<Ordering> {
-		self.packet_id.partial_cmp(&rhs.packet_id)
+		Some(self.cmp(rhs))
 	}
 }
 
@@ -429,7 +513,7 @@ pub fn get_data_segment(packet: &[u8]) -> &[u8] {
 /// Get the valid stream portion of the packet
 #[inline]
 pub fn get_parcel_segment(packet: &[u8]) -> &[u8] {
-	let &header = get_header(packet);
+	let header = get_header(packet);
 	let start = size_of::<PacketHeader>();
 	let end = start + header.signal.get_parcel_byte_count() as usize;
 	debug_assert!(packet.len() >= end);
@@ -439,7 +523,7 @@ pub fn get_parcel_segment(packet: &[u8]) -> &[u8] {
 /// Get the valid stream portion of the packet
 #[inline]
 pub fn get_stream_segment(packet: &[u8]) -> &[u8] {
-	let &header = get_header(packet);
+	let header = get_header(packet);
 	let start = size_of::<PacketHeader>() + header.signal.get_parcel_byte_count() as usize;
 	let end = start + header.signal.get_stream_byte_count() as usize;
 	debug_assert!(packet.len() >= end);
@@ -453,43 +537,120 @@ pub fn get_mut_data_segment(packet: &mut [u8]) -> &mut [u8] {
 	&mut packet[size_of::<PacketHeader>() ..]
 }
 
-/// Get the header segment of a packet.
+/// Byte offsets of the [`PacketHeader`](PacketHeader) fields on the wire.
+mod offset {
+	pub const CONNECTION_ID: usize = 0;
+	pub const PACKET_ID: usize = 2;
+	pub const ACK_PACKET_ID: usize = 3;
+	pub const RECEIVE_WINDOW: usize = 4;
+	pub const TIMESTAMP: usize = 6;
+	pub const ACK_PACKET_MASK: usize = 8;
+	pub const SIGNAL: usize = 16;
+	pub const PRELUDE: usize = 20;
+}
+
+/// Read the header segment of a packet.
+///
+/// Multi-byte fields are decoded from little-endian byte order, independent of the platform.
 #[inline]
-pub fn get_header(packet: &[u8]) -> &PacketHeader {
+pub fn get_header(packet: &[u8]) -> PacketHeader {
 	debug_assert!(packet.len() >= size_of::<PacketHeader>());
-	debug_assert_eq!(packet.as_ptr().align_offset(std::mem::align_of::<PacketHeader>()), 0);
-	unsafe { &*(packet.as_ptr() as *const PacketHeader) }
+	let mut prelude = [0; 4];
+	prelude.copy_from_slice(&packet[offset::PRELUDE .. offset::PRELUDE + 4]);
+	PacketHeader {
+		connection_id: read_u16(packet, offset::CONNECTION_ID),
+		packet_id: packet[offset::PACKET_ID].into(),
+		ack_packet_id: packet[offset::ACK_PACKET_ID].into(),
+		receive_window: read_u16(packet, offset::RECEIVE_WINDOW),
+		timestamp: read_u16(packet, offset::TIMESTAMP),
+		ack_packet_mask: u64::from_le_bytes(read_array(packet, offset::ACK_PACKET_MASK)),
+		signal: SignalBits::from_bits(u32::from_le_bytes(read_array(packet, offset::SIGNAL))),
+		prelude,
+	}
 }
 
 /// Write the provided data into the provided packet data segment.
+///
+/// The offset is relative to the start of the data segment, so the header can not be
+/// overwritten.
+///
+/// # Errors
+/// [`BufferOverflow`](SerializationError::BufferOverflow) if the packet can not hold a header or
+/// the data at provided offset. Nothing is written in that case.
 #[inline]
-pub fn write_data(packet: &mut [u8], data: &[u8], offset: usize) {
-	debug_assert!(packet.len() >= size_of::<PacketHeader>());
-	let offset = offset + size_of::<PacketHeader>();
-	packet[offset..offset + data.len()].copy_from_slice(data)
+pub fn write_data(packet: &mut [u8], data: &[u8], offset: usize) -> Result<(), SerializationError> {
+	let data_segment = packet.get_mut(size_of::<PacketHeader>() ..).ok_or(SerializationError::BufferOverflow)?;
+	let end = offset.checked_add(data.len()).ok_or(SerializationError::BufferOverflow)?;
+	data_segment
+		.get_mut(offset .. end)
+		.ok_or(SerializationError::BufferOverflow)?
+		.copy_from_slice(data);
+	Ok(())
 }
 
 /// Clear the remainder of the data segment of the packet starting at provided offset.
-pub fn clear_remaining_data(packet: &mut [u8], offset: usize) {
-	debug_assert!(packet.len() >= size_of::<PacketHeader>());
-	let offset = offset + size_of::<PacketHeader>();
-	for i in packet[offset..].iter_mut() {
-		*i = 0
-	}
+///
+/// # Errors
+/// [`BufferOverflow`](SerializationError::BufferOverflow) if the offset lies past the end of
+/// the packet. Nothing is cleared in that case.
+pub fn clear_remaining_data(packet: &mut [u8], offset: usize) -> Result<(), SerializationError> {
+	packet
+		.get_mut(size_of::<PacketHeader>() ..)
+		.and_then(|data_segment| data_segment.get_mut(offset ..))
+		.ok_or(SerializationError::BufferOverflow)?
+		.fill(0);
+	Ok(())
 }
 
 /// Write the provided packet header into provided packet.
+///
+/// Multi-byte fields are encoded in little-endian byte order, independent of the platform.
 #[inline]
 pub fn write_header(packet: &mut [u8], header: PacketHeader) {
 	debug_assert!(packet.len() >= size_of::<PacketHeader>());
-	debug_assert_eq!(packet.as_ptr().align_offset(std::mem::align_of::<PacketHeader>()), 0);
-	unsafe { *(packet.as_mut_ptr() as *mut PacketHeader) = header }
+	let header_bytes = &mut packet[.. size_of::<PacketHeader>()];
+	header_bytes.fill(0);
+	header_bytes[offset::CONNECTION_ID .. offset::CONNECTION_ID + 2]
+		.copy_from_slice(&header.connection_id.to_le_bytes());
+	header_bytes[offset::PACKET_ID] = header.packet_id.0.0;
+	header_bytes[offset::ACK_PACKET_ID] = header.ack_packet_id.0.0;
+	header_bytes[offset::RECEIVE_WINDOW .. offset::RECEIVE_WINDOW + 2]
+		.copy_from_slice(&header.receive_window.to_le_bytes());
+	header_bytes[offset::TIMESTAMP .. offset::TIMESTAMP + 2].copy_from_slice(&header.timestamp.to_le_bytes());
+	header_bytes[offset::ACK_PACKET_MASK .. offset::ACK_PACKET_MASK + 8]
+		.copy_from_slice(&header.ack_packet_mask.to_le_bytes());
+	header_bytes[offset::SIGNAL .. offset::SIGNAL + 4].copy_from_slice(&header.signal.bits().to_le_bytes());
+	header_bytes[offset::PRELUDE .. offset::PRELUDE + 4].copy_from_slice(&header.prelude);
 }
 
 /// Read the connection id from the provided packet.
+#[inline]
 pub fn read_connection_id(packet: &[u8]) -> ConnectionId {
 	debug_assert!(packet.len() >= size_of::<PacketHeader>());
-	get_header(packet).connection_id
+	read_u16(packet, offset::CONNECTION_ID)
+}
+
+/// Read a little-endian `u16` at provided offset.
+#[inline]
+fn read_u16(bytes: &[u8], offset: usize) -> u16 {
+	u16::from_le_bytes(read_array(bytes, offset))
+}
+
+/// Copy `N` bytes at provided offset into an array.
+#[inline]
+fn read_array<const N: usize>(bytes: &[u8], offset: usize) -> [u8; N] {
+	let mut array = [0; N];
+	array.copy_from_slice(&bytes[offset .. offset + N]);
+	array
+}
+
+/// Check that the packet is large enough to hold the payload its header accounts for.
+///
+/// The payload of a compressed packet may only be validated once it is decompressed.
+#[inline]
+fn is_payload_length_valid(packet: &[u8], header: &PacketHeader) -> bool {
+	header.signal.is_signal_set(Signal::Compressed)
+		|| header.get_payload_byte_count() as usize <= packet.len() - size_of::<PacketHeader>()
 }
 
 /// Check whether the provided packet is a valid GNet packet.
@@ -498,9 +659,8 @@ pub fn is_valid(packet: &[u8]) -> bool {
 	if packet.len() < size_of::<PacketHeader>() {
 		return false
 	}
-	let &header = get_header(packet);
-	header.is_valid()
-		&& header.get_payload_byte_count() <= (packet.len() - size_of::<PacketHeader>()) as u16
+	let header = get_header(packet);
+	header.is_valid() && is_payload_length_valid(packet, &header)
 }
 
 /// Check whether the provided packet is a valid GNet packet associated with a connection.
@@ -509,9 +669,8 @@ pub fn is_valid_connected(packet: &[u8]) -> bool {
 	if packet.len() < size_of::<PacketHeader>() {
 		return false
 	}
-	let &header = get_header(packet);
-	header.is_valid_connected()
-		&& header.get_payload_byte_count() <= (packet.len() - size_of::<PacketHeader>()) as u16
+	let header = get_header(packet);
+	header.is_valid_connected() && is_payload_length_valid(packet, &header)
 }
 
 /// Check whether the provided packet is a valid connectionless GNet packet.
@@ -520,7 +679,7 @@ pub fn is_valid_connectionless(packet: &[u8]) -> bool {
 	if packet.len() < size_of::<PacketHeader>() {
 		return false
 	}
-	let &header = get_header(packet);
+	let header = get_header(packet);
 	header.is_valid_connectionless()
 		&& header.signal.get_parcel_byte_count() <= (packet.len() - size_of::<PacketHeader>()) as u16
 }
@@ -547,6 +706,77 @@ mod test {
 		assert!(smaller < greater);
 	}
 
+	#[test]
+	fn packet_header_is_little_endian_on_the_wire() {
+		let header = PacketHeader {
+			connection_id: 0x0102,
+			packet_id: 3.into(),
+			ack_packet_id: 4.into(),
+			receive_window: 0x1122,
+			timestamp: 0x3344,
+			ack_packet_mask: 0x0506_0708_090A_0B0C,
+			signal: SignalBits::synchronized(0x0D, 0x0E),
+			prelude: [ 0xF0, 0xF1, 0xF2, 0xF3, ],
+		};
+		let mut packet = [0xFF; 32];
+		write_header(&mut packet, header);
+
+		assert_eq!(&packet[.. 8], &[ 0x02, 0x01, 0x03, 0x04, 0x22, 0x11, 0x44, 0x33, ]);
+		assert_eq!(&packet[8 .. 16], &[ 0x0C, 0x0B, 0x0A, 0x09, 0x08, 0x07, 0x06, 0x05, ]);
+		assert_eq!(&packet[16 .. 20], &header.signal.bits().to_le_bytes());
+		assert_eq!(&packet[20 .. 24], &header.prelude);
+
+		let decoded = get_header(&packet);
+		assert_eq!(decoded.connection_id, 0x0102);
+		assert_eq!(read_connection_id(&packet), 0x0102);
+		assert_eq!(decoded.ack_packet_id, 4.into());
+		assert_eq!(decoded.receive_window, 0x1122);
+		assert_eq!(decoded.timestamp, 0x3344);
+		assert_eq!(decoded.ack_packet_mask, header.ack_packet_mask);
+		assert_eq!(decoded.signal, header.signal);
+		assert_eq!(decoded.prelude, header.prelude);
+	}
+
+	#[test]
+	fn connection_id_decodes_independently_of_host_byte_order() {
+		// Wire bytes of a header carrying connection id 0x1234, as written by a host of any byte order.
+		let mut packet = [0; 24];
+		packet[.. 2].copy_from_slice(&[ 0x34, 0x12, ]);
+
+		assert_eq!(get_header(&packet).connection_id, 0x1234);
+		assert_eq!(read_connection_id(&packet), 0x1234);
+
+		let mut written = [0; 24];
+		write_header(&mut written, get_header(&packet));
+		assert_eq!(written, packet);
+	}
+
+	#[test]
+	fn data_is_written_within_data_segment() {
+		let mut packet = [0; 32];
+		write_header(&mut packet, PacketHeader::volatile(8));
+
+		assert_eq!(write_data(&mut packet, &[ 1, 2, 3, 4, ], 4), Ok(()));
+		assert_eq!(&packet[24 ..], &[ 0, 0, 0, 0, 1, 2, 3, 4, ]);
+		assert_eq!(clear_remaining_data(&mut packet, 6), Ok(()));
+		assert_eq!(&packet[24 ..], &[ 0, 0, 0, 0, 1, 2, 0, 0, ]);
+		assert_eq!(get_header(&packet).signal, SignalBits::volatile(8));
+	}
+
+	#[test]
+	fn out_of_bounds_data_writes_are_rejected() {
+		let mut packet = [0; 32];
+		write_header(&mut packet, PacketHeader::volatile(8));
+		let original = packet;
+
+		assert_eq!(write_data(&mut packet, &[ 1, 2, ], 7), Err(SerializationError::BufferOverflow));
+		assert_eq!(write_data(&mut packet, &[ 1, ], 64), Err(SerializationError::BufferOverflow));
+		assert_eq!(write_data(&mut packet, &[ 1, ], usize::MAX), Err(SerializationError::BufferOverflow));
+		assert_eq!(write_data(&mut packet[.. 16], &[], 0), Err(SerializationError::BufferOverflow));
+		assert_eq!(clear_remaining_data(&mut packet, 9), Err(SerializationError::BufferOverflow));
+		assert_eq!(packet, original);
+	}
+
 	#[test]
 	fn packet_header_acknowledgement_is_correct() {
 		let mut header = PacketHeader::request_connection([ 1, 2, 3, 4, ], 0);
@@ -554,17 +784,17 @@ mod test {
 		header.ack_packet_id = 17.into();
 		header.ack_packet_mask = 7 << 14;
 
-		assert_eq!(header.acknowledges(17.into()), false);
+		assert!(!header.acknowledges(17.into()));
 
 		header.signal.clear_signal(Signal::ConnectionRequest);
 
-		assert_eq!(header.acknowledges(17.into()), true);
-		assert_eq!(header.acknowledges(0.into()), true);
-		assert_eq!(header.acknowledges(1.into()), true);
-		assert_eq!(header.acknowledges(2.into()), true);
+		assert!(header.acknowledges(17.into()));
+		assert!(header.acknowledges(0.into()));
+		assert!(header.acknowledges(1.into()));
+		assert!(header.acknowledges(2.into()));
 
-		assert_eq!(header.acknowledges(3.into()), false);
-		assert_eq!(header.acknowledges(16.into()), false);
-		assert_eq!(header.acknowledges(18.into()), false);
+		assert!(!header.acknowledges(3.into()));
+		assert!(!header.acknowledges(16.into()));
+		assert!(!header.acknowledges(18.into()));
 	}
 }
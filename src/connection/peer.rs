@@ -0,0 +1,356 @@
+//! Peer-to-peer connections, established with UDP hole punching.
+//!
+//! A [`PeerConnection`](PeerConnection) is established by both ends simultaneously sending
+//! connection requests to each other, rather than one end passively listening. The outgoing
+//! requests open mappings in the NATs and firewalls of both ends, so that the requests of the
+//! other end are eventually let through.
+//!
+//! ## Coordination
+//! Each peer must learn the public address of the other one out of band, typically from a
+//! rendezvous server both peers are connected to, which observes the public addresses their
+//! datagrams arrive from. The transmitter the peer connection is constructed with must be the
+//! one the rendezvous server observed, as the NAT mapping is bound to its local port. Deciding
+//! whether to connect to a peer at all (authentication) is also left to the coordination, the
+//! connection requests of peers carry no payload.
+//!
+//! ## Simultaneous open
+//! Once a peer receives the request of the other one, the collision is resolved by comparing the
+//! random handshake ids of both requests: the peer with the greater id accepts the request of the
+//! other one and opens the connection, while the other peer ignores the received request and
+//! opens the connection once the accept packet arrives, as a [client](super::client) would. In
+//! the unlikely case of equal ids, the receiving peer picks a new id and re-sends its request.
+//!
+//! The connection is [open](ConnectionStatus::Open) as soon as either end opens it, however the
+//! path is only known to work both ways once a packet of the opened connection is received, see
+//! [`is_path_confirmed`](PeerConnection::is_path_confirmed).
+
+use crate::endpoint::{Transmit, TransmitError};
+
+use super::context::{Context, ConnectionStatus};
+use super::error::{ConnectError, FlushError};
+use super::id::ConnectionId;
+use super::listen;
+use super::packet::{self, DataPrelude, Signal};
+use super::Parcel;
+
+use rand::random;
+
+use std::cmp::Ordering;
+use std::net::SocketAddr;
+
+/// A connection to a peer, established by both ends requesting it simultaneously.
+///
+/// The connection starts [pending](ConnectionStatus::Pending), its request should be
+/// [re-sent](Self::send_request) until the connection opens, see
+/// [`Context::next_update_deadline`](Context::next_update_deadline).
+///
+/// # Note
+/// The transmitter should be non-blocking, as [`receive`](Self::receive) reads datagrams until
+/// there are no more pending ones.
+pub struct PeerConnection<T: Transmit, P: Parcel> {
+	transmitter: T,
+	remote: SocketAddr,
+	context: Context<P>,
+	/// Handshake id of the request of the other end, if this end accepted it.
+	accepted_handshake_id: Option<DataPrelude>,
+	is_path_confirmed: bool,
+	buffer: Vec<u8>,
+}
+
+impl<T: Transmit, P: Parcel> PeerConnection<T, P> {
+	/// Start punching a hole to the peer at provided public address, sending the first
+	/// connection request through provided transmitter.
+	///
+	/// Returns [`AddressFamilyMismatch`](ConnectError::AddressFamilyMismatch) if the remote address
+	/// is IPv4 while the transmitter is bound to an IPv6 address, or vice versa.
+	pub fn punch(transmitter: T, remote: SocketAddr) -> Result<Self, ConnectError> {
+		if let Some(local) = transmitter.local_addr() {
+			if local.is_ipv4() != remote.is_ipv4() {
+				return Err(ConnectError::AddressFamilyMismatch)
+			}
+		}
+		let max_datagram_length = transmitter.max_datagram_length();
+
+		let mut connection = Self {
+			transmitter,
+			remote,
+			context: Context::pending(),
+			accepted_handshake_id: None,
+			is_path_confirmed: false,
+			buffer: vec![0; max_datagram_length],
+		};
+		connection.send_request()?;
+		Ok(connection)
+	}
+
+	/// Send the connection request again, in case it was dropped before the hole was punched.
+	///
+	/// The connection must be [pending](ConnectionStatus::Pending).
+	pub fn send_request(&mut self) -> Result<(), ConnectError> {
+		let len = self.context.build_request_packet(&mut self.buffer, &[])?;
+		self.transmitter.send_to(&self.buffer[.. len], self.remote)?;
+		Ok(())
+	}
+
+	/// Get the transmitter used by the connection.
+	#[inline]
+	pub fn transmitter(&self) -> &T {
+		&self.transmitter
+	}
+
+	/// Get the public address of the peer.
+	#[inline]
+	pub fn remote_addr(&self) -> SocketAddr {
+		self.remote
+	}
+
+	/// Get the context of the connection.
+	#[inline]
+	pub fn context(&self) -> &Context<P> {
+		&self.context
+	}
+
+	/// Get the mutable context of the connection.
+	#[inline]
+	pub fn context_mut(&mut self) -> &mut Context<P> {
+		&mut self.context
+	}
+
+	/// Check whether datagrams were exchanged both ways over the punched path.
+	///
+	/// The peer whose request was accepted confirms the path with the accept packet, while the
+	/// accepting peer confirms it with the first received packet of the opened connection.
+	#[inline]
+	pub fn is_path_confirmed(&self) -> bool {
+		self.is_path_confirmed
+	}
+
+	/// Close the connection, informing the other end, see [`Context::close`](Context::close).
+	pub fn close(&mut self) -> Result<(), FlushError> {
+		let len = self.context.close(&mut self.buffer)?;
+		self.transmitter.send_to(&self.buffer[.. len], self.remote)?;
+		Ok(())
+	}
+
+	/// Receive all pending datagrams, processing those from the peer.
+	///
+	/// Connection requests of the peer are resolved as described in the
+	/// [module documentation](self), other packets are processed by the context.
+	///
+	/// # Returns
+	/// Number of received datagrams.
+	pub fn receive(&mut self) -> Result<usize, TransmitError> {
+		let mut datagram_count = 0;
+		loop {
+			let (len, src, received_at) = match self.transmitter.try_recv_from_timestamped(&mut self.buffer) {
+				Ok(result) => result,
+				Err(TransmitError::NoPendingPackets) => return Ok(datagram_count),
+				Err(TransmitError::MalformedPacket) => continue,
+				Err(error) => return Err(error),
+			};
+			datagram_count += 1;
+			if src != self.remote {
+				continue
+			}
+
+			let datagram = &self.buffer[.. len];
+			if packet::is_valid_connectionless(datagram) {
+				let header = packet::get_header(datagram);
+				if header.signal.is_signal_set(Signal::ConnectionRequest) {
+					self.process_request(header.prelude);
+					continue
+				}
+			}
+			// Packets the connection fails to process are simply dropped.
+			let is_processed = self.context.process_packet_received_at(datagram, received_at).is_ok();
+			if is_processed && self.context.status() == ConnectionStatus::Open {
+				self.is_path_confirmed = true;
+			}
+		}
+	}
+
+	/// Build and send all packets that are due, if the connection is
+	/// [open](ConnectionStatus::Open).
+	///
+	/// # Returns
+	/// Number of sent datagrams.
+	pub fn flush(&mut self) -> Result<usize, FlushError> {
+		let mut sent_count = 0;
+		if self.context.status() != ConnectionStatus::Open {
+			return Ok(sent_count)
+		}
+		loop {
+			let len = self.context.build_packet(&mut self.buffer)?;
+			if len == 0 {
+				return Ok(sent_count)
+			}
+			self.transmitter.send_to(&self.buffer[.. len], self.remote)?;
+			sent_count += 1;
+		}
+	}
+
+	/// Send a control packet acknowledging received packets right away, if any are yet to be
+	/// acknowledged and the connection is [open](ConnectionStatus::Open).
+	///
+	/// Useful before going idle after receiving, so that the other end does not re-transmit
+	/// packets it has no acknowledgement for. Unnecessary when [flushing](Self::flush) regularly
+	/// with the default [acknowledgement delay](Context::set_ack_delay), or when data is sent
+	/// anyway, as every packet carries the acknowledgements.
+	///
+	/// # Returns
+	/// Whether a packet was sent.
+	pub fn flush_acks(&mut self) -> Result<bool, FlushError> {
+		if self.context.status() != ConnectionStatus::Open || self.context.pending_ack_count() == 0 {
+			return Ok(false)
+		}
+		let len = self.context.build_ack_packet(&mut self.buffer)?;
+		self.transmitter.send_to(&self.buffer[.. len], self.remote)?;
+		Ok(true)
+	}
+
+	/// Resolve a received connection request of the peer with provided handshake id.
+	fn process_request(&mut self, handshake_id: DataPrelude) {
+		match self.context.status() {
+			ConnectionStatus::Pending => match self.context.handshake_id().cmp(&handshake_id) {
+				Ordering::Greater => {
+					let connection_id = random_connection_id();
+					// A failure to send the accept packet is recovered by the next request.
+					if listen::send_accept(&self.transmitter, &mut self.buffer, handshake_id, connection_id, &[], self.remote).is_ok() {
+						self.context.open_accepted(connection_id, handshake_id);
+						self.accepted_handshake_id = Some(handshake_id);
+					}
+				},
+				Ordering::Less => (),
+				Ordering::Equal => {
+					self.context.regenerate_handshake_id();
+					let _ = self.send_request();
+				},
+			},
+			ConnectionStatus::Open if self.accepted_handshake_id == Some(handshake_id) => {
+				// The accept packet was lost, a failure to resend it is recovered by the next request.
+				if let Some(connection_id) = self.context.connection_id() {
+					let _ = listen::send_accept(&self.transmitter, &mut self.buffer, handshake_id, connection_id, &[], self.remote);
+				}
+			},
+			_ => (),
+		}
+	}
+}
+
+/// Pick a random valid connection id.
+fn random_connection_id() -> ConnectionId {
+	loop {
+		let connection_id = random::<ConnectionId>();
+		if connection_id != 0 {
+			return connection_id
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	use std::net::UdpSocket;
+
+	/// Bind a non-blocking socket on provided address.
+	fn bind(addr: SocketAddr) -> UdpSocket {
+		let socket = UdpSocket::bind(addr).unwrap();
+		socket.set_nonblocking(true).unwrap();
+		socket
+	}
+
+	/// Receive a single datagram from provided socket, waiting for it to arrive.
+	fn recv(socket: &UdpSocket, buffer: &mut [u8]) -> usize {
+		loop {
+			if let Ok((len, _)) = socket.recv_from(buffer) {
+				return len
+			}
+		}
+	}
+
+	#[test]
+	fn crossing_requests_open_a_single_connection() {
+		let first_addr = SocketAddr::from(([ 127, 0, 0, 1, ], 10050));
+		let second_addr = SocketAddr::from(([ 127, 0, 0, 1, ], 10051));
+		let (first_socket, second_socket) = (bind(first_addr), bind(second_addr));
+		let mut first = PeerConnection::<_, u32>::punch(first_socket, second_addr).unwrap();
+		let mut second = PeerConnection::<_, u32>::punch(second_socket, first_addr).unwrap();
+
+		// Both requests are in flight before either end receives anything.
+		while first.receive().unwrap() == 0 {}
+		while second.receive().unwrap() == 0 {}
+		let (acceptor, requester) = if first.context().status() == ConnectionStatus::Open {
+			(&mut first, &mut second)
+		} else {
+			(&mut second, &mut first)
+		};
+		assert!(acceptor.accepted_handshake_id.is_some());
+		assert_eq!(requester.accepted_handshake_id, None);
+
+		// The requester already ignored the request of the acceptor, its accept packet is pending.
+		while requester.context().status() == ConnectionStatus::Pending {
+			requester.receive().unwrap();
+		}
+		assert_eq!(requester.context().status(), ConnectionStatus::Open);
+		assert_eq!(requester.context().connection_id(), acceptor.context().connection_id());
+		assert!(requester.is_path_confirmed());
+		assert!(!acceptor.is_path_confirmed());
+	}
+
+	#[test]
+	fn equal_handshake_ids_are_regenerated() {
+		let peer_addr = SocketAddr::from(([ 127, 0, 0, 1, ], 10052));
+		let remote_addr = SocketAddr::from(([ 127, 0, 0, 1, ], 10053));
+		let remote = bind(remote_addr);
+		let mut peer = PeerConnection::<_, u32>::punch(bind(peer_addr), remote_addr).unwrap();
+		let mut buffer = vec![0; 1200];
+
+		let len = recv(&remote, &mut buffer);
+		let handshake_id = packet::get_header(&buffer[.. len]).prelude;
+		remote.send_to(&buffer[.. len], peer_addr).unwrap();
+		while peer.receive().unwrap() == 0 {}
+		assert_eq!(peer.context().status(), ConnectionStatus::Pending);
+
+		let len = recv(&remote, &mut buffer);
+		let header = packet::get_header(&buffer[.. len]);
+		assert!(header.signal.is_signal_set(Signal::ConnectionRequest));
+		assert_ne!(header.prelude, handshake_id);
+	}
+
+	#[test]
+	fn peers_connect_simultaneously() {
+		let first_addr = SocketAddr::from(([ 127, 0, 0, 1, ], 10048));
+		let second_addr = SocketAddr::from(([ 127, 0, 0, 1, ], 10049));
+		let first_socket = bind(first_addr);
+		let second_socket = bind(second_addr);
+		let mut first = PeerConnection::<_, u32>::punch(first_socket, second_addr).unwrap();
+		let mut second = PeerConnection::<_, u32>::punch(second_socket, first_addr).unwrap();
+
+		while !first.is_path_confirmed() || !second.is_path_confirmed() {
+			first.receive().unwrap();
+			second.receive().unwrap();
+			for peer in [ &mut first, &mut second, ].iter_mut() {
+				if peer.context().status() == ConnectionStatus::Pending {
+					peer.send_request().unwrap();
+				} else if peer.accepted_handshake_id.is_none() {
+					// Confirm the path to the accepting peer.
+					peer.context_mut().push_volatile_parcel(0).unwrap();
+					peer.flush().unwrap();
+				}
+			}
+		}
+		assert_eq!(first.context().connection_id(), second.context().connection_id());
+		assert!(first.accepted_handshake_id.is_some() != second.accepted_handshake_id.is_some());
+
+		first.context_mut().push_reliable_parcel(42).unwrap();
+		first.flush().unwrap();
+		while second.receive().unwrap() == 0 {}
+		while let Ok((parcel, _)) = second.context_mut().pop_parcel() {
+			if parcel == 42 {
+				return
+			}
+		}
+		panic!("the reliable parcel was not delivered");
+	}
+}
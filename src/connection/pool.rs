@@ -0,0 +1,126 @@
+//! Recycling of byte buffers.
+//!
+//! Connections allocate a buffer for every queued parcel and every sent synchronized packet
+//! (kept for re-transmission). Users may provide their own [`BufferPool`](BufferPool)
+//! implementation (for example backed by a slab allocator or huge pages) to a
+//! [`Context`](super::context::Context) or a
+//! [`ConnectionListener`](super::listen::ConnectionListener) to control where those buffers come
+//! from and to reuse them instead of allocating anew. Buffers are acquired from and released to
+//! the pool by the library, buffers handed out to the application (such as
+//! [unconnected datagrams](super::listen::ConnectionListener::pop_unconnected)) may be released
+//! by it once processed.
+//!
+//! By default the [`HeapPool`](HeapPool) is used, which allocates every buffer with the standard
+//! allocator. The [`RecyclingPool`](RecyclingPool) keeps released buffers instead, so that once
+//! enough buffers are in circulation queuing parcels and sending packets performs no allocations.
+
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// A trait for objects that vend and recycle byte buffers.
+pub trait BufferPool: Send + Sync {
+	/// Get an empty buffer with a capacity of at least provided number of bytes.
+	fn acquire(&self, capacity: usize) -> Vec<u8>;
+
+	/// Return a buffer that is no longer used, to be vended again.
+	///
+	/// The buffer may have any length, as well as any capacity, including one smaller than what
+	/// it was acquired with.
+	fn release(&self, buffer: Vec<u8>);
+}
+
+/// A [`BufferPool`](BufferPool) allocating every buffer with the standard allocator, without
+/// recycling them.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct HeapPool;
+
+impl BufferPool for HeapPool {
+	#[inline]
+	fn acquire(&self, capacity: usize) -> Vec<u8> {
+		Vec::with_capacity(capacity)
+	}
+
+	#[inline]
+	fn release(&self, _: Vec<u8>) {}
+}
+
+/// A [`BufferPool`](BufferPool) keeping up to a fixed number of released buffers, to be vended
+/// again instead of allocating new ones.
+///
+/// A buffer is only reused if its capacity is large enough for the acquiring code, so buffers of
+/// an application that sends parcels of widely different sizes may be reused less. Further
+/// buffers released while the pool is full are freed.
+#[derive(Debug)]
+pub struct RecyclingPool {
+	buffers: Mutex<Vec<Vec<u8>>>,
+	max_buffer_count: usize,
+	allocation_count: AtomicUsize,
+}
+
+impl RecyclingPool {
+	/// Construct an empty pool keeping up to provided number of released buffers.
+	pub fn new(max_buffer_count: usize) -> Self {
+		Self {
+			buffers: Mutex::new(Vec::new()),
+			max_buffer_count,
+			allocation_count: AtomicUsize::new(0),
+		}
+	}
+
+	/// Get the number of buffers the pool allocated, as no kept buffer could be reused.
+	///
+	/// A count that keeps growing in a steady state hints at the pool being too small.
+	#[inline]
+	pub fn allocation_count(&self) -> usize {
+		self.allocation_count.load(Ordering::Relaxed)
+	}
+
+	/// Lock the kept buffers, recovering them if another thread panicked while holding the lock.
+	#[inline]
+	fn lock(&self) -> MutexGuard<'_, Vec<Vec<u8>>> {
+		self.buffers.lock().unwrap_or_else(PoisonError::into_inner)
+	}
+}
+
+impl BufferPool for RecyclingPool {
+	fn acquire(&self, capacity: usize) -> Vec<u8> {
+		let mut buffers = self.lock();
+		match buffers.iter().rposition(|buffer| buffer.capacity() >= capacity) {
+			Some(index) => buffers.swap_remove(index),
+			None => {
+				self.allocation_count.fetch_add(1, Ordering::Relaxed);
+				Vec::with_capacity(capacity)
+			},
+		}
+	}
+
+	fn release(&self, mut buffer: Vec<u8>) {
+		let mut buffers = self.lock();
+		if buffers.len() < self.max_buffer_count && buffer.capacity() > 0 {
+			buffer.clear();
+			buffers.push(buffer);
+		}
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	#[test]
+	fn recycling_pool_survives_poisoning() {
+		let pool = RecyclingPool::new(1);
+		pool.release(Vec::with_capacity(8));
+		std::thread::scope(|scope| {
+			scope.spawn(|| {
+				let _guard = pool.buffers.lock().unwrap();
+				panic!("poisoning the pool");
+			}).join().unwrap_err();
+		});
+
+		assert!(pool.buffers.is_poisoned());
+		assert!(pool.acquire(8).capacity() >= 8);
+		assert_eq!(pool.allocation_count(), 0);
+		pool.release(Vec::with_capacity(8));
+	}
+}
@@ -0,0 +1,148 @@
+//! Dispatching of received parcels to handlers by their data prelude.
+
+use super::Parcel;
+use super::context::Context;
+use super::error::ConnectionError;
+use super::packet::DataPrelude;
+
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// A handler of routed parcels.
+pub type Handler<'a, P> = Box<dyn FnMut(P, DataPrelude) + 'a>;
+
+/// Dispatches received parcels to handlers registered for their [`DataPrelude`](DataPrelude).
+///
+/// Parcels are routed by a key derived from the prelude. By default the whole prelude is the
+/// key, while [`with_key`](Self::with_key) allows routing by a part of it, for example a
+/// message-type tag in the first byte. Parcels with no registered handler are returned to the
+/// caller.
+///
+/// The sending end selects the prelude with [`Context::set_prelude`](Context::set_prelude).
+pub struct ParcelRouter<'a, P: Parcel, K = DataPrelude> {
+	key: fn(&DataPrelude) -> K,
+	handlers: HashMap<K, Handler<'a, P>>,
+}
+
+impl<'a, P: Parcel> ParcelRouter<'a, P> {
+	/// Construct a router keyed by the whole data prelude.
+	pub fn new() -> Self {
+		Self::with_key(|prelude| *prelude)
+	}
+}
+
+impl<'a, P: Parcel> Default for ParcelRouter<'a, P> {
+	#[inline]
+	fn default() -> Self {
+		Self::new()
+	}
+}
+
+impl<'a, P: Parcel, K: Hash + Eq> ParcelRouter<'a, P, K> {
+	/// Construct a router keyed by the result of provided function of the data prelude.
+	pub fn with_key(key: fn(&DataPrelude) -> K) -> Self {
+		Self {
+			key,
+			handlers: HashMap::new(),
+		}
+	}
+
+	/// Register a handler for parcels with provided key, replacing any previous handler.
+	pub fn register<F: FnMut(P, DataPrelude) + 'a>(&mut self, key: K, handler: F) {
+		self.handlers.insert(key, Box::new(handler));
+	}
+
+	/// Remove the handler registered for provided key.
+	///
+	/// Returns whether a handler was registered.
+	pub fn unregister(&mut self, key: &K) -> bool {
+		self.handlers.remove(key).is_some()
+	}
+
+	/// Dispatch provided parcel to the handler registered for its prelude.
+	///
+	/// Returns the parcel back if no handler is registered for it.
+	pub fn dispatch(&mut self, parcel: P, prelude: DataPrelude) -> Option<(P, DataPrelude)> {
+		match self.handlers.get_mut(&(self.key)(&prelude)) {
+			Some(handler) => {
+				handler(parcel, prelude);
+				None
+			},
+			None => Some((parcel, prelude)),
+		}
+	}
+
+	/// Pop the next parcel of provided connection and dispatch it.
+	///
+	/// Returns the parcel back if no handler is registered for it, or
+	/// [`NoPendingParcels`](ConnectionError::NoPendingParcels) if the connection has no
+	/// received parcels.
+	pub fn dispatch_next(&mut self, context: &mut Context<P>) -> Result<Option<(P, DataPrelude)>, ConnectionError> {
+		let (parcel, prelude) = context.pop_parcel()?;
+		Ok(self.dispatch(parcel, prelude))
+	}
+
+	/// Pop and dispatch all received parcels of provided connection.
+	///
+	/// Returns the parcels that no handler is registered for, in the order they were popped.
+	pub fn dispatch_all(&mut self, context: &mut Context<P>) -> Vec<(P, DataPrelude)> {
+		let mut unhandled = Vec::new();
+		while let Ok(result) = self.dispatch_next(context) {
+			unhandled.extend(result);
+		}
+		unhandled
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	use std::cell::RefCell;
+
+	#[test]
+	fn parcels_are_routed_by_prelude_tag() {
+		let mut buffer = vec![0; 1200];
+		let mut client = Context::<u32>::accept(1);
+		let mut server = Context::<u32>::accept(1);
+
+		client.set_prelude([ 1, 0, 0, 0, ]);
+		client.push_reliable_parcel(10).unwrap();
+		client.set_prelude([ 2, 0, 0, 0, ]);
+		client.push_reliable_parcel(20).unwrap();
+		client.set_prelude([ 3, 0, 0, 0, ]);
+		client.push_reliable_parcel(30).unwrap();
+		while let Ok(len) = client.build_packet(&mut buffer) {
+			if len == 0 {
+				break
+			}
+			server.process_packet(&buffer[.. len]).unwrap();
+		}
+
+		let first = RefCell::new(Vec::new());
+		let second = RefCell::new(Vec::new());
+		let mut router = ParcelRouter::with_key(|prelude| prelude[0]);
+		router.register(1, |parcel, _| first.borrow_mut().push(parcel));
+		router.register(2, |parcel, _| second.borrow_mut().push(parcel));
+
+		assert_eq!(router.dispatch_all(&mut server), vec![(30, [ 3, 0, 0, 0, ])]);
+		assert_eq!(router.dispatch_next(&mut server), Err(ConnectionError::NoPendingParcels));
+		drop(router);
+		assert_eq!(first.into_inner(), vec![10]);
+		assert_eq!(second.into_inner(), vec![20]);
+	}
+
+	#[test]
+	fn unregistered_parcels_are_returned() {
+		let mut count = 0;
+		let mut router = ParcelRouter::<u32>::new();
+		router.register([ 1, 2, 3, 4, ], |_, _| count += 1);
+
+		assert_eq!(router.dispatch(5, [ 1, 2, 3, 4, ]), None);
+		assert_eq!(router.dispatch(6, [ 4, 3, 2, 1, ]), Some((6, [ 4, 3, 2, 1, ])));
+		assert!(router.unregister(&[ 1, 2, 3, 4, ]));
+		assert_eq!(router.dispatch(7, [ 1, 2, 3, 4, ]), Some((7, [ 1, 2, 3, 4, ])));
+		drop(router);
+		assert_eq!(count, 1);
+	}
+}
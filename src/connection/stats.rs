@@ -0,0 +1,126 @@
+//! Connection statistics readable from other threads.
+//!
+//! The counters of a [`Context`](super::context::Context) are atomics shared with every
+//! [`StatsHandle`](StatsHandle) obtained from it, so a monitoring thread may take
+//! [snapshots](StatsHandle::snapshot) without locking the context, which may be owned by the
+//! receive path, for example within a [listener](super::listen::ConnectionListener) behind a
+//! mutex.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// A snapshot of the counters of a connection.
+///
+/// Counters are read one by one, so a snapshot taken while the connection is used may be
+/// slightly inconsistent, for example count a received packet but not the parcels it dropped.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct ConnectionStats {
+	/// Number of built packets, including re-transmissions.
+	pub sent_packet_count: u64,
+	/// Number of bytes of built packets, including padding.
+	pub sent_byte_count: u64,
+	/// Number of successfully processed packets.
+	pub received_packet_count: u64,
+	/// Number of bytes of successfully processed packets.
+	pub received_byte_count: u64,
+	/// Number of synchronized packets that were re-transmitted.
+	pub retransmitted_packet_count: u64,
+	/// Number of sent synchronized packets deemed lost, either by timeout or by later packets
+	/// being acknowledged. Unlike re-transmissions, excludes
+	/// [forced](super::context::Context::resend_unacked) ones. Losses hint at congestion.
+	pub lost_packet_count: u64,
+	/// Number of received packets that were [malformed](super::error::ProcessPacketError::MalformedPacket),
+	/// including those that failed to decompress. Unlike losses, corruption hints at a faulty link
+	/// or a misbehaving peer.
+	pub malformed_packet_count: u64,
+	/// Number of received packets that were sent by the connection itself and looped back by a
+	/// misconfigured network, which are dropped, see
+	/// [`ProcessPacketError::LoopedBackPacket`](super::error::ProcessPacketError::LoopedBackPacket).
+	pub looped_back_packet_count: u64,
+	/// Number of times a black hole was detected, lowering the length of sent datagrams, see
+	/// [`Context::is_black_hole_detected`](super::context::Context::is_black_hole_detected).
+	pub black_hole_count: u64,
+	/// Number of received synchronized packets that were dropped as duplicates, see
+	/// [`SequenceState::duplicate_packet_count`](super::context::SequenceState::duplicate_packet_count).
+	pub duplicate_packet_count: u64,
+	/// Number of received parcels dropped due to a full queue, see
+	/// [`Context::dropped_parcel_count`](super::context::Context::dropped_parcel_count).
+	pub dropped_parcel_count: u64,
+	/// Number of queued volatile parcels dropped as they expired, see
+	/// [`Context::expired_parcel_count`](super::context::Context::expired_parcel_count).
+	pub expired_parcel_count: u64,
+}
+
+/// A cheaply cloneable handle to the counters of a connection.
+///
+/// Obtained with [`Context::stats_handle`](super::context::Context::stats_handle), the handle
+/// stays valid after the context is dropped, reporting its final counters.
+#[derive(Debug, Clone)]
+pub struct StatsHandle {
+	counters: Arc<Counters>,
+}
+
+impl StatsHandle {
+	/// Take a snapshot of the counters, without locking.
+	#[inline]
+	pub fn snapshot(&self) -> ConnectionStats {
+		self.counters.snapshot()
+	}
+}
+
+/// Atomic counters of a connection, shared with its [`StatsHandle`](StatsHandle)s.
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+	pub(crate) sent_packets: AtomicU64,
+	pub(crate) sent_bytes: AtomicU64,
+	pub(crate) received_packets: AtomicU64,
+	pub(crate) received_bytes: AtomicU64,
+	pub(crate) retransmitted_packets: AtomicU64,
+	pub(crate) lost_packets: AtomicU64,
+	pub(crate) malformed_packets: AtomicU64,
+	pub(crate) looped_back_packets: AtomicU64,
+	pub(crate) black_holes: AtomicU64,
+	pub(crate) duplicate_packets: AtomicU64,
+	pub(crate) dropped_parcels: AtomicU64,
+	pub(crate) expired_parcels: AtomicU64,
+}
+
+impl Counters {
+	/// Construct a handle sharing the counters.
+	#[inline]
+	pub(crate) fn handle(self: &Arc<Self>) -> StatsHandle {
+		StatsHandle { counters: self.clone() }
+	}
+
+	/// Take a snapshot of the counters.
+	pub(crate) fn snapshot(&self) -> ConnectionStats {
+		ConnectionStats {
+			sent_packet_count: load(&self.sent_packets),
+			sent_byte_count: load(&self.sent_bytes),
+			received_packet_count: load(&self.received_packets),
+			received_byte_count: load(&self.received_bytes),
+			retransmitted_packet_count: load(&self.retransmitted_packets),
+			lost_packet_count: load(&self.lost_packets),
+			malformed_packet_count: load(&self.malformed_packets),
+			looped_back_packet_count: load(&self.looped_back_packets),
+			black_hole_count: load(&self.black_holes),
+			duplicate_packet_count: load(&self.duplicate_packets),
+			dropped_parcel_count: load(&self.dropped_parcels),
+			expired_parcel_count: load(&self.expired_parcels),
+		}
+	}
+}
+
+/// Add provided value to provided counter.
+///
+/// Counters are only written by the owner of the context, so relaxed ordering suffices.
+#[inline]
+pub(crate) fn add(counter: &AtomicU64, value: u64) {
+	counter.fetch_add(value, Ordering::Relaxed);
+}
+
+/// Read provided counter.
+#[inline]
+pub(crate) fn load(counter: &AtomicU64) -> u64 {
+	counter.load(Ordering::Relaxed)
+}
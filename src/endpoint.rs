@@ -11,6 +11,7 @@
 
 use std::io::Error as IoError;
 use std::net::{ToSocketAddrs, SocketAddr};
+use std::time::Instant;
 
 pub mod transmit;
 pub mod demux;
@@ -25,15 +26,34 @@ pub trait Open: Sized {
 }
 
 impl<T: Transmit, D> Transmit for (T, D) {
-	const MAX_FRAME_LENGTH: usize = T::MAX_FRAME_LENGTH;
+	#[inline]
+	fn max_datagram_length(&self) -> usize {
+		self.0.max_datagram_length()
+	}
 	#[inline]
 	fn send_to(&self, data: &[u8], addr: SocketAddr) -> Result<usize, IoError> {
 		self.0.send_to(data, addr)
 	}
 	#[inline]
+	fn send_segments_to(&self, data: &[u8], segment_length: usize, addr: SocketAddr) -> Result<usize, IoError> {
+		self.0.send_segments_to(data, segment_length, addr)
+	}
+	#[inline]
+	fn send_batch_to(&self, datagrams: &[(&[u8], SocketAddr)]) -> Result<usize, IoError> {
+		self.0.send_batch_to(datagrams)
+	}
+	#[inline]
 	fn try_recv_from(&self, buffer: &mut [u8]) -> Result<(usize, SocketAddr), TransmitError> {
 		self.0.try_recv_from(buffer)
 	}
+	#[inline]
+	fn try_recv_from_timestamped(&self, buffer: &mut [u8]) -> Result<(usize, SocketAddr, Instant), TransmitError> {
+		self.0.try_recv_from_timestamped(buffer)
+	}
+	#[inline]
+	fn local_addr(&self) -> Option<SocketAddr> {
+		self.0.local_addr()
+	}
 }
 
 impl<T, K, D: Demux<K>> Demux<K> for (T, D) {
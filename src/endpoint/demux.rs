@@ -1,12 +1,16 @@
 //! [`Demux`](Demux) trait definition, implementation and test.
 
 #[cfg(test)]
-pub mod test;
+pub(crate) mod test;
 
 use std::collections::HashMap;
 use std::hash::Hash;
 use std::net::SocketAddr;
 
+/// Maximum number of bytes buffered per key by the [`HashMap`](HashMap) demultiplexer, further
+/// datagrams are dropped until the buffered ones are [processed](Demux::process).
+pub const MAX_BUFFERED_BYTES: usize = 1 << 20;
+
 /// A trait for connection demultiplexers.
 ///
 /// Demultiplexers allow multiple connections to use the same endpoint simultaneously by
@@ -27,9 +31,11 @@ pub trait Demux<K> {
 	///
 	/// # Notes
 	/// - The length and source address of the datagram should be recorded as it needs
-	/// to be returned with [`pop`](Mux::pop).
+	///   to be returned with [`process`](Demux::process).
 	/// - The connection may be assumed to be allowed at the time of invocation.
 	/// - The implementation may assume the key is allowed at the time of invocation.
+	/// - The implementation may drop the datagram if its buffer for the key is full, as
+	///   unreliable transport permits, rather than growing the buffer without bounds.
 	fn push(&mut self, key: K, dgram: (&[u8], SocketAddr));
 
 	/// Process buffered datagrams associated with provided key by invoking the provided functor.
@@ -50,7 +56,7 @@ pub trait Demux<K> {
 impl<K: Hash + Eq> Demux<K> for HashMap<K, (Vec<u8>, Vec<(usize, SocketAddr)>)> {
 	#[inline]
 	fn allow(&mut self, key: K) {
-		self.entry(key).or_insert_with(Default::default);
+		self.entry(key).or_default();
 	}
 	#[inline]
 	fn block(&mut self, key: K) {
@@ -63,6 +69,9 @@ impl<K: Hash + Eq> Demux<K> for HashMap<K, (Vec<u8>, Vec<(usize, SocketAddr)>)>
 
 	fn push(&mut self, key: K, dgram: (&[u8], SocketAddr)) {
 		let (bytes, infos) = self.get_mut(&key).unwrap();
+		if bytes.len() + dgram.0.len() > MAX_BUFFERED_BYTES {
+			return
+		}
 		bytes.extend_from_slice(dgram.0);
 		infos.push((dgram.0.len(), dgram.1));
 	}
@@ -73,6 +82,7 @@ impl<K: Hash + Eq> Demux<K> for HashMap<K, (Vec<u8>, Vec<(usize, SocketAddr)>)>
 			functor((&bytes[offset .. offset + *len], *src));
 			offset += *len;
 		}
+		// Clearing keeps the capacity, so the buffers are reused by following pushes.
 		infos.clear();
 		bytes.clear();
 	}
@@ -1,6 +1,6 @@
 //! Generic functions for testing [`Demux`](Demux) implementations.
 
-use super::Demux;
+use super::{Demux, MAX_BUFFERED_BYTES};
 
 use std::collections::HashMap;
 use std::net::SocketAddr;
@@ -53,3 +53,19 @@ fn hash_map_demultiplexes() {
 	let mut hash_map = HashMap::new();
 	generic_demux_test(&mut hash_map);
 }
+
+#[test]
+fn hash_map_caps_buffered_bytes() {
+	let mut hash_map = HashMap::new();
+	let dgram = [0; 1024];
+	let addr = SocketAddr::from(([ 127, 0, 0, 1, ], 0));
+	hash_map.allow(0);
+	for _ in 0 ..= MAX_BUFFERED_BYTES / dgram.len() {
+		hash_map.push(0, (&dgram[..], addr));
+	}
+	assert_eq!(hash_map.get_buffered_counts(0), (MAX_BUFFERED_BYTES / dgram.len(), MAX_BUFFERED_BYTES));
+
+	hash_map.process(0, |_| ());
+	assert_eq!(hash_map.get_buffered_counts(0), (0, 0));
+	assert!(hash_map[&0].0.capacity() >= MAX_BUFFERED_BYTES);
+}
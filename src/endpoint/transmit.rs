@@ -1,11 +1,33 @@
 //! [`Transmit`](Transmit) trait definition, implementation and unit test.
 
 mod basic;
+#[cfg(target_os = "linux")]
+mod device;
+#[cfg(target_os = "linux")]
+mod gso;
+#[cfg(target_os = "linux")]
+mod mmsg;
+#[cfg(any(test, feature = "test-util"))]
+mod record;
+#[cfg(target_os = "linux")]
+mod reuseport;
+#[cfg(target_os = "linux")]
+mod timestamp;
 #[cfg(test)]
-pub mod test;
+pub(crate) mod test;
+
+#[cfg(target_os = "linux")]
+pub use device::bind_to_device;
+#[cfg(any(test, feature = "test-util"))]
+pub use record::{Record, RecordingTransmit};
+#[cfg(target_os = "linux")]
+pub use reuseport::bind_reuse_port;
+#[cfg(target_os = "linux")]
+pub use timestamp::set_receive_timestamps;
 
 use std::io::{Error as IoError, ErrorKind as IoErrorKind};
 use std::net::SocketAddr;
+use std::time::Instant;
 
 /// An error associated with an endpoint.
 #[derive(Debug)]
@@ -44,16 +66,85 @@ pub trait Transmit {
 	/// responsible for the failure.
 	///
 	/// # Note
-	/// Implementation may assume data is at most [`MAX_FRAME_LENGTH`](MAX_FRAME_LENGTH) bytes.
+	/// Implementation may assume data is at most
+	/// [`max_datagram_length()`](Self::max_datagram_length) bytes.
 	fn send_to(&self, data: &[u8], addr: SocketAddr) -> Result<usize, IoError>;
 
+	/// Send provided data as consecutive datagrams of `segment_length` bytes to the provided
+	/// address. The last datagram may be shorter.
+	///
+	/// Return the total number of bytes sent. Or the error responsible for the failure.
+	///
+	/// The default implementation sends each segment separately. Implementations may reduce the
+	/// number of system calls for such same-destination bursts, for example by using UDP generic
+	/// segmentation offload on Linux.
+	///
+	/// # Note
+	/// Implementation may assume `segment_length` is at most
+	/// [`max_datagram_length()`](Self::max_datagram_length) bytes.
+	fn send_segments_to(&self, data: &[u8], segment_length: usize, addr: SocketAddr) -> Result<usize, IoError> {
+		if segment_length == 0 {
+			return Err(IoError::new(IoErrorKind::InvalidInput, "segment length must be non-zero"))
+		}
+		let mut byte_count = 0;
+		for segment in data.chunks(segment_length) {
+			byte_count += self.send_to(segment, addr)?;
+		}
+		Ok(byte_count)
+	}
+
+	/// Send provided datagrams, each to its own address.
+	///
+	/// Return the number of datagrams sent, which may be fewer than provided. An error is only
+	/// returned if not even the first datagram could be sent.
+	///
+	/// The default implementation sends each datagram separately. Implementations may reduce the
+	/// number of system calls, for example by using `sendmmsg` on Linux.
+	///
+	/// # Note
+	/// Implementation may assume each datagram is at most
+	/// [`max_datagram_length()`](Self::max_datagram_length) bytes.
+	fn send_batch_to(&self, datagrams: &[(&[u8], SocketAddr)]) -> Result<usize, IoError> {
+		for (index, &(data, addr)) in datagrams.iter().enumerate() {
+			if let Err(error) = self.send_to(data, addr) {
+				return if index == 0 { Err(error) } else { Ok(index) }
+			}
+		}
+		Ok(datagrams.len())
+	}
+
 	/// Attempt to recover an incoming datagram.
 	///
 	/// Return the number of bytes written to the buffer and the origin of the datagram on success.
 	///
 	/// # Note
-	/// - May assume the buffer is able to hold [`MAX_FRAME_LENGTH`](MAX_FRAME_LENGTH) bytes.
+	/// - May assume the buffer is able to hold
+	///   [`max_datagram_length()`](Self::max_datagram_length) bytes.
 	fn try_recv_from(&self, buffer: &mut [u8]) -> Result<(usize, SocketAddr), TransmitError>;
+
+	/// Attempt to recover an incoming datagram, along with the moment it was received.
+	///
+	/// The default implementation timestamps the datagram with the current time once it is
+	/// received. Implementations may provide more accurate timestamps, for example the kernel
+	/// receive timestamps on Linux (see `set_receive_timestamps`).
+	///
+	/// # Note
+	/// - May assume the buffer is able to hold
+	///   [`max_datagram_length()`](Self::max_datagram_length) bytes.
+	#[inline]
+	fn try_recv_from_timestamped(&self, buffer: &mut [u8]) -> Result<(usize, SocketAddr, Instant), TransmitError> {
+		let (len, addr) = self.try_recv_from(buffer)?;
+		Ok((len, addr, Instant::now()))
+	}
+
+	/// Get the local address the transmitter is bound to, if known.
+	///
+	/// Used to detect mismatched address families before sending. The default implementation
+	/// returns `None`.
+	#[inline]
+	fn local_addr(&self) -> Option<SocketAddr> {
+		None
+	}
 }
 
 impl From<IoError> for TransmitError {
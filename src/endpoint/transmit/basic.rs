@@ -6,6 +6,8 @@ use super::{Transmit, TransmitError};
 
 use std::io::Error as IoError;
 use std::net::{ToSocketAddrs, SocketAddr, UdpSocket};
+#[cfg(target_os = "linux")]
+use std::time::Instant;
 
 impl Transmit for UdpSocket {
 	#[inline]
@@ -18,11 +20,34 @@ impl Transmit for UdpSocket {
 	fn send_to(&self, data: &[u8], addr: SocketAddr) -> Result<usize, IoError> {
 		self.send_to(data, addr)
 	}
+
+	#[cfg(target_os = "linux")]
+	#[inline]
+	fn send_segments_to(&self, data: &[u8], segment_length: usize, addr: SocketAddr) -> Result<usize, IoError> {
+		super::gso::send_segments_to(self, data, segment_length, addr)
+	}
+
+	#[cfg(target_os = "linux")]
+	#[inline]
+	fn send_batch_to(&self, datagrams: &[(&[u8], SocketAddr)]) -> Result<usize, IoError> {
+		super::mmsg::send_batch_to(self, datagrams)
+	}
 	
 	#[inline]
 	fn try_recv_from(&self, buffer: &mut [u8]) -> Result<(usize, SocketAddr), TransmitError> {
 		Ok(self.recv_from(buffer)?)
 	}
+
+	#[cfg(target_os = "linux")]
+	#[inline]
+	fn try_recv_from_timestamped(&self, buffer: &mut [u8]) -> Result<(usize, SocketAddr, Instant), TransmitError> {
+		super::timestamp::recv_from_timestamped(self, buffer)
+	}
+
+	#[inline]
+	fn local_addr(&self) -> Option<SocketAddr> {
+		UdpSocket::local_addr(self).ok()
+	}
 }
 
 impl Open for UdpSocket {
@@ -43,3 +68,72 @@ fn udp_socket_transmits() {
 
 	super::test::generic_transmit_test((&sender, sender_addr), (&receiver, receiver_addr))
 }
+
+#[cfg(test)]
+#[test]
+fn udp_socket_transmits_segments() {
+	let sender_addr = SocketAddr::from(([ 127, 0, 0, 1, ], 10002));
+	let sender = UdpSocket::bind(sender_addr).unwrap();
+
+	let receiver_addr = SocketAddr::from(([ 127, 0, 0, 1, ], 10003));
+	let receiver = UdpSocket::bind(receiver_addr).unwrap();
+
+	super::test::generic_segmented_transmit_test((&sender, sender_addr), (&receiver, receiver_addr))
+}
+
+#[cfg(test)]
+#[test]
+fn udp_socket_transmits_batches() {
+	let sender_addr = SocketAddr::from(([ 127, 0, 0, 1, ], 10004));
+	let sender = UdpSocket::bind(sender_addr).unwrap();
+
+	let receiver_addr = SocketAddr::from(([ 127, 0, 0, 1, ], 10005));
+	let receiver = UdpSocket::bind(receiver_addr).unwrap();
+
+	super::test::generic_batch_transmit_test((&sender, sender_addr), (&receiver, receiver_addr))
+}
+
+#[cfg(all(test, target_os = "linux"))]
+#[test]
+fn udp_socket_timestamps_received_datagrams() {
+	use std::time::Duration;
+
+	let sender_addr = SocketAddr::from(([ 127, 0, 0, 1, ], 10006));
+	let sender = UdpSocket::bind(sender_addr).unwrap();
+
+	let receiver_addr = SocketAddr::from(([ 127, 0, 0, 1, ], 10007));
+	let receiver = UdpSocket::bind(receiver_addr).unwrap();
+	super::set_receive_timestamps(&receiver, true).unwrap();
+
+	let sent_time = Instant::now();
+	sender.send_to(b"timestamped", receiver_addr).unwrap();
+	std::thread::sleep(Duration::from_millis(50));
+
+	let mut buffer = [0; 32];
+	let receive_time = Instant::now();
+	let (len, addr, timestamp) = receiver.try_recv_from_timestamped(&mut buffer).unwrap();
+	assert_eq!((&buffer[.. len], addr), (&b"timestamped"[..], sender_addr));
+	// The datagram is timestamped on arrival, not when it is received by the application.
+	assert!(timestamp + Duration::from_millis(25) < receive_time);
+	assert!(timestamp + Duration::from_millis(25) > sent_time);
+}
+
+#[cfg(all(test, target_os = "linux"))]
+#[test]
+fn udp_socket_binds_to_device() {
+	let sender_addr = SocketAddr::from(([ 127, 0, 0, 1, ], 10008));
+	let sender = UdpSocket::bind(sender_addr).unwrap();
+
+	let receiver_addr = SocketAddr::from(([ 127, 0, 0, 1, ], 10009));
+	let receiver = UdpSocket::bind(receiver_addr).unwrap();
+	super::bind_to_device(&receiver, Some("lo")).unwrap();
+
+	sender.send_to(b"pinned", receiver_addr).unwrap();
+	let mut buffer = [0; 16];
+	let (len, addr) = receiver.recv_from(&mut buffer).unwrap();
+	assert_eq!((&buffer[.. len], addr), (&b"pinned"[..], sender_addr));
+
+	super::bind_to_device(&receiver, None).unwrap();
+	assert!(super::bind_to_device(&receiver, Some("an-overly-long-interface-name")).is_err());
+	assert!(super::bind_to_device(&receiver, Some("lo\0")).is_err());
+}
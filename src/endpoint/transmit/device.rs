@@ -0,0 +1,42 @@
+//! Binding sockets to network interfaces with `SO_BINDTODEVICE` on Linux.
+//!
+//! On hosts with multiple network interfaces binding to an address does not guarantee which
+//! interface the traffic goes through. A socket bound to an interface only receives datagrams
+//! arriving at that interface and only sends datagrams through it.
+
+use std::ffi::CString;
+use std::io::{Error as IoError, ErrorKind as IoErrorKind};
+use std::net::UdpSocket;
+use std::os::unix::io::AsRawFd;
+
+/// Bind provided socket to the network interface with provided name, or remove the binding.
+///
+/// The interface name is limited to `IFNAMSIZ - 1` (15) bytes, longer names and names containing
+/// a nul byte are rejected with [`InvalidInput`](IoErrorKind::InvalidInput).
+///
+/// # Platform support
+/// Only available on Linux. Kernels older than 5.7 require the `CAP_NET_RAW` capability to bind
+/// a socket to an interface.
+pub fn bind_to_device(socket: &UdpSocket, interface: Option<&str>) -> Result<(), IoError> {
+	let name = match interface {
+		Some(interface) if interface.len() < libc::IFNAMSIZ => CString::new(interface)
+			.map_err(|_| IoError::from(IoErrorKind::InvalidInput))?,
+		Some(_) => return Err(IoErrorKind::InvalidInput.into()),
+		None => CString::default(),
+	};
+	let name = name.as_bytes_with_nul();
+	let result = unsafe {
+		libc::setsockopt(
+			socket.as_raw_fd(),
+			libc::SOL_SOCKET,
+			libc::SO_BINDTODEVICE,
+			name.as_ptr() as *const libc::c_void,
+			name.len() as libc::socklen_t,
+		)
+	};
+	if result < 0 {
+		Err(IoError::last_os_error())
+	} else {
+		Ok(())
+	}
+}
@@ -0,0 +1,149 @@
+//! UDP generic segmentation offload (GSO) support for Linux.
+//!
+//! GSO lets the kernel split a single large write into multiple datagrams of equal size, reducing
+//! the number of system calls for bursts of datagrams sent to the same destination.
+
+use std::io::Error as IoError;
+use std::mem::{size_of, zeroed};
+use std::net::{SocketAddr, UdpSocket};
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Maximum number of segments the kernel accepts in a single send.
+const MAX_SEGMENT_COUNT: usize = 64;
+
+/// Maximum number of bytes sent in a single send, leaving room for IPv6 and UDP headers.
+const MAX_SEND_BYTE_COUNT: usize = u16::MAX as usize - 48;
+
+/// Cleared once the kernel reports lack of GSO support.
+static IS_GSO_SUPPORTED: AtomicBool = AtomicBool::new(true);
+
+/// Control message buffer for a single `UDP_SEGMENT` message, aligned for `cmsghdr`.
+#[repr(C, align(8))]
+struct ControlBuffer([u8; 32]);
+
+/// Send provided data as consecutive datagrams of `segment_length` bytes using UDP GSO.
+///
+/// Falls back to sending each datagram separately if GSO is not supported.
+pub fn send_segments_to(
+	socket: &UdpSocket,
+	data: &[u8],
+	segment_length: usize,
+	addr: SocketAddr,
+) -> Result<usize, IoError> {
+	if segment_length == 0 || segment_length > u16::MAX as usize {
+		return Err(IoError::from_raw_os_error(libc::EINVAL))
+	}
+
+	let segments_per_send = MAX_SEGMENT_COUNT.min(MAX_SEND_BYTE_COUNT / segment_length).max(1);
+	let mut byte_count = 0;
+	for burst in data.chunks(segments_per_send * segment_length) {
+		byte_count += if burst.len() > segment_length && IS_GSO_SUPPORTED.load(Ordering::Relaxed) {
+			match send_burst(socket, burst, segment_length, addr) {
+				Err(error) if is_unsupported(&error) => {
+					IS_GSO_SUPPORTED.store(false, Ordering::Relaxed);
+					send_separately(socket, burst, segment_length, addr)?
+				},
+				Err(error) if is_rejected(&error) => send_separately(socket, burst, segment_length, addr)?,
+				result => result?,
+			}
+		} else {
+			send_separately(socket, burst, segment_length, addr)?
+		}
+	}
+	Ok(byte_count)
+}
+
+/// Send each segment of provided data as a separate datagram.
+fn send_separately(
+	socket: &UdpSocket,
+	data: &[u8],
+	segment_length: usize,
+	addr: SocketAddr,
+) -> Result<usize, IoError> {
+	let mut byte_count = 0;
+	for segment in data.chunks(segment_length) {
+		byte_count += socket.send_to(segment, addr)?;
+	}
+	Ok(byte_count)
+}
+
+/// Send a burst of segments with a single `sendmsg` call carrying a `UDP_SEGMENT` control message.
+fn send_burst(
+	socket: &UdpSocket,
+	burst: &[u8],
+	segment_length: usize,
+	addr: SocketAddr,
+) -> Result<usize, IoError> {
+	let (mut address, address_length) = raw_address(addr);
+	let mut iov = libc::iovec {
+		iov_base: burst.as_ptr() as *mut libc::c_void,
+		iov_len: burst.len(),
+	};
+	let mut control = ControlBuffer([0; 32]);
+
+	let result = unsafe {
+		let control_length = libc::CMSG_SPACE(size_of::<u16>() as u32) as usize;
+		debug_assert!(control_length <= control.0.len());
+
+		let mut message: libc::msghdr = zeroed();
+		message.msg_name = &mut address as *mut libc::sockaddr_storage as *mut libc::c_void;
+		message.msg_namelen = address_length;
+		message.msg_iov = &mut iov;
+		message.msg_iovlen = 1;
+		message.msg_control = control.0.as_mut_ptr() as *mut libc::c_void;
+		message.msg_controllen = control_length as _;
+
+		let header = libc::CMSG_FIRSTHDR(&message);
+		(*header).cmsg_level = libc::SOL_UDP;
+		(*header).cmsg_type = libc::UDP_SEGMENT;
+		(*header).cmsg_len = libc::CMSG_LEN(size_of::<u16>() as u32) as _;
+		std::ptr::write_unaligned(libc::CMSG_DATA(header) as *mut u16, segment_length as u16);
+
+		libc::sendmsg(socket.as_raw_fd(), &message, 0)
+	};
+
+	if result < 0 {
+		Err(IoError::last_os_error())
+	} else {
+		Ok(result as usize)
+	}
+}
+
+/// Check whether the error indicates that the kernel does not support GSO at all.
+fn is_unsupported(error: &IoError) -> bool {
+	matches!(error.raw_os_error(), Some(libc::ENOPROTOOPT) | Some(libc::EOPNOTSUPP))
+}
+
+/// Check whether the error indicates that this particular burst could not be segmented, for
+/// example by a network device without checksum offload.
+///
+/// Such errors are specific to the socket or the route, so the burst is sent separately without
+/// disabling GSO for other sockets.
+fn is_rejected(error: &IoError) -> bool {
+	matches!(error.raw_os_error(), Some(libc::EIO) | Some(libc::EINVAL))
+}
+
+/// Convert provided address into its raw representation.
+pub(super) fn raw_address(addr: SocketAddr) -> (libc::sockaddr_storage, libc::socklen_t) {
+	let mut storage: libc::sockaddr_storage = unsafe { zeroed() };
+	let length = match addr {
+		SocketAddr::V4(addr) => {
+			let raw = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in) };
+			raw.sin_family = libc::AF_INET as libc::sa_family_t;
+			raw.sin_port = addr.port().to_be();
+			raw.sin_addr = libc::in_addr { s_addr: u32::from_ne_bytes(addr.ip().octets()) };
+			size_of::<libc::sockaddr_in>()
+		},
+		SocketAddr::V6(addr) => {
+			let raw = unsafe { &mut *(&mut storage as *mut _ as *mut libc::sockaddr_in6) };
+			raw.sin6_family = libc::AF_INET6 as libc::sa_family_t;
+			raw.sin6_port = addr.port().to_be();
+			raw.sin6_flowinfo = addr.flowinfo();
+			raw.sin6_addr = libc::in6_addr { s6_addr: addr.ip().octets() };
+			raw.sin6_scope_id = addr.scope_id();
+			size_of::<libc::sockaddr_in6>()
+		},
+	};
+	(storage, length as libc::socklen_t)
+}
@@ -0,0 +1,51 @@
+//! Batched sending of datagrams with `sendmmsg` on Linux.
+
+use super::gso::raw_address;
+
+use std::io::Error as IoError;
+use std::mem::zeroed;
+use std::net::{SocketAddr, UdpSocket};
+use std::os::unix::io::AsRawFd;
+
+/// Maximum number of messages the kernel accepts in a single `sendmmsg` call (`UIO_MAXIOV`).
+const MAX_BATCH_LENGTH: usize = 1024;
+
+/// Send provided datagrams with a single `sendmmsg` call.
+///
+/// Returns the number of datagrams sent, which may be fewer than provided.
+pub fn send_batch_to(socket: &UdpSocket, datagrams: &[(&[u8], SocketAddr)]) -> Result<usize, IoError> {
+	let batch = &datagrams[.. datagrams.len().min(MAX_BATCH_LENGTH)];
+	if batch.is_empty() {
+		return Ok(0)
+	}
+
+	let mut addresses: Vec<_> = batch.iter().map(|&(_, addr)| raw_address(addr)).collect();
+	let mut iovecs: Vec<_> = batch
+		.iter()
+		.map(|&(data, _)| libc::iovec {
+			iov_base: data.as_ptr() as *mut libc::c_void,
+			iov_len: data.len(),
+		})
+		.collect();
+	let mut messages: Vec<_> = addresses
+		.iter_mut()
+		.zip(iovecs.iter_mut())
+		.map(|((address, address_length), iov)| {
+			let mut message: libc::mmsghdr = unsafe { zeroed() };
+			message.msg_hdr.msg_name = address as *mut libc::sockaddr_storage as *mut libc::c_void;
+			message.msg_hdr.msg_namelen = *address_length;
+			message.msg_hdr.msg_iov = iov;
+			message.msg_hdr.msg_iovlen = 1;
+			message
+		})
+		.collect();
+
+	let result = unsafe {
+		libc::sendmmsg(socket.as_raw_fd(), messages.as_mut_ptr(), messages.len() as libc::c_uint, 0)
+	};
+	if result < 0 {
+		Err(IoError::last_os_error())
+	} else {
+		Ok(result as usize)
+	}
+}
@@ -0,0 +1,191 @@
+//! Transmitter recording every datagram, for protocol tests.
+
+use super::{Transmit, TransmitError};
+
+use std::io::{Error as IoError, ErrorKind as IoErrorKind};
+use std::net::SocketAddr;
+use std::sync::Mutex;
+use std::time::Instant;
+
+/// A single operation recorded by a [`RecordingTransmit`](RecordingTransmit).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Record {
+	/// A datagram was sent to the address.
+	Sent(Vec<u8>, SocketAddr),
+	/// A datagram was received from the address.
+	Received(Vec<u8>, SocketAddr),
+	/// A [malformed](TransmitError::MalformedPacket) datagram was received.
+	Malformed,
+	/// Sending or receiving failed with an underlying error of the kind.
+	Failed(IoErrorKind),
+}
+
+/// A [`Transmit`](Transmit) wrapper logging every datagram sent and received through the inner
+/// transmitter.
+///
+/// The log can be inspected after exercising a connection, to assert exactly what went over the
+/// wire, for example that the third sent packet was a keep-alive or that nothing was
+/// re-transmitted. Receive attempts with [no pending packets](TransmitError::NoPendingPackets)
+/// are not recorded.
+///
+/// Segmented and batched sends are recorded as separate datagrams, in the order they were sent.
+#[derive(Debug, Default)]
+pub struct RecordingTransmit<T: Transmit> {
+	inner: T,
+	records: Mutex<Vec<Record>>,
+}
+
+impl<T: Transmit> RecordingTransmit<T> {
+	/// Wrap provided transmitter, starting with an empty log.
+	#[inline]
+	pub fn new(inner: T) -> Self {
+		Self { inner, records: Mutex::new(Vec::new()) }
+	}
+
+	/// Get the wrapped transmitter.
+	#[inline]
+	pub fn inner(&self) -> &T {
+		&self.inner
+	}
+
+	/// Unwrap the transmitter, discarding the log.
+	#[inline]
+	pub fn into_inner(self) -> T {
+		self.inner
+	}
+
+	/// Get a copy of all records, in the order they were made.
+	pub fn records(&self) -> Vec<Record> {
+		self.records.lock().unwrap().clone()
+	}
+
+	/// Get a copy of all sent datagrams, in the order they were sent.
+	pub fn sent(&self) -> Vec<(Vec<u8>, SocketAddr)> {
+		self.records.lock().unwrap().iter().filter_map(|record| match record {
+			Record::Sent(data, addr) => Some((data.clone(), *addr)),
+			_ => None,
+		}).collect()
+	}
+
+	/// Get a copy of all received datagrams, in the order they were received.
+	pub fn received(&self) -> Vec<(Vec<u8>, SocketAddr)> {
+		self.records.lock().unwrap().iter().filter_map(|record| match record {
+			Record::Received(data, addr) => Some((data.clone(), *addr)),
+			_ => None,
+		}).collect()
+	}
+
+	/// Discard all records made so far.
+	pub fn clear(&self) {
+		self.records.lock().unwrap().clear();
+	}
+
+	fn record(&self, record: Record) {
+		self.records.lock().unwrap().push(record);
+	}
+
+	fn record_send_error(&self, error: &IoError) {
+		self.record(Record::Failed(error.kind()));
+	}
+
+	fn record_receive<R>(&self, buffer: &[u8], result: &Result<(usize, SocketAddr, R), TransmitError>) {
+		match result {
+			Ok((len, addr, _)) => self.record(Record::Received(buffer[.. *len].to_vec(), *addr)),
+			Err(TransmitError::NoPendingPackets) => {},
+			Err(TransmitError::MalformedPacket) => self.record(Record::Malformed),
+			Err(TransmitError::Io(error)) => self.record(Record::Failed(error.kind())),
+		}
+	}
+}
+
+impl<T: Transmit> Transmit for RecordingTransmit<T> {
+	#[inline]
+	fn max_datagram_length(&self) -> usize {
+		self.inner.max_datagram_length()
+	}
+
+	fn send_to(&self, data: &[u8], addr: SocketAddr) -> Result<usize, IoError> {
+		let result = self.inner.send_to(data, addr);
+		match &result {
+			Ok(_) => self.record(Record::Sent(data.to_vec(), addr)),
+			Err(error) => self.record_send_error(error),
+		}
+		result
+	}
+
+	fn send_segments_to(&self, data: &[u8], segment_length: usize, addr: SocketAddr) -> Result<usize, IoError> {
+		let result = self.inner.send_segments_to(data, segment_length, addr);
+		match &result {
+			Ok(byte_count) => for segment in data[.. *byte_count].chunks(segment_length) {
+				self.record(Record::Sent(segment.to_vec(), addr));
+			},
+			Err(error) => self.record_send_error(error),
+		}
+		result
+	}
+
+	fn send_batch_to(&self, datagrams: &[(&[u8], SocketAddr)]) -> Result<usize, IoError> {
+		let result = self.inner.send_batch_to(datagrams);
+		match &result {
+			Ok(sent_count) => for &(data, addr) in &datagrams[.. *sent_count] {
+				self.record(Record::Sent(data.to_vec(), addr));
+			},
+			Err(error) => self.record_send_error(error),
+		}
+		result
+	}
+
+	fn try_recv_from(&self, buffer: &mut [u8]) -> Result<(usize, SocketAddr), TransmitError> {
+		let result = self.inner.try_recv_from(buffer).map(|(len, addr)| (len, addr, ()));
+		self.record_receive(buffer, &result);
+		result.map(|(len, addr, _)| (len, addr))
+	}
+
+	fn try_recv_from_timestamped(&self, buffer: &mut [u8]) -> Result<(usize, SocketAddr, Instant), TransmitError> {
+		let result = self.inner.try_recv_from_timestamped(buffer);
+		self.record_receive(buffer, &result);
+		result
+	}
+
+	#[inline]
+	fn local_addr(&self) -> Option<SocketAddr> {
+		self.inner.local_addr()
+	}
+}
+
+#[cfg(test)]
+mod test {
+	use super::*;
+
+	use std::net::UdpSocket;
+
+	#[test]
+	fn records_sent_and_received_datagrams() {
+		let sender_addr = SocketAddr::from(([ 127, 0, 0, 1, ], 10054));
+		let sender = RecordingTransmit::new(UdpSocket::bind(sender_addr).unwrap());
+
+		let receiver_addr = SocketAddr::from(([ 127, 0, 0, 1, ], 10055));
+		let receiver = RecordingTransmit::new(UdpSocket::bind(receiver_addr).unwrap());
+
+		super::super::test::generic_transmit_test((&sender, sender_addr), (&receiver, receiver_addr));
+		let sent = sender.sent();
+		assert_eq!(sent.len(), 2);
+		assert!(sent.iter().all(|&(_, addr)| addr == receiver_addr));
+		assert!(sender.received().is_empty());
+
+		let mut received = receiver.received();
+		received.sort();
+		let mut expected: Vec<_> = sent.into_iter().map(|(data, _)| (data, sender_addr)).collect();
+		expected.sort();
+		assert_eq!(received, expected);
+
+		// Polling with no pending datagrams is not recorded.
+		receiver.inner().set_nonblocking(true).unwrap();
+		let mut buffer = [0; 8];
+		assert_eq!(receiver.try_recv_from(&mut buffer), Err(TransmitError::NoPendingPackets));
+		assert_eq!(receiver.records().len(), 2);
+
+		receiver.clear();
+		assert!(receiver.records().is_empty());
+	}
+}
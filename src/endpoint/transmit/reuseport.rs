@@ -0,0 +1,51 @@
+//! Sharding a port across sockets with `SO_REUSEPORT` on Linux.
+//!
+//! Sockets bound to the same address with `SO_REUSEPORT` share its traffic, the kernel hashing
+//! the source of every datagram to pick the socket that receives it. Datagrams from a single
+//! remote address therefore keep arriving at the same socket, while different remotes are spread
+//! across all of them.
+
+use super::gso::raw_address;
+
+use std::io::Error as IoError;
+use std::mem::size_of;
+use std::net::{SocketAddr, UdpSocket};
+use std::os::unix::io::FromRawFd;
+
+/// Bind a new socket to provided address with `SO_REUSEPORT` set.
+///
+/// Other sockets of the same user may bind the same address as long as they also set the option,
+/// for example with this function.
+///
+/// # Platform support
+/// Only available on Linux.
+pub fn bind_reuse_port(addr: SocketAddr) -> Result<UdpSocket, IoError> {
+	let family = if addr.is_ipv4() { libc::AF_INET } else { libc::AF_INET6 };
+	let fd = unsafe { libc::socket(family, libc::SOCK_DGRAM | libc::SOCK_CLOEXEC, 0) };
+	if fd < 0 {
+		return Err(IoError::last_os_error())
+	}
+	// Owning the descriptor right away closes it on failure.
+	let socket = unsafe { UdpSocket::from_raw_fd(fd) };
+
+	let value: libc::c_int = 1;
+	let result = unsafe {
+		libc::setsockopt(
+			fd,
+			libc::SOL_SOCKET,
+			libc::SO_REUSEPORT,
+			&value as *const libc::c_int as *const libc::c_void,
+			size_of::<libc::c_int>() as libc::socklen_t,
+		)
+	};
+	if result < 0 {
+		return Err(IoError::last_os_error())
+	}
+
+	let (address, address_len) = raw_address(addr);
+	let result = unsafe { libc::bind(fd, &address as *const _ as *const libc::sockaddr, address_len) };
+	if result < 0 {
+		return Err(IoError::last_os_error())
+	}
+	Ok(socket)
+}
@@ -45,3 +45,57 @@ pub fn generic_transmit_test<S: Transmit, R: Transmit>(
 		assert_eq!(&buffer[.. DATAGRAMS[0].len()], DATAGRAMS[0]);
 	}
 }
+
+/// Test that segmented sends of provided [`Transmit`](Transmit) implementation arrive as
+/// separate datagrams.
+pub fn generic_segmented_transmit_test<S: Transmit, R: Transmit>(
+	(sender, sender_addr): (&S, SocketAddr),
+	(receiver, receiver_addr): (&R, SocketAddr),
+) {
+	const SEGMENT_LENGTH: usize = 16;
+	const DATA: &[u8] = b"GNET SEGMENTED  TRANSMIT TEST   LAST";
+
+	assert_eq!(
+		sender.send_segments_to(DATA, SEGMENT_LENGTH, receiver_addr).expect("Failed to send segments!"),
+		DATA.len(),
+	);
+
+	let mut buffer = vec![0; receiver.max_datagram_length()];
+	let mut received = Vec::new();
+	for _ in 0 .. 3 {
+		let (len, addr) = receiver.try_recv_from(&mut buffer).expect("Failed to receive a segment!");
+		assert_eq!(addr, sender_addr);
+		received.push(buffer[.. len].to_vec());
+	}
+	received.sort();
+
+	let mut expected: Vec<_> = DATA.chunks(SEGMENT_LENGTH).map(<[u8]>::to_vec).collect();
+	expected.sort();
+	assert_eq!(received, expected);
+}
+
+/// Test that batched sends of provided [`Transmit`](Transmit) implementation arrive as separate
+/// datagrams.
+pub fn generic_batch_transmit_test<S: Transmit, R: Transmit>(
+	(sender, sender_addr): (&S, SocketAddr),
+	(receiver, receiver_addr): (&R, SocketAddr),
+) {
+	let batch: Vec<_> = DATAGRAMS.iter().map(|&datagram| (datagram, receiver_addr)).collect();
+	let mut sent_count = 0;
+	while sent_count < batch.len() {
+		sent_count += sender.send_batch_to(&batch[sent_count ..]).expect("Failed to send a batch!");
+	}
+
+	let mut buffer = vec![0; receiver.max_datagram_length()];
+	let mut received = Vec::new();
+	for _ in 0 .. DATAGRAMS.len() {
+		let (len, addr) = receiver.try_recv_from(&mut buffer).expect("Failed to receive a datagram!");
+		assert_eq!(addr, sender_addr);
+		received.push(buffer[.. len].to_vec());
+	}
+	received.sort();
+
+	let mut expected: Vec<_> = DATAGRAMS.iter().map(|datagram| datagram.to_vec()).collect();
+	expected.sort();
+	assert_eq!(received, expected);
+}
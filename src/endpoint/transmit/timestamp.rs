@@ -0,0 +1,108 @@
+//! Kernel receive timestamps with `SO_TIMESTAMPNS` on Linux.
+//!
+//! The kernel records the moment a datagram arrived at the socket, which is not affected by the
+//! time the datagram spends buffered before the application receives it.
+
+use super::TransmitError;
+
+use std::io::Error as IoError;
+use std::mem::{size_of, zeroed};
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV6, UdpSocket};
+use std::os::unix::io::AsRawFd;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Control message buffer for a single `SCM_TIMESTAMPNS` message, aligned for `cmsghdr`.
+#[repr(C, align(8))]
+struct ControlBuffer([u8; 64]);
+
+/// Enable or disable kernel receive timestamps of provided socket.
+///
+/// Once enabled, datagrams received with
+/// [`try_recv_from_timestamped`](super::Transmit::try_recv_from_timestamped) are timestamped by the
+/// kernel.
+pub fn set_receive_timestamps(socket: &UdpSocket, enable: bool) -> Result<(), IoError> {
+	let value = enable as libc::c_int;
+	let result = unsafe {
+		libc::setsockopt(
+			socket.as_raw_fd(),
+			libc::SOL_SOCKET,
+			libc::SO_TIMESTAMPNS,
+			&value as *const libc::c_int as *const libc::c_void,
+			size_of::<libc::c_int>() as libc::socklen_t,
+		)
+	};
+	if result < 0 {
+		Err(IoError::last_os_error())
+	} else {
+		Ok(())
+	}
+}
+
+/// Receive a datagram with a single `recvmsg` call, along with its kernel receive timestamp.
+///
+/// Datagrams without a timestamp, for example when timestamps are not enabled, are timestamped
+/// with the current time instead.
+pub fn recv_from_timestamped(socket: &UdpSocket, buffer: &mut [u8]) -> Result<(usize, SocketAddr, Instant), TransmitError> {
+	let mut address: libc::sockaddr_storage = unsafe { zeroed() };
+	let mut iov = libc::iovec {
+		iov_base: buffer.as_mut_ptr() as *mut libc::c_void,
+		iov_len: buffer.len(),
+	};
+	let mut control = ControlBuffer([0; 64]);
+
+	let mut message: libc::msghdr = unsafe { zeroed() };
+	message.msg_name = &mut address as *mut libc::sockaddr_storage as *mut libc::c_void;
+	message.msg_namelen = size_of::<libc::sockaddr_storage>() as libc::socklen_t;
+	message.msg_iov = &mut iov;
+	message.msg_iovlen = 1;
+	message.msg_control = control.0.as_mut_ptr() as *mut libc::c_void;
+	message.msg_controllen = control.0.len() as _;
+
+	let result = unsafe { libc::recvmsg(socket.as_raw_fd(), &mut message, 0) };
+	if result < 0 {
+		return Err(IoError::last_os_error().into())
+	}
+	let addr = socket_address(&address).ok_or(TransmitError::MalformedPacket)?;
+	let timestamp = kernel_timestamp(&message).map_or_else(Instant::now, to_instant);
+	Ok((result as usize, addr, timestamp))
+}
+
+/// Find the `SCM_TIMESTAMPNS` control message of provided received message.
+fn kernel_timestamp(message: &libc::msghdr) -> Option<SystemTime> {
+	unsafe {
+		let mut header = libc::CMSG_FIRSTHDR(message);
+		while !header.is_null() {
+			if (*header).cmsg_level == libc::SOL_SOCKET && (*header).cmsg_type == libc::SCM_TIMESTAMPNS {
+				let time = std::ptr::read_unaligned(libc::CMSG_DATA(header) as *const libc::timespec);
+				return Some(UNIX_EPOCH + Duration::new(time.tv_sec as u64, time.tv_nsec as u32))
+			}
+			header = libc::CMSG_NXTHDR(message, header);
+		}
+	}
+	None
+}
+
+/// Convert a wall-clock time in the recent past to a monotonic instant.
+fn to_instant(time: SystemTime) -> Instant {
+	let now = Instant::now();
+	let age = SystemTime::now().duration_since(time).unwrap_or_default();
+	now.checked_sub(age).unwrap_or(now)
+}
+
+/// Convert provided raw address into a socket address.
+fn socket_address(storage: &libc::sockaddr_storage) -> Option<SocketAddr> {
+	match storage.ss_family as libc::c_int {
+		libc::AF_INET => {
+			let raw = unsafe { &*(storage as *const _ as *const libc::sockaddr_in) };
+			let ip = Ipv4Addr::from(raw.sin_addr.s_addr.to_ne_bytes());
+			Some(SocketAddr::from((ip, u16::from_be(raw.sin_port))))
+		},
+		libc::AF_INET6 => {
+			let raw = unsafe { &*(storage as *const _ as *const libc::sockaddr_in6) };
+			let ip = Ipv6Addr::from(raw.sin6_addr.s6_addr);
+			let port = u16::from_be(raw.sin6_port);
+			Some(SocketAddrV6::new(ip, port, raw.sin6_flowinfo, raw.sin6_scope_id).into())
+		},
+		_ => None,
+	}
+}
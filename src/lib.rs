@@ -9,13 +9,13 @@
 //! ## Features
 //!
 //! - `protocol` (default) - enables the [`protocol`](protocol) module. Users may opt-out if
-//! they with to only use endpoint or byte-serialization portions of the library.
+//!   they with to only use endpoint or byte-serialization portions of the library.
 //! - `adv-endpoint` - advanced endpoint implementations. Their use is encouraged over using
-//! default library [`endpoint`](endpoint) trait implementors, as the focus was simplicity
-//! instead of performance.
+//!   default library [`endpoint`](endpoint) trait implementors, as the focus was simplicity
+//!   instead of performance.
 
 #![warn(clippy::all)]
 
 pub mod byte;
-// pub mod endpoint;
+pub mod endpoint;
 pub mod connection;
@@ -0,0 +1,82 @@
+use gnet::byte::{ByteSerialize, SerializationError};
+use gnet::connection::client::ClientConnection;
+use gnet::connection::context::ConnectionStatus;
+use gnet::connection::listen::{AcceptDecision, ConnectionListener};
+use std::net::{SocketAddr, UdpSocket};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+struct Message(u32);
+
+impl ByteSerialize for Message {
+	fn byte_count(&self) -> usize {
+		self.0.byte_count()
+	}
+
+	fn to_bytes(&self, bytes: &mut [u8]) {
+		self.0.to_bytes(bytes)
+	}
+
+	fn from_bytes(bytes: &[u8]) -> Result<(Self, usize), SerializationError> {
+		u32::from_bytes(bytes).map(|(value, byte_count)| (Self(value), byte_count))
+	}
+}
+
+impl gnet::connection::Parcel for Message {}
+
+#[test]
+fn loopback_test() {
+	const MESSAGE_COUNT: u32 = 100;
+
+	let listener_addr = SocketAddr::from(([ 127, 0, 0, 1, ], 2102));
+	let listener_socket = UdpSocket::bind(listener_addr).expect("Failed to bind listener socket.");
+	listener_socket.set_nonblocking(true).unwrap();
+	let mut listener = ConnectionListener::<_, Message>::new(listener_socket);
+
+	// Handshake
+	let mut client = ClientConnection::<_, Message>::connect_new(listener_addr, b"loopback").unwrap();
+	let client_addr = client.transmitter().local_addr().unwrap();
+	while listener.receive().unwrap() == 0 {}
+	let connection_id = listener.try_accept(|addr, payload| {
+		if addr.port() == client_addr.port() && payload == b"loopback" {
+			AcceptDecision::Allow
+		} else {
+			AcceptDecision::Reject
+		}
+	}).unwrap();
+	while client.receive().unwrap() == 0 {}
+	assert_eq!(client.context().status(), ConnectionStatus::Open);
+	assert_eq!(client.context().connection_id(), Some(connection_id));
+
+	// Reliable parcels both ways
+	for index in 0 .. MESSAGE_COUNT {
+		client.context_mut().push_reliable_parcel(Message(index)).unwrap();
+		listener.connection_mut(connection_id).unwrap().push_reliable_parcel(Message(MESSAGE_COUNT + index)).unwrap();
+	}
+	let mut client_received = Vec::new();
+	let mut server_received = Vec::new();
+	while client_received.len() < MESSAGE_COUNT as usize || server_received.len() < MESSAGE_COUNT as usize {
+		client.flush().unwrap();
+		listener.flush_all().unwrap();
+		client.receive().unwrap();
+		listener.receive().unwrap();
+		while let Ok((Message(value), _)) = client.context_mut().pop_parcel() {
+			client_received.push(value);
+		}
+		while let Ok((Message(value), _)) = listener.connection_mut(connection_id).unwrap().pop_parcel() {
+			server_received.push(value);
+		}
+	}
+	client_received.sort_unstable();
+	server_received.sort_unstable();
+	assert_eq!(client_received, (MESSAGE_COUNT .. 2 * MESSAGE_COUNT).collect::<Vec<_>>());
+	assert_eq!(server_received, (0 .. MESSAGE_COUNT).collect::<Vec<_>>());
+
+	// Close
+	client.close().unwrap();
+	assert_eq!(client.context().status(), ConnectionStatus::Closed);
+	while listener.connection(connection_id).unwrap().status() == ConnectionStatus::Open {
+		listener.receive().unwrap();
+	}
+	assert_eq!(listener.connection(connection_id).unwrap().status(), ConnectionStatus::Closed);
+	assert!(listener.remove_connection(connection_id).is_some());
+}
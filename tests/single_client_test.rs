@@ -1,6 +1,6 @@
 use gnet::byte::ByteSerialize;
 use gnet::connection::context::Context;
-use gnet::connection::packet;
+use gnet::connection::listen::{AcceptDecision, ConnectionListener};
 use std::net::{SocketAddr, UdpSocket};
 
 #[derive(Debug, PartialEq, Clone)]
@@ -38,8 +38,9 @@ impl ByteSerialize for TestParcel {
 		match variant_index {
 			0 => {
 				let (byte_count, extra_offset) = u16::from_bytes(&bytes[offset ..])?;
+				let offset = offset + extra_offset;
 				let string = String::from_utf8(bytes[offset .. offset + byte_count as usize].to_vec())?;
-				Ok((Self::String(string), offset + extra_offset + byte_count as usize))
+				Ok((Self::String(string), offset + byte_count as usize))
 			},
 			1 => {
 				let (value, extra_offset) = u64::from_bytes(&bytes[offset ..])?;
@@ -64,10 +65,10 @@ fn single_client_test() {
 	let client_addr = SocketAddr::from(([ 127, 0, 0, 1, ], 2101));
 
 	// Set up listener
-	// TODO:
 	let listener_socket = UdpSocket::bind(listener_addr).expect("Faild to bind listener socket.");
-	// Listener::new()
-	
+	listener_socket.set_nonblocking(true).unwrap();
+	let mut listener = ConnectionListener::<_, TestParcel>::new(listener_socket);
+
 	// Set up client
 	let client_socket = UdpSocket::bind(client_addr).expect("Failed to bind client socket");
 	let mut client_context = Context::<TestParcel>::pending();
@@ -75,12 +76,35 @@ fn single_client_test() {
 	// Connect
 	let len = client_context.build_request_packet(&mut byte_buffer, REQUEST_PAYLOAD).unwrap();
 	client_socket.send_to(&byte_buffer[.. len], listener_addr).unwrap();
-	
+
 	// Accept
-	let (recv_bytes, recv_addr) = listener_socket.recv_from(&mut byte_buffer).unwrap();
-	assert_eq!(recv_addr, client_addr);
-	// TODO: listener utility that allows constructing accept packet
-	assert_eq!(packet::get_data_segment(&byte_buffer[.. recv_bytes]), REQUEST_PAYLOAD);
+	while listener.receive().unwrap() == 0 {}
+	let connection_id = listener.try_accept(|addr, payload| {
+		if addr == client_addr && payload == REQUEST_PAYLOAD {
+			AcceptDecision::Allow
+		} else {
+			AcceptDecision::Reject
+		}
+	}).unwrap();
+
+	let (recv_bytes, _) = client_socket.recv_from(&mut byte_buffer).unwrap();
+	client_context.process_packet(&byte_buffer[.. recv_bytes]).unwrap();
+	assert_eq!(client_context.connection_id(), Some(connection_id));
+
+	// Client -> Server
+	client_context.push_reliable_parcel(test_parcel.clone()).unwrap();
+	let len = client_context.build_packet(&mut byte_buffer).unwrap();
+	client_socket.send_to(&byte_buffer[.. len], listener_addr).unwrap();
+
+	while listener.receive().unwrap() == 0 {}
+	let server_context = listener.connection_mut(connection_id).unwrap();
+	assert_eq!(server_context.pop_parcel().unwrap().0, test_parcel);
+
+	// Server -> Client
+	server_context.push_reliable_parcel(TestParcel::Index(42)).unwrap();
+	assert_eq!(listener.flush_all().unwrap(), 1);
 
-	// TODO: send and receive parcels from both ends
+	let (recv_bytes, _) = client_socket.recv_from(&mut byte_buffer).unwrap();
+	client_context.process_packet(&byte_buffer[.. recv_bytes]).unwrap();
+	assert_eq!(client_context.pop_parcel().unwrap().0, TestParcel::Index(42));
 }